@@ -1,32 +1,102 @@
 #[cfg(feature = "genproto")]
 extern crate prost_build;
 #[cfg(feature = "genproto")]
-use std::{env, fs, fs::File, path::Path};
+extern crate tonic_build;
+#[cfg(feature = "genproto")]
+use std::{env, fs, path::Path};
 
 /// To generate updated proto objects:
-/// 1. run `cargo build --features=genproto`
+/// 1. update `src/proto/vss.proto`, which extends the upstream VSS protocol
+/// 2. run `cargo build --features=genproto`
+///
+/// Along with the messages, the server of the gRPC `VssService` is generated, which `src/grpc.rs`
+/// implements.
 fn main() {
 	#[cfg(feature = "genproto")]
 	generate_protos();
 }
 
+/// Messages exchanged with clients, which can also be encoded as proto3 JSON.
 #[cfg(feature = "genproto")]
-fn generate_protos() {
-	download_file(
-		"https://raw.githubusercontent.com/lightningdevkit/vss-server/cb1159c3b1835c66a857b25b114f15d18d2a4297/app/src/main/proto/vss.proto",
-		"src/proto/vss.proto",
-	).unwrap();
+const API_MESSAGES: [&str; 44] = [
+	"GetObjectRequest", "GetObjectResponse", "GetObjectsRequest", "GetObjectsResponse", "PutObjectRequest", "ItemExpiry", "PutObjectResponse", "DeleteObjectRequest",
+	"DeleteObjectResponse", "ListKeyVersionsRequest", "ListKeyVersionsResponse", "ListObjectVersionsRequest", "ListObjectVersionsResponse",
+	"ObjectVersion", "GetObjectVersionRequest", "GetObjectVersionResponse", "ListDeletedObjectsRequest", "ListDeletedObjectsResponse",
+	"DeletedObject", "RestoreObjectRequest", "RestoreObjectResponse", "CreateSnapshotRequest", "CreateSnapshotResponse", "Snapshot",
+	"ListSnapshotsRequest", "ListSnapshotsResponse", "GetSnapshotRequest", "GetSnapshotResponse", "RestoreSnapshotRequest",
+	"RestoreSnapshotResponse", "DeleteSnapshotRequest", "DeleteSnapshotResponse", "ListStoresRequest", "ListStoresResponse", "StoreStats",
+	"GetStoreRequest", "GetStoreResponse", "StoreMetadata", "DeleteStoreRequest", "DeleteStoreResponse", "VerifyAuditLogRequest", "VerifyAuditLogResponse",
+	"ErrorResponse", "KeyValue",
+];
 
-	prost_build::compile_protos(&["src/proto/vss.proto"], &["src/"]).unwrap();
-	let from_path = Path::new(&env::var("OUT_DIR").unwrap()).join("vss.rs");
-	fs::copy(from_path, "src/types.rs").unwrap();
-}
+/// Serde attributes of fields of `API_MESSAGES` whose JSON mapping differs from their Rust type,
+/// or whose name differs in camel case.
+#[cfg(feature = "genproto")]
+const API_FIELD_ATTRIBUTES: [(&str, &str); 47] = [
+	("store_id", "#[serde(alias = \"store_id\")]"),
+	("known_version", "#[serde(with = \"crate::json::optional_int64\", skip_serializing_if = \"Option::is_none\", alias = \"known_version\")]"),
+	("GetObjectResponse.value", "#[serde(skip_serializing_if = \"Option::is_none\")]"),
+	("not_modified", "#[serde(alias = \"not_modified\")]"),
+	("missing_keys", "#[serde(alias = \"missing_keys\")]"),
+	("PutObjectRequest.global_version", "#[serde(with = \"crate::json::optional_int64\", skip_serializing_if = \"Option::is_none\", alias = \"global_version\")]"),
+	("PutObjectResponse.global_version", "#[serde(with = \"crate::json::optional_int64\", skip_serializing_if = \"Option::is_none\", alias = \"global_version\")]"),
+	("transaction_items", "#[serde(alias = \"transaction_items\")]"),
+	("delete_items", "#[serde(alias = \"delete_items\")]"),
+	("item_expiries", "#[serde(alias = \"item_expiries\")]"),
+	("expires_at", "#[serde(with = \"crate::json::int64\", alias = \"expires_at\")]"),
+	("key_value", "#[serde(skip_serializing_if = \"Option::is_none\", alias = \"key_value\")]"),
+	("key_prefix", "#[serde(skip_serializing_if = \"Option::is_none\", alias = \"key_prefix\")]"),
+	("page_size", "#[serde(skip_serializing_if = \"Option::is_none\", alias = \"page_size\")]"),
+	("page_token", "#[serde(skip_serializing_if = \"Option::is_none\", alias = \"page_token\")]"),
+	("include_values", "#[serde(alias = \"include_values\")]"),
+	("key_versions", "#[serde(alias = \"key_versions\")]"),
+	("next_page_token", "#[serde(skip_serializing_if = \"Option::is_none\", alias = \"next_page_token\")]"),
+	("ListKeyVersionsResponse.global_version", "#[serde(with = \"crate::json::optional_int64\", skip_serializing_if = \"Option::is_none\", alias = \"global_version\")]"),
+	("ObjectVersion.version", "#[serde(with = \"crate::json::int64\")]"),
+	("superseded_at", "#[serde(with = \"crate::json::int64\", alias = \"superseded_at\")]"),
+	("GetObjectVersionRequest.version", "#[serde(with = \"crate::json::int64\")]"),
+	("GetObjectVersionResponse.value", "#[serde(skip_serializing_if = \"Option::is_none\")]"),
+	("DeletedObject.version", "#[serde(with = \"crate::json::int64\")]"),
+	("deleted_at", "#[serde(with = \"crate::json::int64\", alias = \"deleted_at\")]"),
+	("RestoreObjectResponse.value", "#[serde(skip_serializing_if = \"Option::is_none\")]"),
+	("CreateSnapshotResponse.snapshot", "#[serde(skip_serializing_if = \"Option::is_none\")]"),
+	("Snapshot.global_version", "#[serde(with = \"crate::json::int64\", alias = \"global_version\")]"),
+	("Snapshot.created_at", "#[serde(with = \"crate::json::int64\", alias = \"created_at\")]"),
+	("item_count", "#[serde(with = \"crate::json::int64\", alias = \"item_count\")]"),
+	("GetSnapshotRequest.global_version", "#[serde(with = \"crate::json::int64\", alias = \"global_version\")]"),
+	("key_values", "#[serde(alias = \"key_values\")]"),
+	("RestoreSnapshotRequest.global_version", "#[serde(with = \"crate::json::int64\", alias = \"global_version\")]"),
+	("DeleteSnapshotRequest.global_version", "#[serde(with = \"crate::json::int64\", alias = \"global_version\")]"),
+	("key_count", "#[serde(with = \"crate::json::int64\", alias = \"key_count\")]"),
+	("total_size", "#[serde(with = \"crate::json::int64\", alias = \"total_size\")]"),
+	("GetStoreResponse.store", "#[serde(skip_serializing_if = \"Option::is_none\")]"),
+	("StoreMetadata.stats", "#[serde(skip_serializing_if = \"Option::is_none\")]"),
+	("StoreMetadata.global_version", "#[serde(with = \"crate::json::int64\", alias = \"global_version\")]"),
+	("StoreMetadata.created_at", "#[serde(with = \"crate::json::optional_int64\", skip_serializing_if = \"Option::is_none\", alias = \"created_at\")]"),
+	("last_modified_at", "#[serde(with = \"crate::json::optional_int64\", skip_serializing_if = \"Option::is_none\", alias = \"last_modified_at\")]"),
+	("deleted_keys", "#[serde(with = \"crate::json::int64\", alias = \"deleted_keys\")]"),
+	("entry_count", "#[serde(with = \"crate::json::int64\", alias = \"entry_count\")]"),
+	("broken_at", "#[serde(with = \"crate::json::optional_int64\", skip_serializing_if = \"Option::is_none\", alias = \"broken_at\")]"),
+	("error_code", "#[serde(with = \"crate::json::error_code\", alias = \"error_code\")]"),
+	("KeyValue.version", "#[serde(with = \"crate::json::int64\")]"),
+	("KeyValue.value", "#[serde(with = \"crate::json::bytes\")]"),
+];
 
 #[cfg(feature = "genproto")]
-fn download_file(url: &str, save_to: &str) -> Result<(), Box<dyn std::error::Error>> {
-	let mut response = reqwest::blocking::get(url)?;
-	fs::create_dir_all(Path::new(save_to).parent().unwrap())?;
-	let mut out_file = File::create(save_to)?;
-	response.copy_to(&mut out_file)?;
-	Ok(())
+fn generate_protos() {
+	let mut config = prost_build::Config::new();
+	for message in API_MESSAGES {
+		let path = format!(".vss.{}", message);
+		config.type_attribute(&path, "#[derive(::serde::Serialize, ::serde::Deserialize)]");
+		config.type_attribute(&path, "#[serde(rename_all = \"camelCase\", default)]");
+	}
+	for (field, attribute) in API_FIELD_ATTRIBUTES {
+		config.field_attribute(field, attribute);
+	}
+	tonic_build::configure()
+		.build_client(false)
+		.compile_with_config(config, &["src/proto/vss.proto"], &["src/"])
+		.unwrap();
+	let from_path = Path::new(&env::var("OUT_DIR").unwrap()).join("vss.rs");
+	fs::copy(from_path, "src/types.rs").unwrap();
 }
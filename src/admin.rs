@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::extract::rejection::BytesRejection;
+use axum::http::{HeaderMap, Request, header};
+use axum::middleware::{self, Next};
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::Router;
+use sha2::{Digest, Sha256};
+
+use crate::api::{Encoding, build_error_response, handle_admin_request};
+use crate::audit::{self, AuditStore};
+use crate::metrics::track_http_metrics;
+use crate::snapshot::Snapshots;
+use crate::soft_delete::{self, SoftDeleteStore};
+use crate::store::{KvStore, validate_store_id};
+use crate::telemetry::{loggable_store_id, trace_request};
+use crate::types::{DeleteStoreRequest, DeleteStoreResponse, ErrorCode, GetStoreRequest, GetStoreResponse, ListStoresRequest};
+
+#[derive(Clone)]
+struct AdminState {
+	store: Arc<dyn KvStore>,
+	snapshots: Arc<Snapshots>,
+}
+
+/// Routes of the admin API, requiring `Authorization: Bearer <token>` with the configured token:
+/// * `POST /admin/listStores` lists stores with their number of keys and total size, a page at a
+///   time, scanning the backend.
+/// * `POST /admin/getStore` returns the metadata of a store.
+/// * `POST /admin/deleteStore` permanently deletes a store, along with its previous versions,
+///   deleted items and snapshots, e.g. to honour erasure requests. Writes made to the store while it
+///   is being deleted may survive it.
+///
+/// * `POST /admin/listDeletedObjects` and `POST /admin/restoreObject` list and restore deleted items,
+///   see [`crate::soft_delete::routes`].
+/// * `POST /admin/verifyAuditLog` checks the chain of the audit log, see [`crate::audit::routes`].
+///
+/// Requests and responses are encoded like those of the data plane, errors are `ErrorResponse`s.
+pub fn router(store: Arc<dyn KvStore>, soft_delete: Option<Arc<SoftDeleteStore>>, audit: Option<Arc<AuditStore>>, snapshots: Arc<Snapshots>, token: String, max_body_size: usize) -> Router {
+	Router::new()
+		.route("/admin/listStores", post(list_stores))
+		.route("/admin/getStore", post(get_store))
+		.route("/admin/deleteStore", post(delete_store))
+		.layer(DefaultBodyLimit::max(max_body_size))
+		.with_state(AdminState { store: Arc::clone(&store), snapshots })
+		.merge(soft_delete::routes(store, soft_delete, max_body_size))
+		.merge(audit::routes(audit))
+		.route_layer(middleware::from_fn(track_http_metrics))
+		.route_layer(middleware::from_fn_with_state(Arc::new(token), require_token))
+		.route_layer(middleware::from_fn(trace_request))
+}
+
+async fn require_token<B>(State(token): State<Arc<String>>, request: Request<B>, next: Next<B>) -> axum::response::Response {
+	let authorized = request.headers().get(header::AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+		.is_some_and(|provided| digests_match(provided, &token));
+	if !authorized {
+		tracing::info!("Admin request without a valid token");
+		return build_error_response(Encoding::Json, ErrorCode::AuthException, "Missing or invalid admin token").into_response();
+	}
+	next.run(request).await
+}
+
+/// Compares the digests of `a` and `b` in constant time, so that response times do not reveal how
+/// much of a guessed token is correct.
+fn digests_match(a: &str, b: &str) -> bool {
+	Sha256::digest(a.as_bytes()).iter()
+		.zip(Sha256::digest(b.as_bytes()).iter())
+		.fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Rejects empty store ids, and those used internally to keep chunks, metadata or the data of
+/// `KvStore` wrappers.
+fn check_store_id(store_id: &str) -> std::io::Result<()> {
+	if store_id.is_empty() {
+		return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid store_id"));
+	}
+	validate_store_id(store_id)
+}
+
+async fn list_stores(State(state): State<AdminState>, headers: HeaderMap, body: Result<Bytes, BytesRejection>) -> impl IntoResponse {
+	handle_admin_request(&headers, body, |request: ListStoresRequest| async move {
+		state.store.list_stores(request).await
+	}, "Failed to list stores").await
+}
+
+async fn get_store(State(state): State<AdminState>, headers: HeaderMap, body: Result<Bytes, BytesRejection>) -> impl IntoResponse {
+	handle_admin_request(&headers, body, |request: GetStoreRequest| async move {
+		check_store_id(&request.store_id)?;
+		let store = state.store.store_metadata(&request.store_id).await?;
+		Ok(GetStoreResponse { store: Some(store) })
+	}, "Failed to get store metadata").await
+}
+
+async fn delete_store(State(state): State<AdminState>, headers: HeaderMap, body: Result<Bytes, BytesRejection>) -> impl IntoResponse {
+	handle_admin_request(&headers, body, |request: DeleteStoreRequest| async move {
+		check_store_id(&request.store_id)?;
+		let deleted_keys = state.store.delete_store(&request.store_id).await?;
+		state.snapshots.delete_all(&request.store_id).await?;
+		tracing::info!(store_id = loggable_store_id(&request.store_id), deleted_keys, "Deleted store");
+		Ok(DeleteStoreResponse { deleted_keys: deleted_keys as i64 })
+	}, "Failed to delete store").await
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use axum::body::Body;
+	use axum::http::StatusCode;
+	use tower::ServiceExt;
+
+	use super::*;
+	use crate::config::SoftDeleteConfig;
+	use crate::testing::{MemoryStore, put};
+
+	fn admin_router(backend: Arc<dyn KvStore>) -> Router {
+		let config = SoftDeleteConfig { retention: Duration::from_secs(60), reap_interval: Duration::from_secs(60) };
+		let soft_delete = Arc::new(SoftDeleteStore::new(Arc::clone(&backend), Arc::clone(&backend), config));
+		let store: Arc<dyn KvStore> = Arc::clone(&soft_delete) as Arc<dyn KvStore>;
+		let snapshots = Arc::new(Snapshots::new(backend, Arc::clone(&store)));
+		router(store, Some(soft_delete), None, snapshots, "secret".to_string(), 1024)
+	}
+
+	async fn post(router: &Router, path: &str, token: Option<&str>, body: &str) -> (StatusCode, serde_json::Value) {
+		let mut request = Request::post(path).header(header::CONTENT_TYPE, "application/json");
+		if let Some(token) = token {
+			request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+		}
+		let response = router.clone().oneshot(request.body(Body::from(body.to_string())).unwrap()).await.unwrap();
+		let status = response.status();
+		let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+		(status, serde_json::from_slice(&body).unwrap())
+	}
+
+	#[tokio::test]
+	async fn routes_require_token() {
+		let router = admin_router(Arc::new(MemoryStore::default()));
+		let status = |path: &'static str, token: Option<&'static str>| {
+			let router = router.clone();
+			async move { post(&router, path, token, r#"{"storeId": "store", "key": "k"}"#).await.0 }
+		};
+		for path in ["/admin/listStores", "/admin/getStore", "/admin/deleteStore", "/admin/listDeletedObjects", "/admin/restoreObject", "/admin/verifyAuditLog"] {
+			assert_eq!(status(path, None).await, StatusCode::UNAUTHORIZED);
+			assert_eq!(status(path, Some("guess")).await, StatusCode::UNAUTHORIZED);
+		}
+		assert_eq!(status("/admin/listStores", Some("secret")).await, StatusCode::OK);
+		assert_eq!(status("/admin/getStore", Some("secret")).await, StatusCode::NOT_FOUND);
+		assert_eq!(status("/admin/deleteStore", Some("secret")).await, StatusCode::OK);
+		assert_eq!(status("/admin/listDeletedObjects", Some("secret")).await, StatusCode::OK);
+		assert_eq!(status("/admin/restoreObject", Some("secret")).await, StatusCode::NOT_FOUND);
+		// No audit log is recorded.
+		assert_eq!(status("/admin/verifyAuditLog", Some("secret")).await, StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn manages_stores() {
+		let backend: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		for store_id in ["a", "b", "c"] {
+			put(backend.as_ref(), store_id, "k", 0, b"value").await.unwrap();
+		}
+		let router = admin_router(Arc::clone(&backend));
+
+		let (status, page) = post(&router, "/admin/listStores", Some("secret"), r#"{"pageSize": 2}"#).await;
+		assert_eq!(status, StatusCode::OK);
+		assert_eq!(page["stores"], serde_json::json!([
+			{"storeId": "a", "keyCount": "1", "totalSize": "5"},
+			{"storeId": "b", "keyCount": "1", "totalSize": "5"},
+		]));
+		let body = serde_json::json!({"pageSize": 2, "pageToken": page["nextPageToken"]}).to_string();
+		let (_, page) = post(&router, "/admin/listStores", Some("secret"), &body).await;
+		assert_eq!(page["stores"], serde_json::json!([{"storeId": "c", "keyCount": "1", "totalSize": "5"}]));
+		assert_eq!(page.get("nextPageToken"), None);
+
+		let (_, store) = post(&router, "/admin/getStore", Some("secret"), r#"{"storeId": "a"}"#).await;
+		assert_eq!(store["store"]["stats"]["keyCount"], "1");
+		assert_eq!(store["store"]["globalVersion"], "1");
+
+		let (_, deleted) = post(&router, "/admin/deleteStore", Some("secret"), r#"{"storeId": "a"}"#).await;
+		assert_eq!(deleted["deletedKeys"], "1");
+		let (status, _) = post(&router, "/admin/getStore", Some("secret"), r#"{"storeId": "a"}"#).await;
+		assert_eq!(status, StatusCode::NOT_FOUND);
+		let (status, _) = post(&router, "/admin/getStore", Some("secret"), r#"{"storeId": "a\u0000history"}"#).await;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+	}
+}
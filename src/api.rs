@@ -1,102 +1,387 @@
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 use ::prost::Message;
 use axum::body::Body;
 use axum::body::Bytes;
+use axum::extract::rejection::BytesRejection;
 use axum::debug_handler;
+use axum::Extension;
 use axum::extract::State;
-use axum::http::{Response, StatusCode};
+use axum::http::{HeaderMap, Request, Response, StatusCode, header};
+use axum::middleware::Next;
 use axum::response::IntoResponse;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 
-use crate::dynamodb_store::DynamoDbStore;
-use crate::store::KvStore;
-use crate::types::{DeleteObjectRequest, GetObjectRequest, ListKeyVersionsRequest, PutObjectRequest};
+use crate::audit::{ADMIN_PRINCIPAL, ANONYMOUS_PRINCIPAL, with_principal};
+use crate::store::{KvStore, is_conflict, validate_store_id};
+use crate::telemetry::record_store_id;
+use crate::tls::ClientIdentity;
+use crate::types::{CreateSnapshotRequest, DeleteObjectRequest, DeleteSnapshotRequest, ErrorCode, ErrorResponse, GetObjectRequest, GetObjectVersionRequest, GetObjectsRequest, GetSnapshotRequest, ListDeletedObjectsRequest, ListKeyVersionsRequest, ListObjectVersionsRequest, ListSnapshotsRequest, PutObjectRequest, RestoreObjectRequest, RestoreSnapshotRequest};
 
 #[debug_handler]
 pub async fn get_object(
-	State(kvstore): State<Arc<DynamoDbStore>>,
-	body: Bytes,
+	State(kvstore): State<Arc<dyn KvStore>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
 ) -> impl IntoResponse {
-	let request = match GetObjectRequest::decode(body.as_ref()) {
-		Ok(req) => req,
-		Err(_) => return Response::builder().status(StatusCode::BAD_REQUEST).body(Body::from("Unable to decode GetObjectRequest")).unwrap(),
-	};
+	handle_request(&headers, identity, body, |request: GetObjectRequest| async move {
+		kvstore.get(request).await
+	}, "Failed to get object").await
+}
 
-	match kvstore.get(request).await {
-		Ok(response) => {
-			let mut rsp = Response::new(Body::from(response.encode_to_vec()));
-			*rsp.status_mut() = StatusCode::OK;
-			rsp
-		}
-		Err(err) => {
-			eprintln!("Failed to get object: {:?}", err);
-			Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::from("Failed to get object")).unwrap()
-		}
-	}
+pub async fn get_objects(
+	State(kvstore): State<Arc<dyn KvStore>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: GetObjectsRequest| async move {
+		kvstore.get_objects(request).await
+	}, "Failed to get objects").await
 }
 
 pub async fn put_object(
-	State(kvstore): State<Arc<DynamoDbStore>>,
-	body: Bytes,
+	State(kvstore): State<Arc<dyn KvStore>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
 ) -> impl IntoResponse {
-	let request = match PutObjectRequest::decode(body.as_ref()) {
-		Ok(req) => req,
-		Err(_err) => return Response::builder().status(StatusCode::BAD_REQUEST).body(Body::from("Unable to decode PutObjectRequest")).unwrap(),
+	handle_request(&headers, identity, body, |request: PutObjectRequest| async move {
+		kvstore.put(request).await
+	}, "Failed to put object").await
+}
+
+pub async fn delete_object(
+	State(kvstore): State<Arc<dyn KvStore>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: DeleteObjectRequest| async move {
+		kvstore.delete(request).await
+	}, "Failed to delete object").await
+}
+
+pub async fn list_key_versions(
+	State(kvstore): State<Arc<dyn KvStore>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: ListKeyVersionsRequest| async move {
+		kvstore.list_key_versions(request).await
+	}, "Failed to list key versions").await
+}
+
+pub async fn list_object_versions(
+	State(kvstore): State<Arc<dyn KvStore>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: ListObjectVersionsRequest| async move {
+		kvstore.list_object_versions(request).await
+	}, "Failed to list object versions").await
+}
+
+pub async fn get_object_version(
+	State(kvstore): State<Arc<dyn KvStore>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: GetObjectVersionRequest| async move {
+		kvstore.get_object_version(request).await
+	}, "Failed to get object version").await
+}
+
+/// Requests scoped to a single `store_id`.
+pub trait StoreRequest {
+	fn store_id(&self) -> &str;
+}
+
+macro_rules! impl_store_request {
+	($($request:ty),*) => {
+		$(impl StoreRequest for $request {
+			fn store_id(&self) -> &str {
+				&self.store_id
+			}
+		})*
 	};
+}
 
-	match kvstore.put(request).await {
-		Ok(response) => {
-			let mut rsp = Response::new(Body::from(response.encode_to_vec()));
-			*rsp.status_mut() = StatusCode::OK;
-			rsp
-		}
-		Err(err) => {
-			eprintln!("Failed to put object: {:?}", err);
-			Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::from("Failed to put object")).unwrap()
+impl_store_request!(GetObjectRequest, GetObjectsRequest, PutObjectRequest, DeleteObjectRequest, ListKeyVersionsRequest, ListObjectVersionsRequest, GetObjectVersionRequest, ListDeletedObjectsRequest, RestoreObjectRequest,
+	CreateSnapshotRequest, ListSnapshotsRequest, GetSnapshotRequest, RestoreSnapshotRequest, DeleteSnapshotRequest);
+
+/// Encoding of request and response bodies.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+	Protobuf,
+	/// Canonical proto3 JSON mapping.
+	/// Ref: https://protobuf.dev/programming-guides/proto3/#json
+	Json,
+}
+
+impl Encoding {
+	/// Encoding of a request body, protobuf unless its `Content-Type` is `application/json`.
+	fn of_request(headers: &HeaderMap) -> Self {
+		let is_json = headers.get(header::CONTENT_TYPE)
+			.and_then(|value| value.to_str().ok())
+			.is_some_and(|value| media_type(value) == "application/json");
+		if is_json { Encoding::Json } else { Encoding::Protobuf }
+	}
+
+	/// Encoding of the response to a request, as listed in its `Accept` header. Responses are encoded
+	/// like the request if it does not accept either encoding specifically.
+	fn of_response(headers: &HeaderMap, request_encoding: Encoding) -> Self {
+		let accepted: Vec<String> = headers.get_all(header::ACCEPT).iter()
+			.filter_map(|value| value.to_str().ok())
+			.flat_map(|value| value.split(',').map(media_type).collect::<Vec<_>>())
+			.collect();
+		if accepted.iter().any(|media_type| media_type == "application/json") {
+			Encoding::Json
+		} else if accepted.iter().any(|media_type| matches!(media_type.as_str(), "application/x-protobuf" | "application/protobuf" | "application/octet-stream")) {
+			Encoding::Protobuf
+		} else {
+			request_encoding
 		}
 	}
 }
 
-pub async fn delete_object(
-	State(kvstore): State<Arc<DynamoDbStore>>,
-	body: Bytes,
-) -> impl IntoResponse {
-	let request = match DeleteObjectRequest::decode(body.as_ref()) {
-		Ok(req) => req,
-		Err(_err) => return Response::builder().status(StatusCode::BAD_REQUEST).body(Body::from("Unable to decode DeleteObjectRequest")).unwrap(),
+/// Returns the media type of a `Content-Type` or `Accept` value, without parameters.
+fn media_type(value: &str) -> String {
+	value.split(';').next().unwrap_or_default().trim().to_ascii_lowercase()
+}
+
+/// Decodes a request from `body`, checks that its `store_id` is valid and that the client may access it, and passes it
+/// to `call`. Responses and errors are encoded as negotiated with the client.
+pub async fn handle_request<Req, Resp, F, Fut>(
+	headers: &HeaderMap, identity: Option<Extension<ClientIdentity>>, body: Result<Bytes, BytesRejection>, call: F, message: &str,
+) -> Response<Body>
+where
+	Req: Message + DeserializeOwned + Default + StoreRequest,
+	Resp: Message + Serialize,
+	F: FnOnce(Req) -> Fut,
+	Fut: Future<Output = std::io::Result<Resp>>,
+{
+	let (encoding, request) = decode_request::<Req>(headers, body);
+	let request = match request {
+		Ok(request) => request,
+		Err((error_code, message)) => return build_error_response(encoding, error_code, &message),
 	};
+	record_store_id(request.store_id());
+	if let Err(err) = validate_store_id(request.store_id()) {
+		return build_error_response(encoding, ErrorCode::InvalidRequestException, &err.to_string());
+	}
+	let principal = identity.as_ref().map_or_else(|| ANONYMOUS_PRINCIPAL.to_string(), |Extension(identity)| identity.subject.clone());
+	if let Some(rsp) = authorize(encoding, identity, request.store_id()) {
+		return rsp;
+	}
 
-	match kvstore.delete(request).await {
-		Ok(response) => {
-			let mut rsp = Response::new(Body::from(response.encode_to_vec()));
-			*rsp.status_mut() = StatusCode::OK;
-			rsp
-		}
-		Err(err) => {
-			eprintln!("Failed to delete object: {:?}", err);
-			Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::from("Failed to delete object")).unwrap()
-		}
+	match with_principal(principal, call(request)).await {
+		Ok(response) => build_response(encoding, StatusCode::OK, &response),
+		Err(err) => map_store_error(encoding, err, message),
 	}
 }
 
-pub async fn list_key_versions(
-	State(kvstore): State<Arc<DynamoDbStore>>,
-	body: Bytes,
-) -> impl IntoResponse {
-	let request = match ListKeyVersionsRequest::decode(body.as_ref()) {
-		Ok(req) => req,
-		Err(_err) => return Response::builder().status(StatusCode::BAD_REQUEST).body(Body::from("Unable to decode ListKeyVersionsRequest")).unwrap(),
+/// Like [`handle_request`], for requests of the admin API. Those are authorized by the admin token
+/// rather than per store, and made on behalf of [`ADMIN_PRINCIPAL`], so `call` has to validate the
+/// store ids it is passed.
+pub async fn handle_admin_request<Req, Resp, F, Fut>(headers: &HeaderMap, body: Result<Bytes, BytesRejection>, call: F, message: &str) -> Response<Body>
+where
+	Req: Message + DeserializeOwned + Default,
+	Resp: Message + Serialize,
+	F: FnOnce(Req) -> Fut,
+	Fut: Future<Output = std::io::Result<Resp>>,
+{
+	let (encoding, request) = decode_request::<Req>(headers, body);
+	let request = match request {
+		Ok(request) => request,
+		Err((error_code, message)) => return build_error_response(encoding, error_code, &message),
 	};
+	match with_principal(ADMIN_PRINCIPAL.to_string(), call(request)).await {
+		Ok(response) => build_response(encoding, StatusCode::OK, &response),
+		Err(err) => map_store_error(encoding, err, message),
+	}
+}
+
+/// Decodes a request from `body`, returning it along with the encoding of the response, or the
+/// error to respond with if it cannot be decoded.
+fn decode_request<Req: Message + DeserializeOwned + Default>(headers: &HeaderMap, body: Result<Bytes, BytesRejection>) -> (Encoding, Result<Req, (ErrorCode, String)>) {
+	let request_encoding = Encoding::of_request(headers);
+	let encoding = Encoding::of_response(headers, request_encoding);
+	let request_name = std::any::type_name::<Req>().rsplit("::").next().unwrap_or_default();
+	let body = match body {
+		Ok(body) => body,
+		Err(rejection) if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+			return (encoding, Err((ErrorCode::PayloadTooLargeException, format!("{} is larger than allowed", request_name))));
+		}
+		Err(rejection) => {
+			return (encoding, Err((ErrorCode::InvalidRequestException, format!("Unable to read {}: {}", request_name, rejection.body_text()))));
+		}
+	};
+	let request = match request_encoding {
+		Encoding::Protobuf => Req::decode(body.as_ref())
+			.map_err(|_| format!("Unable to decode {}", request_name)),
+		Encoding::Json => serde_json::from_slice::<Req>(body.as_ref())
+			.map_err(|err| format!("Unable to decode {}: {}", request_name, err)),
+	};
+	(encoding, request.map_err(|message| (ErrorCode::InvalidRequestException, message)))
+}
+
+/// Middleware failing requests that are not answered within `timeout` with `REQUEST_TIMEOUT_EXCEPTION`.
+///
+/// The handler is dropped once the deadline passes, so timed out writes may or may not have been
+/// applied.
+pub async fn enforce_deadline<B>(State(timeout): State<Duration>, request: Request<B>, next: Next<B>) -> axum::response::Response {
+	let encoding = Encoding::of_response(request.headers(), Encoding::of_request(request.headers()));
+	match tokio::time::timeout(timeout, next.run(request)).await {
+		Ok(response) => response,
+		Err(_) => {
+			tracing::info!(timeout_ms = timeout.as_millis() as u64, "Request did not complete within the deadline");
+			let message = format!("Request did not complete within {}ms", timeout.as_millis());
+			build_error_response(encoding, ErrorCode::RequestTimeoutException, &message).into_response()
+		}
+	}
+}
 
-	match kvstore.list_key_versions(request).await {
-		Ok(response) => {
-			let mut rsp = Response::new(Body::from(response.encode_to_vec()));
-			*rsp.status_mut() = StatusCode::OK;
-			rsp
+/// Returns a `PERMISSION_DENIED_EXCEPTION` response if the client authenticated with `identity` may not access
+/// `store_id`. Requests are not restricted when mutual TLS is disabled.
+pub fn authorize(encoding: Encoding, identity: Option<Extension<ClientIdentity>>, store_id: &str) -> Option<Response<Body>> {
+	match identity {
+		Some(Extension(identity)) if !identity.may_access(store_id) => {
+			tracing::info!(subject = identity.subject, "Client is not authorized to access store");
+			Some(build_error_response(encoding, ErrorCode::PermissionDeniedException, "Client is not authorized to access this store_id"))
 		}
-		Err(err) => {
-			eprintln!("Failed to list key versions: {:?}", err);
-			Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::from("Failed to list key versions")).unwrap()
+		_ => None,
+	}
+}
+
+/// Maps an error returned by the `KvStore` to an `ErrorResponse`. Invalid requests and missing keys
+/// carry the error message, other errors are reported with `message` to avoid leaking internals.
+pub fn map_store_error(encoding: Encoding, err: std::io::Error, message: &str) -> Response<Body> {
+	let error_code = store_error_code(&err);
+	if error_code == ErrorCode::InternalServerException {
+		tracing::error!(error = ?err, "{}", message);
+		build_error_response(encoding, error_code, message)
+	} else {
+		tracing::info!(error = %err, "{}", message);
+		build_error_response(encoding, error_code, &err.to_string())
+	}
+}
+
+/// Returns the `ErrorCode` reported to clients for an error returned by the `KvStore`.
+pub fn store_error_code(err: &std::io::Error) -> ErrorCode {
+	if is_conflict(err) {
+		ErrorCode::ConflictException
+	} else {
+		match err.kind() {
+			std::io::ErrorKind::InvalidInput => ErrorCode::InvalidRequestException,
+			std::io::ErrorKind::NotFound => ErrorCode::NoSuchKeyException,
+			std::io::ErrorKind::TimedOut => ErrorCode::RequestTimeoutException,
+			_ => ErrorCode::InternalServerException,
 		}
 	}
-}
\ No newline at end of file
+}
+
+pub fn build_error_response(encoding: Encoding, error_code: ErrorCode, message: &str) -> Response<Body> {
+	let status = match error_code {
+		ErrorCode::ConflictException => StatusCode::CONFLICT,
+		ErrorCode::InvalidRequestException => StatusCode::BAD_REQUEST,
+		ErrorCode::NoSuchKeyException => StatusCode::NOT_FOUND,
+		ErrorCode::AuthException => StatusCode::UNAUTHORIZED,
+		ErrorCode::PermissionDeniedException => StatusCode::FORBIDDEN,
+		ErrorCode::PayloadTooLargeException => StatusCode::PAYLOAD_TOO_LARGE,
+		ErrorCode::RequestTimeoutException => StatusCode::GATEWAY_TIMEOUT,
+		ErrorCode::InternalServerException | ErrorCode::Unknown => StatusCode::INTERNAL_SERVER_ERROR,
+	};
+	let error_response = ErrorResponse { error_code: error_code.into(), message: message.to_string() };
+	let mut response = build_response(encoding, status, &error_response);
+	// Read by the metrics middleware to count errors by code.
+	response.extensions_mut().insert(error_code);
+	response
+}
+
+fn build_response<T: Message + Serialize>(encoding: Encoding, status: StatusCode, message: &T) -> Response<Body> {
+	match encoding {
+		Encoding::Protobuf => Response::builder()
+			.status(status)
+			.body(Body::from(message.encode_to_vec()))
+			.unwrap(),
+		Encoding::Json => Response::builder()
+			.status(status)
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(Body::from(serde_json::to_vec(message).unwrap()))
+			.unwrap(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::Router;
+	use axum::routing::post;
+	use tower::ServiceExt;
+
+	use super::*;
+	use crate::testing::MemoryStore;
+	use crate::types::GetObjectResponse;
+
+	async fn get_object_json(body: &str) -> (StatusCode, ErrorResponse) {
+		get_object_json_as(body, None).await
+	}
+
+	async fn get_object_json_as(body: &str, identity: Option<ClientIdentity>) -> (StatusCode, ErrorResponse) {
+		let mut headers = HeaderMap::new();
+		headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+		let response = handle_request(&headers, identity.map(Extension), Ok(Bytes::from(body.to_string())), |_: GetObjectRequest| async {
+			Ok(GetObjectResponse::default())
+		}, "Failed to get object").await;
+		let status = response.status();
+		let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+		(status, serde_json::from_slice(&body).unwrap_or_default())
+	}
+
+	#[tokio::test]
+	async fn rejects_internal_store_ids() {
+		let (status, error) = get_object_json(r#"{"storeId": "store\u0000history", "key": "k"}"#).await;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert_eq!(error.error_code, ErrorCode::InvalidRequestException as i32);
+
+		let (status, _) = get_object_json(r#"{"storeId": "store", "key": "k"}"#).await;
+		assert_eq!(status, StatusCode::OK);
+	}
+
+	#[tokio::test]
+	async fn denies_access_to_other_stores() {
+		let identity = ClientIdentity::allowed("CN=wallet-1", &["store-a"]);
+		let (status, _) = get_object_json_as(r#"{"storeId": "store-a", "key": "k"}"#, Some(identity.clone())).await;
+		assert_eq!(status, StatusCode::OK);
+
+		let (status, error) = get_object_json_as(r#"{"storeId": "store-b", "key": "k"}"#, Some(identity)).await;
+		assert_eq!(status, StatusCode::FORBIDDEN);
+		assert_eq!(error.error_code, ErrorCode::PermissionDeniedException as i32);
+	}
+
+	#[tokio::test]
+	async fn times_out_slow_requests() {
+		let store: Arc<dyn KvStore> = Arc::new(MemoryStore::with_get_latency(Duration::from_secs(60)));
+		let router = Router::new()
+			.route("/getObject", post(get_object))
+			.route_layer(axum::middleware::from_fn_with_state(Duration::from_millis(10), enforce_deadline))
+			.with_state(store);
+		let request = Request::post("/getObject")
+			.header(header::CONTENT_TYPE, "application/json")
+			.body(Body::from(r#"{"storeId": "store", "key": "k"}"#))
+			.unwrap();
+		let response = router.oneshot(request).await.unwrap();
+		assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+		assert_eq!(response.extensions().get::<ErrorCode>(), Some(&ErrorCode::RequestTimeoutException));
+		let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+		let error: ErrorResponse = serde_json::from_slice(&body).unwrap();
+		assert_eq!(error.error_code, ErrorCode::RequestTimeoutException as i32);
+	}
+}
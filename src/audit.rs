@@ -0,0 +1,739 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
+use aws_sdk_dynamodb::types::{AttributeDefinition, AttributeValue, BillingMode, KeySchemaElement, KeyType, Put, ScalarAttributeType, TableStatus, TransactWriteItem};
+use axum::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::extract::rejection::BytesRejection;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tracing::Instrument;
+
+use crate::api::{handle_admin_request, store_error_code};
+use crate::metrics::observe_backend_call;
+use crate::store::{HealthStatus, KvStore, conflict_error, is_conflict, next_version};
+use crate::telemetry::loggable_store_id;
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, GetObjectVersionRequest, GetObjectVersionResponse, ItemExpiry, KeyValue, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, PutObjectRequest, PutObjectResponse, StoreMetadata, VerifyAuditLogRequest, VerifyAuditLogResponse};
+
+/// Principal recorded for requests made without a client certificate.
+pub const ANONYMOUS_PRINCIPAL: &str = "anonymous";
+/// Principal recorded for requests made through the admin API.
+pub const ADMIN_PRINCIPAL: &str = "admin";
+// Principal recorded for writes made outside of any request.
+const INTERNAL_PRINCIPAL: &str = "internal";
+// Number of times an entry is appended again after entries were appended by other instances.
+const MAX_APPEND_ATTEMPTS: usize = 10;
+// Value of the `backend` label of metrics recorded by the DynamoDB sink.
+const BACKEND: &str = "dynamodb";
+// Number of bytes read at a time from the end of the audit log file to find its last entry.
+const TAIL_CHUNK_SIZE: u64 = 4096;
+// Number of entries waiting to be appended before operations wait to be recorded.
+const APPEND_QUEUE_SIZE: usize = 1024;
+// Maximum number of entries appended at once, the number of items of a DynamoDB transaction.
+const MAX_APPEND_BATCH_SIZE: usize = 100;
+// How long to wait for the audit table to become ACTIVE after creating it.
+const TABLE_ACTIVE_TIMEOUT: Duration = Duration::from_secs(300);
+
+tokio::task_local! {
+	static PRINCIPAL: String;
+}
+
+/// Runs `future` on behalf of `principal`, who is recorded as the author of the writes it makes.
+pub async fn with_principal<F: Future>(principal: String, future: F) -> F::Output {
+	PRINCIPAL.scope(principal, future).await
+}
+
+fn current_principal() -> String {
+	PRINCIPAL.try_with(Clone::clone).unwrap_or_else(|_| INTERNAL_PRINCIPAL.to_string())
+}
+
+/// Error returned by the admin API verifying the audit log when none is recorded.
+pub fn audit_log_not_enabled() -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidInput, "Audit log is not enabled")
+}
+
+fn now_millis() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as i64
+}
+
+/// A key written or deleted by an audited operation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditedKey {
+	pub key: String,
+	/// Version of the key before the operation, `None` if it did not exist.
+	pub old_version: Option<i64>,
+	/// Version of the key written by the operation, `None` if it was deleted.
+	pub new_version: Option<i64>,
+}
+
+/// An entry of the audit log, chained to the previous one by `prev_hash`.
+///
+/// `hash` is the hex-encoded SHA-256 digest of the entry serialized as JSON with an empty `hash`,
+/// so altering, removing or reordering entries breaks the chain from that point on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+	pub seq: u64,
+	/// Time the operation completed, in milliseconds since the Unix epoch.
+	pub timestamp: i64,
+	/// Subject of the client certificate of the client, or one of the fixed principals.
+	pub principal: String,
+	/// `put`, `delete` or `delete_store`.
+	pub operation: String,
+	pub store_id: String,
+	pub keys: Vec<AuditedKey>,
+	/// `OK`, or the `ErrorCode` returned to the client.
+	pub outcome: String,
+	/// `hash` of the previous entry, empty for the first one.
+	pub prev_hash: String,
+	pub hash: String,
+}
+
+impl AuditEntry {
+	fn compute_hash(&self) -> String {
+		let unhashed = AuditEntry { hash: String::new(), ..self.clone() };
+		hex::encode(Sha256::digest(serde_json::to_vec(&unhashed).unwrap()))
+	}
+
+	/// Whether this entry is intact and directly follows `previous`.
+	pub fn follows(&self, previous: Option<&AuditEntry>) -> bool {
+		let (seq, prev_hash) = match previous {
+			Some(previous) => (previous.seq + 1, previous.hash.as_str()),
+			None => (0, ""),
+		};
+		self.seq == seq && self.prev_hash == prev_hash && self.hash == self.compute_hash()
+	}
+}
+
+/// Destination of audit entries, appended in `seq` order.
+#[async_trait]
+pub trait AuditSink: Send + Sync {
+	/// Returns the last entry appended, failing if it was tampered with.
+	async fn last(&self) -> std::io::Result<Option<AuditEntry>>;
+	/// Appends `entries`, each following the previous one, either all or none of them. Fails with a
+	/// conflict if an entry with the same `seq` exists.
+	async fn append(&self, entries: &[AuditEntry]) -> std::io::Result<()>;
+	/// Walks the chain from its first entry, checking that each entry follows the previous one.
+	async fn verify(&self) -> std::io::Result<VerifyAuditLogResponse>;
+}
+
+fn tampered(message: String) -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Checks the entries of a chain as they are read, from the first one.
+#[derive(Default)]
+struct ChainVerifier {
+	previous: Option<AuditEntry>,
+	response: VerifyAuditLogResponse,
+}
+
+impl ChainVerifier {
+	/// Checks the next entry, `None` if it could not be read. Returns whether the chain is intact so far.
+	fn check(&mut self, entry: Option<AuditEntry>) -> bool {
+		match entry {
+			Some(entry) if entry.follows(self.previous.as_ref()) => {
+				self.response.entry_count += 1;
+				self.previous = Some(entry);
+				true
+			}
+			_ => {
+				self.response.broken_at = Some(self.previous.as_ref().map_or(0, |previous| previous.seq as i64 + 1));
+				false
+			}
+		}
+	}
+}
+
+/// Appends entries as JSON lines to a file, which must only be written to by this instance.
+pub struct FileSink {
+	path: PathBuf,
+	file: Mutex<tokio::fs::File>,
+	last: std::sync::Mutex<Option<AuditEntry>>,
+}
+
+impl FileSink {
+	/// Opens the audit log at `path`, creating it if needed, and checks its last entry. Earlier
+	/// entries are checked by [`AuditSink::verify`].
+	pub async fn open(path: &Path) -> std::io::Result<Self> {
+		let last = match read_last_line(path).await? {
+			Some(line) => {
+				let entry: AuditEntry = serde_json::from_slice(&line)
+					.map_err(|err| tampered(format!("Unreadable last entry of {}: {}", path.display(), err)))?;
+				if entry.hash != entry.compute_hash() {
+					return Err(tampered(format!("Last entry of audit log {} was altered", path.display())));
+				}
+				Some(entry)
+			}
+			None => None,
+		};
+		let file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+		Ok(Self { path: path.to_path_buf(), file: Mutex::new(file), last: std::sync::Mutex::new(last) })
+	}
+}
+
+/// Reads the last line of the file at `path`, without its newline, reading the file backwards from
+/// its end.
+async fn read_last_line(path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+	let mut file = match tokio::fs::File::open(path).await {
+		Ok(file) => file,
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+		Err(err) => return Err(err),
+	};
+	let mut end = file.metadata().await?.len();
+	let mut tail = Vec::new();
+	loop {
+		let start = end.saturating_sub(TAIL_CHUNK_SIZE);
+		let mut chunk = vec![0; (end - start) as usize];
+		file.seek(SeekFrom::Start(start)).await?;
+		file.read_exact(&mut chunk).await?;
+		chunk.extend_from_slice(&tail);
+		tail = chunk;
+		end = start;
+		let lines = tail.strip_suffix(b"\n").unwrap_or(&tail);
+		if let Some(newline) = lines.iter().rposition(|byte| *byte == b'\n') {
+			return Ok(Some(lines[newline + 1..].to_vec()));
+		}
+		if start == 0 {
+			return Ok(Some(lines.to_vec()).filter(|line| !line.is_empty()));
+		}
+	}
+}
+
+#[async_trait]
+impl AuditSink for FileSink {
+	async fn last(&self) -> std::io::Result<Option<AuditEntry>> {
+		Ok(self.last.lock().unwrap().clone())
+	}
+	async fn append(&self, entries: &[AuditEntry]) -> std::io::Result<()> {
+		let mut lines = Vec::new();
+		for entry in entries {
+			serde_json::to_writer(&mut lines, entry).unwrap();
+			lines.push(b'\n');
+		}
+		let mut file = self.file.lock().await;
+		let len = file.metadata().await?.len();
+		if let Err(err) = file.write_all(&lines).await.and(file.sync_data().await) {
+			// Drops the part of the entries which was written, so that the chain can be continued.
+			file.set_len(len).await.ok();
+			return Err(std::io::Error::new(err.kind(), format!("Failed to append to audit log {}: {}", self.path.display(), err)));
+		}
+		if let Some(entry) = entries.last() {
+			*self.last.lock().unwrap() = Some(entry.clone());
+		}
+		Ok(())
+	}
+	async fn verify(&self) -> std::io::Result<VerifyAuditLogResponse> {
+		let mut reader = tokio::io::BufReader::new(tokio::fs::File::open(&self.path).await?);
+		let mut verifier = ChainVerifier::default();
+		let mut line = Vec::new();
+		loop {
+			line.clear();
+			reader.read_until(b'\n', &mut line).await?;
+			// A line without a newline is still being appended.
+			let Some(line) = line.strip_suffix(b"\n") else { break };
+			if !verifier.check(serde_json::from_slice(line).ok()) {
+				break;
+			}
+		}
+		Ok(verifier.response)
+	}
+}
+
+/// Appends entries to a DynamoDB table, with the `chain` partition key and `seq` numeric sort key,
+/// holding the entry as JSON in `entry`. Instances sharing the table should append to chains with
+/// different ids, otherwise they compete to append to the same chain.
+pub struct DynamoDbSink {
+	client: Client,
+	table_name: String,
+	chain_id: String,
+}
+
+impl DynamoDbSink {
+	pub fn new(client: Client, table_name: String, chain_id: String) -> Self {
+		Self { client, table_name, chain_id }
+	}
+
+	fn item(&self, entry: &AuditEntry) -> HashMap<String, AttributeValue> {
+		HashMap::from([
+			("chain".to_string(), AttributeValue::S(self.chain_id.clone())),
+			("seq".to_string(), AttributeValue::N(entry.seq.to_string())),
+			("entry".to_string(), AttributeValue::S(serde_json::to_string(entry).unwrap())),
+		])
+	}
+
+	fn parse_entry(item: &HashMap<String, AttributeValue>) -> std::io::Result<AuditEntry> {
+		let json = item.get("entry").and_then(|av| av.as_s().ok()).ok_or_else(|| tampered("Audit entry without `entry`".to_string()))?;
+		serde_json::from_str(json).map_err(|err| tampered(format!("Unreadable audit entry: {}", err)))
+	}
+
+	/// Creates the audit table if it does not exist and `create` is set.
+	pub async fn ensure_table(&self, create: bool) -> std::io::Result<()> {
+		match self.client.describe_table().table_name(&self.table_name).send().await {
+			Ok(_) => return Ok(()),
+			Err(err) => {
+				let err = err.into_service_error();
+				if !(create && err.is_resource_not_found_exception()) {
+					return Err(std::io::Error::other(format!("Failed to describe table {}: {:?}", self.table_name, err)));
+				}
+			}
+		}
+		let query = self.client.create_table()
+			.table_name(&self.table_name)
+			.attribute_definitions(AttributeDefinition::builder().attribute_name("chain").attribute_type(ScalarAttributeType::S).build().unwrap())
+			.attribute_definitions(AttributeDefinition::builder().attribute_name("seq").attribute_type(ScalarAttributeType::N).build().unwrap())
+			.key_schema(KeySchemaElement::builder().attribute_name("chain").key_type(KeyType::Hash).build().unwrap())
+			.key_schema(KeySchemaElement::builder().attribute_name("seq").key_type(KeyType::Range).build().unwrap())
+			.billing_mode(BillingMode::PayPerRequest);
+		match query.send().await.map_err(|err| err.into_service_error()) {
+			Ok(_) => {}
+			// Another instance created the table concurrently.
+			Err(err) if err.is_resource_in_use_exception() => {}
+			Err(err) => return Err(std::io::Error::other(format!("Failed to create table {}: {:?}", self.table_name, err))),
+		}
+		let deadline = tokio::time::Instant::now() + TABLE_ACTIVE_TIMEOUT;
+		loop {
+			let output = self.client.describe_table().table_name(&self.table_name).send().await
+				.map_err(|err| std::io::Error::other(format!("Failed to describe table {}: {:?}", self.table_name, err)))?;
+			if output.table.as_ref().and_then(|table| table.table_status()) == Some(&TableStatus::Active) {
+				return Ok(());
+			}
+			if tokio::time::Instant::now() >= deadline {
+				return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, format!("Table {} did not become active", self.table_name)));
+			}
+			tokio::time::sleep(Duration::from_secs(1)).await;
+		}
+	}
+}
+
+#[async_trait]
+impl AuditSink for DynamoDbSink {
+	async fn last(&self) -> std::io::Result<Option<AuditEntry>> {
+		let query = self.client.query()
+			.table_name(&self.table_name)
+			.key_condition_expression("chain = :chain")
+			.expression_attribute_values(":chain", AttributeValue::S(self.chain_id.clone()))
+			.scan_index_forward(false)
+			.limit(1)
+			.consistent_read(true);
+		let output = observe_backend_call(BACKEND, "Query", query.send())
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to read last audit entry: {:?}", err)))?;
+		let entry = match output.items.unwrap_or_default().pop() {
+			Some(item) => Self::parse_entry(&item)?,
+			None => return Ok(None),
+		};
+		// Earlier entries are checked by `verify`.
+		if entry.hash != entry.compute_hash() {
+			return Err(tampered(format!("Audit entry {} of table {} was altered", entry.seq, self.table_name)));
+		}
+		Ok(Some(entry))
+	}
+	async fn append(&self, entries: &[AuditEntry]) -> std::io::Result<()> {
+		let conflict = || conflict_error(format!("Audit entries from {} already exist", entries.first().map_or(0, |entry| entry.seq)));
+		if let [entry] = entries {
+			let query = self.client.put_item()
+				.table_name(&self.table_name)
+				.set_item(Some(self.item(entry)))
+				.condition_expression("attribute_not_exists(seq)");
+			observe_backend_call(BACKEND, "PutItem", query.send())
+				.await
+				.map_err(|err| match err.into_service_error() {
+					err if err.is_conditional_check_failed_exception() => conflict(),
+					err => std::io::Error::other(format!("Failed to append audit entry: {:?}", err)),
+				})?;
+			return Ok(());
+		}
+		let items = entries.iter()
+			.map(|entry| {
+				let put = Put::builder()
+					.table_name(&self.table_name)
+					.set_item(Some(self.item(entry)))
+					.condition_expression("attribute_not_exists(seq)")
+					.build()
+					.unwrap();
+				TransactWriteItem::builder().put(put).build()
+			})
+			.collect();
+		let query = self.client.transact_write_items().set_transact_items(Some(items));
+		observe_backend_call(BACKEND, "TransactWriteItems", query.send())
+			.await
+			.map_err(|err| match err.into_service_error() {
+				TransactWriteItemsError::TransactionCanceledException(err)
+					if err.cancellation_reasons().iter().any(|reason| reason.code() == Some("ConditionalCheckFailed")) => conflict(),
+				err => std::io::Error::other(format!("Failed to append audit entries: {:?}", err)),
+			})?;
+		Ok(())
+	}
+	async fn verify(&self) -> std::io::Result<VerifyAuditLogResponse> {
+		let mut verifier = ChainVerifier::default();
+		let mut exclusive_start_key = None;
+		loop {
+			let query = self.client.query()
+				.table_name(&self.table_name)
+				.key_condition_expression("chain = :chain")
+				.expression_attribute_values(":chain", AttributeValue::S(self.chain_id.clone()))
+				.set_exclusive_start_key(exclusive_start_key)
+				.consistent_read(true);
+			let output = observe_backend_call(BACKEND, "Query", query.send())
+				.await
+				.map_err(|err| std::io::Error::other(format!("Failed to read audit entries: {:?}", err)))?;
+			for item in output.items.unwrap_or_default() {
+				if !verifier.check(Self::parse_entry(&item).ok()) {
+					return Ok(verifier.response);
+				}
+			}
+			match output.last_evaluated_key {
+				Some(key) => exclusive_start_key = Some(key),
+				None => return Ok(verifier.response),
+			}
+		}
+	}
+}
+
+/// An entry waiting to be chained and appended by the appender task.
+struct PendingEntry {
+	timestamp: i64,
+	principal: String,
+	operation: &'static str,
+	store_id: String,
+	keys: Vec<AuditedKey>,
+	outcome: &'static str,
+}
+
+struct AppendRequest {
+	entry: PendingEntry,
+	appended: oneshot::Sender<std::io::Result<()>>,
+}
+
+/// Appends the entries requested through `requests` to `sink`, continuing the chain from `last`.
+/// Entries requested while an append is in progress are appended together by the next one.
+async fn run_appender(sink: Arc<dyn AuditSink>, mut last: Option<AuditEntry>, mut requests: mpsc::Receiver<AppendRequest>) {
+	let mut batch = Vec::with_capacity(MAX_APPEND_BATCH_SIZE);
+	while requests.recv_many(&mut batch, MAX_APPEND_BATCH_SIZE).await > 0 {
+		let result = append_batch(sink.as_ref(), &mut last, &batch).await;
+		for request in batch.drain(..) {
+			let result = result.as_ref().map(|_| ()).map_err(|err| std::io::Error::new(err.kind(), err.to_string()));
+			request.appended.send(result).ok();
+		}
+	}
+}
+
+async fn append_batch(sink: &dyn AuditSink, last: &mut Option<AuditEntry>, batch: &[AppendRequest]) -> std::io::Result<()> {
+	for _ in 0..MAX_APPEND_ATTEMPTS {
+		let mut entries: Vec<AuditEntry> = Vec::with_capacity(batch.len());
+		for AppendRequest { entry, .. } in batch {
+			let previous = entries.last().or(last.as_ref());
+			let mut entry = AuditEntry {
+				seq: previous.map_or(0, |previous| previous.seq + 1),
+				timestamp: entry.timestamp,
+				principal: entry.principal.clone(),
+				operation: entry.operation.to_string(),
+				store_id: entry.store_id.clone(),
+				keys: entry.keys.clone(),
+				outcome: entry.outcome.to_string(),
+				prev_hash: previous.map(|previous| previous.hash.clone()).unwrap_or_default(),
+				hash: String::new(),
+			};
+			entry.hash = entry.compute_hash();
+			entries.push(entry);
+		}
+		match sink.append(&entries).await {
+			Ok(()) => {
+				*last = entries.pop();
+				return Ok(());
+			}
+			// Appended to by another instance, continue from its entries.
+			Err(err) if is_conflict(&err) => *last = sink.last().await?,
+			Err(err) => return Err(err),
+		}
+	}
+	Err(conflict_error("Audit log kept being appended to by other instances".to_string()))
+}
+
+/// A `KvStore` recording every put and delete made through it, with their principal and outcome, in
+/// a hash-chained audit log.
+///
+/// Each operation runs in a task along with the append of its entry, so that operations applied
+/// are recorded even if the client goes away. An operation only succeeds once its entry is
+/// appended: if the entry cannot be appended, the operation fails although it may have been
+/// applied. Entries are appended by a single task in the order operations completed, those of
+/// operations completing while an append is in progress being appended together.
+pub struct AuditStore {
+	inner: Arc<dyn KvStore>,
+	sink: Arc<dyn AuditSink>,
+	appender: mpsc::Sender<AppendRequest>,
+}
+
+impl AuditStore {
+	/// Continues the chain of the entries already in `sink`.
+	pub async fn new(inner: Arc<dyn KvStore>, sink: Arc<dyn AuditSink>) -> std::io::Result<Self> {
+		let last = sink.last().await?;
+		let (appender, requests) = mpsc::channel(APPEND_QUEUE_SIZE);
+		tokio::spawn(run_appender(Arc::clone(&sink), last, requests));
+		Ok(Self { inner, sink, appender })
+	}
+
+	/// Checks the whole chain of the audit log, see [`AuditSink::verify`].
+	pub async fn verify(&self) -> std::io::Result<VerifyAuditLogResponse> {
+		self.sink.verify().await
+	}
+
+	/// Runs `operation` in a task which then appends its entry, failing if the entry cannot be
+	/// appended.
+	async fn audited<T, F>(&self, name: &'static str, store_id: String, keys: Vec<AuditedKey>, operation: F) -> std::io::Result<T>
+	where
+		T: Send + 'static,
+		F: Future<Output = std::io::Result<T>> + Send + 'static,
+	{
+		let principal = current_principal();
+		let appender = self.appender.clone();
+		let task = with_principal(principal.clone(), async move {
+			let result = operation.await;
+			let outcome = match &result {
+				Ok(_) => "OK",
+				Err(err) => store_error_code(err).as_str_name(),
+			};
+			let loggable_store_id = loggable_store_id(&store_id);
+			let entry = PendingEntry { timestamp: now_millis(), principal, operation: name, store_id, keys, outcome };
+			if let Err(err) = append(&appender, entry).await {
+				tracing::error!(store_id = loggable_store_id, operation = name, outcome, error = %err, "Failed to append to audit log");
+				return result.and(Err(std::io::Error::other(format!("Failed to append to audit log, the {} may have been applied", name))));
+			}
+			result
+		});
+		tokio::spawn(task.in_current_span()).await
+			.unwrap_or_else(|err| Err(std::io::Error::other(format!("Audited {} failed: {}", name, err))))
+	}
+}
+
+async fn append(appender: &mpsc::Sender<AppendRequest>, entry: PendingEntry) -> std::io::Result<()> {
+	let stopped = || std::io::Error::other("Audit log appender stopped");
+	let (sender, appended) = oneshot::channel();
+	appender.send(AppendRequest { entry, appended: sender }).await.map_err(|_| stopped())?;
+	appended.await.unwrap_or_else(|_| Err(stopped()))
+}
+
+#[async_trait]
+impl KvStore for AuditStore {
+	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
+		self.inner.get(request).await
+	}
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+		self.inner.get_objects(request).await
+	}
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+		self.inner.get_versions(store_id, keys).await
+	}
+	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+		let store_id = request.store_id.clone();
+		let keys = request.transaction_items.iter().chain(&request.delete_items).map(|kv| kv.key.clone()).collect();
+		let current_versions = self.inner.get_versions(&store_id, keys).await?;
+		let audited_keys = request.transaction_items.iter()
+			.map(|kv| (kv, Some(next_version(kv.version))))
+			.chain(request.delete_items.iter().map(|kv| (kv, None)))
+			.map(|(kv, new_version)| AuditedKey { key: kv.key.clone(), old_version: current_versions.get(&kv.key).copied(), new_version })
+			.collect();
+
+		let inner = Arc::clone(&self.inner);
+		self.audited("put", store_id, audited_keys, async move { inner.put(request).await }).await
+	}
+	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
+		let store_id = request.store_id.clone();
+		let keys: Vec<String> = request.key_value.iter().map(|kv| kv.key.clone()).collect();
+		let current_versions = self.inner.get_versions(&store_id, keys.clone()).await?;
+		let audited_keys = keys.into_iter()
+			.map(|key| AuditedKey { old_version: current_versions.get(&key).copied(), key, new_version: None })
+			.collect();
+
+		let inner = Arc::clone(&self.inner);
+		self.audited("delete", store_id, audited_keys, async move { inner.delete(request).await }).await
+	}
+	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+		self.inner.list_key_versions(request).await
+	}
+	async fn list_object_versions(&self, request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+		self.inner.list_object_versions(request).await
+	}
+	async fn get_object_version(&self, request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+		self.inner.get_object_version(request).await
+	}
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+		self.inner.global_version(store_id).await
+	}
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+		self.inner.backfill(store_id, key_values, item_expiries).await
+	}
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+		self.inner.backfill_global_version(store_id, global_version).await
+	}
+	async fn purge_expired(&self) -> std::io::Result<usize> {
+		self.inner.purge_expired().await
+	}
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+		self.inner.list_stores(request).await
+	}
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+		self.inner.store_metadata(store_id).await
+	}
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+		// Keys are not listed, the entry records the erasure of the whole store.
+		let inner = Arc::clone(&self.inner);
+		let owned_store_id = store_id.to_string();
+		self.audited("delete_store", store_id.to_string(), Vec::new(), async move { inner.delete_store(&owned_store_id).await }).await
+	}
+	async fn check_health(&self) -> std::io::Result<HealthStatus> {
+		self.inner.check_health().await
+	}
+}
+
+/// Route of the admin API verifying the chain of the audit log, `POST /admin/verifyAuditLog`. It is
+/// served by [`crate::admin::router`], behind the admin token.
+pub fn routes(audit: Option<Arc<AuditStore>>) -> Router {
+	Router::new()
+		.route("/admin/verifyAuditLog", post(verify_audit_log))
+		.with_state(audit)
+}
+
+async fn verify_audit_log(State(audit): State<Option<Arc<AuditStore>>>, headers: HeaderMap, body: Result<Bytes, BytesRejection>) -> impl IntoResponse {
+	handle_admin_request(&headers, body, |_: VerifyAuditLogRequest| async move {
+		audit.ok_or_else(audit_log_not_enabled)?.verify().await
+	}, "Failed to verify audit log").await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{MemoryStore, dynamodb_client, get, put};
+
+	fn log_path() -> PathBuf {
+		std::env::temp_dir().join(format!("vss-audit-{}.log", uuid::Uuid::new_v4()))
+	}
+
+	async fn file_audit_store(path: &Path) -> AuditStore {
+		let sink = Arc::new(FileSink::open(path).await.unwrap());
+		AuditStore::new(Arc::new(MemoryStore::default()), sink).await.unwrap()
+	}
+
+	fn read_entries(path: &Path) -> Vec<AuditEntry> {
+		std::fs::read_to_string(path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+	}
+
+	struct FailingSink;
+
+	#[async_trait]
+	impl AuditSink for FailingSink {
+		async fn last(&self) -> std::io::Result<Option<AuditEntry>> {
+			Ok(None)
+		}
+		async fn append(&self, _entries: &[AuditEntry]) -> std::io::Result<()> {
+			Err(std::io::Error::other("Disk full"))
+		}
+		async fn verify(&self) -> std::io::Result<VerifyAuditLogResponse> {
+			Ok(VerifyAuditLogResponse::default())
+		}
+	}
+
+	#[tokio::test]
+	async fn chains_entries_of_concurrent_writes() {
+		let path = log_path();
+		let store = Arc::new(file_audit_store(&path).await);
+		let writes = (0..20).map(|index| {
+			let store = Arc::clone(&store);
+			with_principal("alice".to_string(), async move { put(store.as_ref(), "store", &format!("k{}", index), 0, b"v").await })
+		});
+		for result in futures_util::future::join_all(writes).await {
+			result.unwrap();
+		}
+		assert!(is_conflict(&put(store.as_ref(), "store", "k0", 0, b"v").await.unwrap_err()));
+
+		let entries = read_entries(&path);
+		assert_eq!(entries.len(), 21);
+		assert!(entries[..20].iter().all(|entry| entry.principal == "alice" && entry.outcome == "OK"));
+		assert_eq!((entries[20].principal.as_str(), entries[20].outcome.as_str()), (INTERNAL_PRINCIPAL, "CONFLICT_EXCEPTION"));
+		assert_eq!(store.verify().await.unwrap(), VerifyAuditLogResponse { entry_count: 21, broken_at: None });
+
+		// Reopening the log continues its chain.
+		drop(store);
+		let store = file_audit_store(&path).await;
+		put(&store, "store", "k", 0, b"v").await.unwrap();
+		assert_eq!(store.verify().await.unwrap(), VerifyAuditLogResponse { entry_count: 22, broken_at: None });
+		std::fs::remove_file(path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn detects_tampered_entries() {
+		let path = log_path();
+		let store = file_audit_store(&path).await;
+		for key in ["a", "b", "c"] {
+			put(&store, "store", key, 0, b"v").await.unwrap();
+		}
+		let mut entries = read_entries(&path);
+		entries[1].store_id = "other".to_string();
+		let lines: Vec<String> = entries.iter().map(|entry| serde_json::to_string(entry).unwrap()).collect();
+		std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+		assert_eq!(store.verify().await.unwrap(), VerifyAuditLogResponse { entry_count: 1, broken_at: Some(1) });
+		// Only the last entry is checked when opening the log.
+		assert!(FileSink::open(&path).await.is_ok());
+		entries[2].outcome = "CONFLICT_EXCEPTION".to_string();
+		let lines: Vec<String> = entries.iter().map(|entry| serde_json::to_string(entry).unwrap()).collect();
+		std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+		assert_eq!(FileSink::open(&path).await.err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+		std::fs::remove_file(path).unwrap();
+	}
+
+	#[tokio::test]
+	async fn fails_writes_whose_entries_cannot_be_appended() {
+		let inner = Arc::new(MemoryStore::default());
+		let store = AuditStore::new(Arc::clone(&inner) as Arc<dyn KvStore>, Arc::new(FailingSink)).await.unwrap();
+		assert_eq!(put(&store, "store", "k", 0, b"v").await.unwrap_err().kind(), std::io::ErrorKind::Other);
+		// The write itself was applied.
+		assert_eq!(get(inner.as_ref(), "store", "k").await.unwrap().version, 1);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn verifies_chains_of_dynamodb_table() {
+		let client = dynamodb_client().await;
+		let table_name = format!("vss-test-audit-{}", uuid::Uuid::new_v4());
+		let sink = |chain_id: &str| DynamoDbSink::new(client.clone(), table_name.clone(), chain_id.to_string());
+		sink("a").ensure_table(true).await.unwrap();
+		let inner: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		let store_a = AuditStore::new(Arc::clone(&inner), Arc::new(sink("a"))).await.unwrap();
+		let store_b = AuditStore::new(inner, Arc::new(sink("b"))).await.unwrap();
+		for key in ["a", "b", "c"] {
+			put(&store_a, "store", key, 0, b"v").await.unwrap();
+		}
+		put(&store_b, "store", "d", 0, b"v").await.unwrap();
+		assert_eq!(store_a.verify().await.unwrap(), VerifyAuditLogResponse { entry_count: 3, broken_at: None });
+		assert_eq!(store_b.verify().await.unwrap(), VerifyAuditLogResponse { entry_count: 1, broken_at: None });
+
+		// Batches are appended atomically, failing if any of their entries exists.
+		let last = store_a.sink.last().await.unwrap().unwrap();
+		let next = AuditEntry { seq: last.seq + 1, ..last.clone() };
+		assert!(is_conflict(&sink("a").append(&[last, next]).await.unwrap_err()));
+		assert_eq!(store_a.verify().await.unwrap(), VerifyAuditLogResponse { entry_count: 3, broken_at: None });
+
+		let mut tampered = store_a.sink.last().await.unwrap().unwrap();
+		tampered.seq = 1;
+		tampered.outcome = "OK".to_string();
+		client.put_item().table_name(&table_name).set_item(Some(sink("a").item(&tampered))).send().await.unwrap();
+		assert_eq!(store_a.verify().await.unwrap(), VerifyAuditLogResponse { entry_count: 1, broken_at: Some(1) });
+		assert_eq!(store_b.verify().await.unwrap(), VerifyAuditLogResponse { entry_count: 1, broken_at: None });
+	}
+}
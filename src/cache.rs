@@ -0,0 +1,436 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::async_trait;
+
+use crate::config::CacheConfig;
+use crate::metrics::{record_cache_lookup, set_cache_size};
+use crate::store::{HealthStatus, KvStore, MAX_GET_OBJECTS_KEYS, next_version};
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, GetObjectVersionRequest, GetObjectVersionResponse, ItemExpiry, KeyValue, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, PutObjectRequest, PutObjectResponse, StoreMetadata};
+
+// Memory taken by a cached value besides its key and value, counted against `max_bytes`.
+const ENTRY_OVERHEAD: usize = 128;
+// Values taking more than this fraction of the cache are not cached, so that a single large value
+// does not evict all others.
+const MAX_ENTRY_FRACTION: usize = 8;
+
+type CacheKey = (String, String);
+
+struct Entry {
+	// `None` for keys written through the cache whose value is not cached.
+	value: Option<KeyValue>,
+	cached_at: Instant,
+	last_used: u64,
+	// `Lru::writes` once the key was last written through the cache, as far as known.
+	written_at: u64,
+}
+
+fn entry_size(store_id: &str, key: &str, value: Option<&KeyValue>) -> usize {
+	store_id.len() + key.len() + value.map_or(0, |value| value.value.len()) + ENTRY_OVERHEAD
+}
+
+/// Values keyed by store id and key, evicting the least recently used beyond `max_bytes`.
+///
+/// Keys written through the cache keep an entry, without a value if it is not cached, recording
+/// when they were written so that values read before are not cached after the write.
+struct Lru {
+	entries: HashMap<CacheKey, Entry>,
+	// Keys of the entries by the time they were last used.
+	recency: BTreeMap<u64, CacheKey>,
+	clock: u64,
+	size: usize,
+	max_bytes: usize,
+	// Number of writes made through the cache, read when reads start to order them with writes.
+	writes: u64,
+	// Highest `written_at` of the entries removed, which reads started before may have missed.
+	evicted_floor: u64,
+}
+
+impl Lru {
+	fn new(max_bytes: usize) -> Self {
+		Self { entries: HashMap::new(), recency: BTreeMap::new(), clock: 0, size: 0, max_bytes, writes: 0, evicted_floor: 0 }
+	}
+
+	fn get(&mut self, store_id: &str, key: &str) -> Option<(KeyValue, Instant)> {
+		let cache_key = (store_id.to_string(), key.to_string());
+		let entry = self.entries.get_mut(&cache_key)?;
+		self.clock += 1;
+		self.recency.remove(&entry.last_used);
+		entry.last_used = self.clock;
+		self.recency.insert(self.clock, cache_key);
+		Some((entry.value.clone()?, entry.cached_at))
+	}
+
+	/// Caches `value` read by a read which started once `read_start` writes were made, unless it
+	/// may have missed a write of the key.
+	fn cache_read(&mut self, store_id: &str, value: KeyValue, read_start: u64) {
+		if read_start < self.evicted_floor {
+			return;
+		}
+		let written_at = match self.entries.get(&(store_id.to_string(), value.key.clone())) {
+			Some(entry) if entry.written_at > read_start => return,
+			Some(entry) => entry.written_at,
+			None => self.evicted_floor,
+		};
+		let key = value.key.clone();
+		self.insert(store_id, &key, Some(value), written_at);
+	}
+
+	/// Records a write of `key` through the cache, caching the `value` written if known.
+	fn record_write(&mut self, store_id: &str, key: &str, value: Option<KeyValue>) {
+		self.writes += 1;
+		self.insert(store_id, key, value, self.writes);
+	}
+
+	fn insert(&mut self, store_id: &str, key: &str, value: Option<KeyValue>, written_at: u64) {
+		let cache_key = (store_id.to_string(), key.to_string());
+		if let Some(entry) = self.entries.remove(&cache_key) {
+			self.recency.remove(&entry.last_used);
+			self.size -= entry_size(store_id, key, entry.value.as_ref());
+		}
+		// Values too large are not cached, though the write is still recorded.
+		let value = value.filter(|value| entry_size(store_id, key, Some(value)) <= self.max_bytes / MAX_ENTRY_FRACTION);
+		self.clock += 1;
+		self.size += entry_size(store_id, key, value.as_ref());
+		self.recency.insert(self.clock, cache_key.clone());
+		self.entries.insert(cache_key, Entry { value, cached_at: Instant::now(), last_used: self.clock, written_at });
+		while self.size > self.max_bytes {
+			let Some((_, (store_id, key))) = self.recency.pop_first() else { break };
+			self.remove(&store_id, &key);
+		}
+		set_cache_size(self.size);
+	}
+
+	fn remove(&mut self, store_id: &str, key: &str) {
+		if let Some(entry) = self.entries.remove(&(store_id.to_string(), key.to_string())) {
+			self.recency.remove(&entry.last_used);
+			self.size -= entry_size(store_id, key, entry.value.as_ref());
+			self.evicted_floor = self.evicted_floor.max(entry.written_at);
+			set_cache_size(self.size);
+		}
+	}
+
+	/// Removes all entries of `store_id`, once deleted.
+	fn remove_store(&mut self, store_id: &str) {
+		let keys: Vec<String> = self.entries.keys()
+			.filter(|(entry_store_id, _)| entry_store_id == store_id)
+			.map(|(_, key)| key.clone())
+			.collect();
+		for key in keys {
+			self.remove(store_id, &key);
+		}
+		// Every key of the store was written to, including those without an entry.
+		self.writes += 1;
+		self.evicted_floor = self.writes;
+	}
+}
+
+/// Answers a read of `value` as the backend would for a client holding `known_version`.
+fn respond(value: KeyValue, known_version: Option<i64>) -> GetObjectResponse {
+	if known_version == Some(value.version) {
+		return GetObjectResponse { value: Some(KeyValue { key: value.key, version: value.version, ..Default::default() }), not_modified: true };
+	}
+	GetObjectResponse { value: Some(value), not_modified: false }
+}
+
+/// A `KvStore` keeping the values read and written through it in memory, up to
+/// [`CacheConfig::max_bytes`].
+///
+/// Writes through it update or evict the values they replace. If [`CacheConfig::validate`] is set,
+/// cached values are only served once the backend confirmed their version is current, which costs a
+/// read of the version alone, so writes made by other instances are never missed. Otherwise they
+/// are served for up to [`CacheConfig::max_age`].
+pub struct CachingStore {
+	inner: Arc<dyn KvStore>,
+	config: CacheConfig,
+	lru: Mutex<Lru>,
+}
+
+impl CachingStore {
+	pub fn new(inner: Arc<dyn KvStore>, config: CacheConfig) -> Self {
+		let lru = Mutex::new(Lru::new(config.max_bytes));
+		Self { inner, config, lru }
+	}
+
+	/// Returns the number of writes made so far, to be passed to [`CachingStore::cache_reads`] by
+	/// reads starting.
+	fn read_start(&self) -> u64 {
+		self.lru.lock().unwrap().writes
+	}
+
+	fn lookup(&self, store_id: &str, key: &str) -> Option<KeyValue> {
+		let mut lru = self.lru.lock().unwrap();
+		let (value, cached_at) = lru.get(store_id, key)?;
+		if !self.config.validate && cached_at.elapsed() > self.config.max_age {
+			lru.remove(store_id, key);
+			return None;
+		}
+		Some(value)
+	}
+
+	/// Caches `values` read from the backend by a read started at `read_start`, except those of
+	/// keys written since.
+	fn cache_reads(&self, store_id: &str, values: impl IntoIterator<Item = KeyValue>, read_start: u64) {
+		let mut lru = self.lru.lock().unwrap();
+		for value in values {
+			lru.cache_read(store_id, value, read_start);
+		}
+	}
+
+	/// Records writes of `keys`, caching the values written if known.
+	fn record_writes(&self, store_id: &str, keys: &[String], mut written: HashMap<String, KeyValue>) {
+		let mut lru = self.lru.lock().unwrap();
+		for key in keys {
+			lru.record_write(store_id, key, written.remove(key));
+		}
+	}
+}
+
+#[async_trait]
+impl KvStore for CachingStore {
+	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
+		let store_id = request.store_id.clone();
+		let known_version = request.known_version;
+		let read_start = self.read_start();
+		let cached = match self.lookup(&store_id, &request.key) {
+			Some(cached) => cached,
+			None => {
+				record_cache_lookup("miss");
+				let response = self.inner.get(request).await?;
+				if let Some(value) = response.value.as_ref().filter(|_| !response.not_modified) {
+					self.cache_reads(&store_id, [value.clone()], read_start);
+				}
+				return Ok(response);
+			}
+		};
+		if !self.config.validate {
+			record_cache_lookup("hit");
+			return Ok(respond(cached, known_version));
+		}
+
+		let versions = self.inner.get_versions(&store_id, vec![request.key.clone()]).await?;
+		match versions.get(&request.key) {
+			Some(version) if *version == cached.version => {
+				record_cache_lookup("hit");
+				Ok(respond(cached, known_version))
+			}
+			None => {
+				record_cache_lookup("stale");
+				self.lru.lock().unwrap().remove(&store_id, &request.key);
+				Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Key not found"))
+			}
+			Some(_) => {
+				record_cache_lookup("stale");
+				let response = self.inner.get(request).await?;
+				if let Some(value) = response.value.as_ref().filter(|_| !response.not_modified) {
+					self.cache_reads(&store_id, [value.clone()], read_start);
+				}
+				Ok(response)
+			}
+		}
+	}
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+		self.inner.get_versions(store_id, keys).await
+	}
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+		if request.keys.len() > MAX_GET_OBJECTS_KEYS {
+			return self.inner.get_objects(request).await;
+		}
+		let store_id = request.store_id.clone();
+		let read_start = self.read_start();
+		let mut keys: Vec<&str> = Vec::with_capacity(request.keys.len());
+		for key in &request.keys {
+			if !keys.contains(&key.as_str()) {
+				keys.push(key);
+			}
+		}
+		let mut values = HashMap::new();
+		let mut uncached_keys = Vec::new();
+		for key in &keys {
+			match self.lookup(&store_id, key) {
+				Some(value) => {
+					values.insert(key.to_string(), value);
+				}
+				None => {
+					record_cache_lookup("miss");
+					uncached_keys.push(key.to_string());
+				}
+			}
+		}
+		// Cached values are validated in a single read of their versions.
+		if self.config.validate && !values.is_empty() {
+			let versions = self.inner.get_versions(&store_id, values.keys().cloned().collect()).await?;
+			values.retain(|key, value| {
+				if versions.get(key) == Some(&value.version) {
+					return true;
+				}
+				record_cache_lookup("stale");
+				match versions.get(key) {
+					Some(_) => uncached_keys.push(key.clone()),
+					None => self.lru.lock().unwrap().remove(&store_id, key),
+				}
+				false
+			});
+		}
+		for _ in 0..values.len() {
+			record_cache_lookup("hit");
+		}
+		if !uncached_keys.is_empty() {
+			let fetched = self.inner.get_objects(GetObjectsRequest { store_id: store_id.clone(), keys: uncached_keys }).await?.values;
+			self.cache_reads(&store_id, fetched.iter().cloned(), read_start);
+			values.extend(fetched.into_iter().map(|value| (value.key.clone(), value)));
+		}
+
+		let mut response = GetObjectsResponse::default();
+		for key in keys {
+			match values.remove(key) {
+				Some(value) => response.values.push(value),
+				None => response.missing_keys.push(key.to_string()),
+			}
+		}
+		Ok(response)
+	}
+	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+		let store_id = request.store_id.clone();
+		let keys: Vec<String> = request.transaction_items.iter().chain(&request.delete_items).map(|kv| kv.key.clone()).collect();
+		// Reads do not return the expiry of values, so values written with one are not cached.
+		let written: HashMap<String, KeyValue> = request.transaction_items.iter()
+			.filter(|kv| !request.item_expiries.iter().any(|item_expiry| item_expiry.key == kv.key))
+			.map(|kv| (kv.key.clone(), KeyValue { version: next_version(kv.version), ..kv.clone() }))
+			.collect();
+
+		let result = self.inner.put(request).await;
+		// A failed write may still have been applied, its values are not cached.
+		let written = if result.is_ok() { written } else { HashMap::new() };
+		self.record_writes(&store_id, &keys, written);
+		result
+	}
+	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
+		let store_id = request.store_id.clone();
+		let keys: Vec<String> = request.key_value.iter().map(|kv| kv.key.clone()).collect();
+		let result = self.inner.delete(request).await;
+		self.record_writes(&store_id, &keys, HashMap::new());
+		result
+	}
+	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+		self.inner.list_key_versions(request).await
+	}
+	async fn list_object_versions(&self, request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+		self.inner.list_object_versions(request).await
+	}
+	async fn get_object_version(&self, request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+		self.inner.get_object_version(request).await
+	}
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+		self.inner.global_version(store_id).await
+	}
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+		let keys: Vec<String> = key_values.iter().map(|kv| kv.key.clone()).collect();
+		let result = self.inner.backfill(store_id.clone(), key_values, item_expiries).await;
+		self.record_writes(&store_id, &keys, HashMap::new());
+		result
+	}
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+		self.inner.backfill_global_version(store_id, global_version).await
+	}
+	async fn purge_expired(&self) -> std::io::Result<usize> {
+		self.inner.purge_expired().await
+	}
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+		self.inner.list_stores(request).await
+	}
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+		self.inner.store_metadata(store_id).await
+	}
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+		let result = self.inner.delete_store(store_id).await;
+		self.lru.lock().unwrap().remove_store(store_id);
+		result
+	}
+	async fn check_health(&self) -> std::io::Result<HealthStatus> {
+		self.inner.check_health().await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+	use crate::testing::{MemoryStore, delete, get, kv, put};
+
+	fn caching_store(validate: bool) -> (Arc<MemoryStore>, CachingStore) {
+		let inner = Arc::new(MemoryStore::default());
+		let config = CacheConfig { max_bytes: 64 * 1024, validate, max_age: Duration::from_secs(60) };
+		(Arc::clone(&inner), CachingStore::new(inner, config))
+	}
+
+	fn get_objects_request(keys: &[&str]) -> GetObjectsRequest {
+		GetObjectsRequest { store_id: "store".to_string(), keys: keys.iter().map(|key| key.to_string()).collect() }
+	}
+
+	#[tokio::test]
+	async fn validates_cached_values_by_version() {
+		let (inner, store) = caching_store(true);
+		put(&store, "store", "a", 0, b"a").await.unwrap();
+		put(&store, "store", "b", 0, b"b").await.unwrap();
+
+		assert_eq!(get(&store, "store", "a").await.unwrap(), kv("a", 1, b"a"));
+		let response = store.get_objects(get_objects_request(&["a", "b"])).await.unwrap();
+		assert_eq!(response.values.len(), 2);
+		assert_eq!(inner.value_reads(), 0);
+
+		// Written by another instance.
+		put(inner.as_ref(), "store", "a", 1, b"c").await.unwrap();
+		delete(inner.as_ref(), "store", "b", 1).await.unwrap();
+		let response = store.get_objects(get_objects_request(&["a", "b"])).await.unwrap();
+		assert_eq!((response.values, response.missing_keys), (vec![kv("a", 2, b"c")], vec!["b".to_string()]));
+		assert_eq!(inner.value_reads(), 1);
+		assert_eq!(get(&store, "store", "a").await.unwrap(), kv("a", 2, b"c"));
+		assert_eq!(get(&store, "store", "b").await.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+		assert_eq!(inner.value_reads(), 2);
+	}
+
+	#[tokio::test]
+	async fn serves_unvalidated_values_until_written() {
+		let (inner, store) = caching_store(false);
+		put(inner.as_ref(), "store", "k", 0, b"a").await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 1, b"a"));
+		put(inner.as_ref(), "store", "k", 1, b"b").await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 1, b"a"));
+
+		delete(&store, "store", "k", 2).await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+		assert_eq!(inner.value_reads(), 2);
+	}
+
+	#[test]
+	fn does_not_cache_values_read_before_a_write_of_their_key() {
+		let mut lru = Lru::new(64 * 1024);
+		let read_start = lru.writes;
+		lru.record_write("store", "a", None);
+		lru.cache_read("store", kv("a", 1, b"stale"), read_start);
+		assert!(lru.get("store", "a").is_none());
+		// Writes of other keys do not prevent caching.
+		lru.cache_read("store", kv("b", 1, b"b"), read_start);
+		assert_eq!(lru.get("store", "b").unwrap().0, kv("b", 1, b"b"));
+		lru.cache_read("store", kv("a", 2, b"fresh"), lru.writes);
+		assert_eq!(lru.get("store", "a").unwrap().0, kv("a", 2, b"fresh"));
+	}
+
+	#[test]
+	fn does_not_cache_values_read_before_an_evicted_write() {
+		let mut lru = Lru::new(10 * ENTRY_OVERHEAD);
+		let read_start = lru.writes;
+		lru.record_write("store", "a", None);
+		for index in 0..10 {
+			lru.cache_read("store", kv(&index.to_string(), 1, b""), lru.writes);
+		}
+		assert!(!lru.entries.contains_key(&("store".to_string(), "a".to_string())));
+		lru.cache_read("store", kv("a", 1, b"stale"), read_start);
+		assert!(lru.get("store", "a").is_none());
+		lru.cache_read("store", kv("a", 2, b"fresh"), lru.writes);
+		assert!(lru.get("store", "a").is_some());
+	}
+}
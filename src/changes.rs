@@ -0,0 +1,514 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use axum::async_trait;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, Response};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Extension, Router, middleware};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, watch};
+
+use crate::api::{Encoding, authorize, build_error_response};
+use crate::metrics::track_http_metrics;
+use crate::store::{HealthStatus, KvStore, next_version, validate_store_id};
+use crate::telemetry::{loggable_store_id, record_store_id, trace_request};
+use crate::tls::ClientIdentity;
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, ErrorCode, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, ItemExpiry, KeyValue, GetObjectVersionRequest, GetObjectVersionResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, PutObjectRequest, PutObjectResponse, StoreMetadata};
+
+// Number of live events of a store buffered per subscriber before it is considered lagging and reset.
+const SUBSCRIBER_BUFFER_SIZE: usize = 1024;
+
+/// Kind of write reported by a `ChangeEvent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ChangeOp {
+	Put,
+	Delete,
+}
+
+/// A write to a key, published on the `ChangeBus` once applied.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEvent {
+	#[serde(skip)]
+	pub store_id: String,
+	pub key: String,
+	/// Version written, or version of the value deleted.
+	#[serde(with = "crate::json::int64")]
+	pub version: i64,
+	pub op: ChangeOp,
+	/// `global_version` of the store once the write was applied. Deletes do not increment it.
+	#[serde(with = "crate::json::int64")]
+	pub global_version: i64,
+}
+
+#[derive(Clone)]
+enum BusMessage {
+	Change(Arc<ChangeEvent>),
+	/// Changes to the store may have been missed. Subscribers must reconcile with `ListKeyVersions`.
+	Reset,
+}
+
+/// In-process bus of writes fanned out to subscribers, fed either by a `NotifyingStore` with the
+/// writes made through this server, or by the DynamoDB stream of the table with those made through
+/// any server.
+///
+/// Events are sent on a channel per store, so that subscribers only fall behind when the writes to
+/// their own store outpace them. The last `capacity` events of all stores are kept so that
+/// subscribers can resume from a `global_version` they have seen, including the events tagged with
+/// it. Events are hints to refetch a key rather than a log: a subscriber may receive an event more
+/// than once.
+pub struct ChangeBus {
+	history: Mutex<History>,
+}
+
+struct History {
+	/// Channels of the stores with subscribers, removed once those are gone.
+	channels: HashMap<String, broadcast::Sender<BusMessage>>,
+	/// Highest `global_version` published for each store.
+	latest: HashMap<String, i64>,
+	events: VecDeque<Arc<ChangeEvent>>,
+	capacity: usize,
+	/// Lowest `global_version` subscribers of a store may resume from: events tagged with it or
+	/// above are all buffered, those tagged below may have been evicted, or applied before this
+	/// server first saw a write to the store.
+	horizons: HashMap<String, i64>,
+}
+
+impl History {
+	fn push(&mut self, event: Arc<ChangeEvent>) {
+		let latest = self.latest.entry(event.store_id.clone()).or_insert(event.global_version);
+		*latest = (*latest).max(event.global_version);
+		self.horizons.entry(event.store_id.clone()).or_insert_with(|| initial_horizon(&event));
+		if self.events.len() == self.capacity {
+			if let Some(evicted) = self.events.pop_front() {
+				let horizon = self.horizons.entry(evicted.store_id.clone()).or_insert(0);
+				*horizon = (*horizon).max(evicted.global_version + 1);
+			}
+		}
+		if self.capacity > 0 {
+			self.events.push_back(Arc::clone(&event));
+		}
+		let store_id = event.store_id.clone();
+		self.send(&store_id, BusMessage::Change(event));
+	}
+
+	fn send(&mut self, store_id: &str, message: BusMessage) {
+		if let Some(sender) = self.channels.get(store_id) {
+			if sender.send(message).is_err() {
+				self.channels.remove(store_id);
+			}
+		}
+	}
+
+	fn forget(&mut self, store_id: &str) {
+		self.events.retain(|event| event.store_id != store_id);
+		self.horizons.remove(store_id);
+		self.latest.remove(store_id);
+	}
+}
+
+/// Lowest `global_version` a subscriber may resume from when `event` is the first one seen for its
+/// store. Puts are tagged with the global version they created, so the events of their write are
+/// all published after them, deletes with the one they were applied at.
+fn initial_horizon(event: &ChangeEvent) -> i64 {
+	match event.op {
+		ChangeOp::Put => event.global_version,
+		ChangeOp::Delete => event.global_version + 1,
+	}
+}
+
+/// Events of a store to send to a subscriber, followed by live ones from `receiver`.
+struct Subscription {
+	/// `None` if the events since the requested global version are no longer buffered.
+	replay: Option<Vec<Arc<ChangeEvent>>>,
+	receiver: broadcast::Receiver<BusMessage>,
+}
+
+impl ChangeBus {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			history: Mutex::new(History {
+				channels: HashMap::new(),
+				latest: HashMap::new(),
+				events: VecDeque::with_capacity(capacity),
+				capacity,
+				horizons: HashMap::new(),
+			}),
+		}
+	}
+
+	pub fn publish(&self, event: ChangeEvent) {
+		// Sent under the lock, so that subscribers see each event either replayed or live.
+		self.history.lock().unwrap().push(Arc::new(event));
+	}
+
+	/// Highest `global_version` published for `store_id` since it was last reset, if any.
+	pub fn latest_global_version(&self, store_id: &str) -> Option<i64> {
+		self.history.lock().unwrap().latest.get(store_id).copied()
+	}
+
+	/// Tells subscribers of `store_id` that they may have missed changes, and forgets its events.
+	pub fn reset(&self, store_id: &str) {
+		let mut history = self.history.lock().unwrap();
+		history.forget(store_id);
+		history.send(store_id, BusMessage::Reset);
+	}
+
+	/// Tells all subscribers that they may have missed changes, and forgets all events.
+	pub fn reset_all(&self) {
+		let mut history = self.history.lock().unwrap();
+		history.events.clear();
+		history.horizons.clear();
+		history.latest.clear();
+		history.channels.retain(|_, sender| sender.send(BusMessage::Reset).is_ok());
+	}
+
+	/// Returns the events of `store_id` buffered for subscribers resuming, in the order published.
+	#[cfg(test)]
+	pub fn buffered_events(&self, store_id: &str) -> Vec<Arc<ChangeEvent>> {
+		self.history.lock().unwrap().events.iter().filter(|event| event.store_id == store_id).cloned().collect()
+	}
+
+	fn subscribe(&self, store_id: &str, from_global_version: Option<i64>) -> Subscription {
+		let mut history = self.history.lock().unwrap();
+		let replay = match from_global_version {
+			None => Some(Vec::new()),
+			// Events tagged with `from` itself are replayed, as all the writes of a transaction share
+			// its global version and the subscriber may have missed some of them.
+			Some(from) if history.horizons.get(store_id).is_some_and(|horizon| from >= *horizon) => Some(
+				history.events.iter()
+					.filter(|event| event.store_id == store_id && event.global_version >= from)
+					.cloned()
+					.collect(),
+			),
+			Some(_) => None,
+		};
+		let receiver = match history.channels.get(store_id) {
+			Some(sender) => sender.subscribe(),
+			None => {
+				let (sender, receiver) = broadcast::channel(SUBSCRIBER_BUFFER_SIZE);
+				history.channels.insert(store_id.to_string(), sender);
+				receiver
+			}
+		};
+		Subscription { replay, receiver }
+	}
+}
+
+/// A `KvStore` publishing the writes applied to `inner` on a `ChangeBus`.
+pub struct NotifyingStore {
+	inner: Arc<dyn KvStore>,
+	bus: Arc<ChangeBus>,
+}
+
+impl NotifyingStore {
+	pub fn new(inner: Arc<dyn KvStore>, bus: Arc<ChangeBus>) -> Self {
+		Self { inner, bus }
+	}
+
+	/// Global version a delete from `store_id` was applied at. Deletes leave it unchanged, so it is
+	/// that of the last put published, only read from `inner` if none was since the last reset. It
+	/// may then be ahead if other writes completed meanwhile, which only causes their events to be
+	/// replayed again.
+	async fn global_version_of_delete(&self, store_id: &str) -> Option<i64> {
+		if let Some(global_version) = self.bus.latest_global_version(store_id) {
+			return Some(global_version);
+		}
+		match self.inner.global_version(store_id).await {
+			Ok(global_version) => Some(global_version),
+			Err(err) => {
+				tracing::warn!(store_id = loggable_store_id(store_id), error = %err, "Failed to read global version, resetting subscribers");
+				self.bus.reset(store_id);
+				None
+			}
+		}
+	}
+}
+
+#[async_trait]
+impl KvStore for NotifyingStore {
+	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
+		self.inner.get(request).await
+	}
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+		self.inner.get_objects(request).await
+	}
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+		self.inner.get_versions(store_id, keys).await
+	}
+	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+		let response = self.inner.put(request.clone()).await?;
+
+		let Some(global_version) = response.global_version.or(request.global_version.map(|global_version| global_version + 1)) else {
+			tracing::warn!(store_id = loggable_store_id(&request.store_id), "Put returned no global version, resetting subscribers");
+			self.bus.reset(&request.store_id);
+			return Ok(response);
+		};
+		let puts = request.transaction_items.into_iter().map(|kv| (kv.key, next_version(kv.version), ChangeOp::Put));
+		let deletes = request.delete_items.into_iter().map(|kv| (kv.key, kv.version, ChangeOp::Delete));
+		for (key, version, op) in puts.chain(deletes) {
+			self.bus.publish(ChangeEvent { store_id: request.store_id.clone(), key, version, op, global_version });
+		}
+		Ok(response)
+	}
+	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
+		let response = self.inner.delete(request.clone()).await?;
+
+		if let Some(kv) = request.key_value {
+			if let Some(global_version) = self.global_version_of_delete(&request.store_id).await {
+				self.bus.publish(ChangeEvent { store_id: request.store_id, key: kv.key, version: kv.version, op: ChangeOp::Delete, global_version });
+			}
+		}
+		Ok(response)
+	}
+	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+		self.inner.list_key_versions(request).await
+	}
+	async fn list_object_versions(&self, request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+		self.inner.list_object_versions(request).await
+	}
+	async fn get_object_version(&self, request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+		self.inner.get_object_version(request).await
+	}
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+		self.inner.global_version(store_id).await
+	}
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+		self.inner.backfill(store_id, key_values, item_expiries).await
+	}
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+		self.inner.backfill_global_version(store_id, global_version).await
+	}
+	async fn purge_expired(&self) -> std::io::Result<usize> {
+		self.inner.purge_expired().await
+	}
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+		self.inner.list_stores(request).await
+	}
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+		self.inner.store_metadata(store_id).await
+	}
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+		let result = self.inner.delete_store(store_id).await;
+		// Deletes are not published one by one, subscribers reconcile with the emptied store instead.
+		self.bus.reset(store_id);
+		result
+	}
+	async fn check_health(&self) -> std::io::Result<HealthStatus> {
+		self.inner.check_health().await
+	}
+}
+
+#[derive(Clone)]
+struct SubscribeState {
+	bus: Arc<ChangeBus>,
+	shutdown: watch::Receiver<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SubscribeParams {
+	#[serde(alias = "store_id")]
+	store_id: String,
+	#[serde(alias = "from_global_version")]
+	from_global_version: Option<i64>,
+}
+
+/// Route streaming the changes to a store as server-sent events:
+/// `GET /subscribe?storeId=<store_id>[&fromGlobalVersion=<global_version>]`.
+///
+/// Each write is sent as a `change` event whose data is a JSON `ChangeEvent` and whose id is its
+/// `global_version`. When resuming from a global version, given as `fromGlobalVersion` or as the
+/// `Last-Event-ID` header sent by reconnecting `EventSource`s, buffered events since then are sent
+/// first. Those tagged with that global version are sent again, since the writes of a transaction
+/// share it and the subscriber may have disconnected before receiving all of them, so clients may
+/// receive some events twice. If they are no longer buffered, or if the subscriber falls behind, a `reset` event is sent
+/// instead, after which clients should reconcile with `ListKeyVersions`. Streams end on shutdown.
+pub fn router(bus: Arc<ChangeBus>, shutdown: watch::Receiver<bool>) -> Router {
+	Router::new()
+		.route("/subscribe", get(subscribe))
+		.route_layer(middleware::from_fn(track_http_metrics))
+		.route_layer(middleware::from_fn(trace_request))
+		.with_state(SubscribeState { bus, shutdown })
+}
+
+async fn subscribe(
+	State(state): State<SubscribeState>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	params: Result<Query<SubscribeParams>, QueryRejection>,
+) -> Response<axum::body::BoxBody> {
+	let params = match params {
+		Ok(Query(params)) => params,
+		Err(rejection) => {
+			let message = format!("Invalid subscription: {}", rejection.body_text());
+			return build_error_response(Encoding::Json, ErrorCode::InvalidRequestException, &message).into_response();
+		}
+	};
+	record_store_id(&params.store_id);
+	if let Err(err) = validate_store_id(&params.store_id) {
+		return build_error_response(Encoding::Json, ErrorCode::InvalidRequestException, &err.to_string()).into_response();
+	}
+	if let Some(rsp) = authorize(Encoding::Json, identity, &params.store_id) {
+		return rsp.into_response();
+	}
+
+	let last_event_id = headers.get("last-event-id").and_then(|value| value.to_str().ok()).and_then(|value| value.parse().ok());
+	let subscription = state.bus.subscribe(&params.store_id, params.from_global_version.or(last_event_id));
+	let events = subscription_events(subscription).take_until(crate::shutdown_requested(state.shutdown));
+	Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+fn subscription_events(subscription: Subscription) -> impl Stream<Item = Result<Event, Infallible>> {
+	let Subscription { replay, receiver } = subscription;
+	let replay = match replay {
+		Some(events) => events.iter().map(|event| change_event(event)).collect(),
+		None => vec![reset_event()],
+	};
+	let live = stream::unfold(Some(receiver), |receiver| async move {
+		let mut receiver = receiver?;
+		match receiver.recv().await {
+			Ok(BusMessage::Change(event)) => Some((change_event(&event), Some(receiver))),
+			Ok(BusMessage::Reset) => Some((reset_event(), Some(receiver))),
+			// Events were dropped, the subscriber has to reconcile and subscribe again.
+			Err(broadcast::error::RecvError::Lagged(_)) => Some((reset_event(), None)),
+			Err(broadcast::error::RecvError::Closed) => None,
+		}
+	});
+	stream::iter(replay).chain(live).map(Ok)
+}
+
+fn change_event(event: &ChangeEvent) -> Event {
+	Event::default()
+		.event("change")
+		.id(event.global_version.to_string())
+		.data(serde_json::to_string(event).unwrap())
+}
+
+fn reset_event() -> Event {
+	Event::default().event("reset").data("")
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::body::{Body, HttpBody};
+	use axum::http::Request;
+	use tower::ServiceExt;
+
+	use super::*;
+	use crate::testing::{MemoryStore, delete, kv, put};
+
+	fn change(store_id: &str, key: &str, global_version: i64) -> ChangeEvent {
+		ChangeEvent { store_id: store_id.to_string(), key: key.to_string(), version: 1, op: ChangeOp::Put, global_version }
+	}
+
+	fn received(receiver: &mut broadcast::Receiver<BusMessage>) -> Vec<(String, ChangeOp, i64)> {
+		let mut events = Vec::new();
+		while let Ok(BusMessage::Change(event)) = receiver.try_recv() {
+			events.push((event.key.clone(), event.op, event.global_version));
+		}
+		events
+	}
+
+	#[test]
+	fn busy_stores_do_not_make_subscribers_of_others_lag() {
+		let bus = ChangeBus::new(16);
+		let mut busy = bus.subscribe("busy", None).receiver;
+		let mut quiet = bus.subscribe("quiet", None).receiver;
+		for global_version in 1..=SUBSCRIBER_BUFFER_SIZE as i64 + 1 {
+			bus.publish(change("busy", "k", global_version));
+		}
+		bus.publish(change("quiet", "k", 1));
+
+		assert!(matches!(busy.try_recv(), Err(broadcast::error::TryRecvError::Lagged(1))));
+		assert_eq!(received(&mut quiet), vec![("k".to_string(), ChangeOp::Put, 1)]);
+
+		bus.reset("busy");
+		assert!(quiet.try_recv().is_err());
+		drop(busy);
+		bus.publish(change("busy", "k", 1));
+		assert!(!bus.history.lock().unwrap().channels.contains_key("busy"));
+	}
+
+	#[tokio::test]
+	async fn tags_writes_without_reading_global_version() {
+		let inner = Arc::new(MemoryStore::default());
+		let bus = Arc::new(ChangeBus::new(16));
+		let store = NotifyingStore::new(Arc::clone(&inner) as Arc<dyn KvStore>, Arc::clone(&bus));
+		let mut receiver = bus.subscribe("store", None).receiver;
+
+		put(&store, "store", "a", 0, b"a").await.unwrap();
+		put(&store, "store", "b", 0, b"b").await.unwrap();
+		delete(&store, "store", "a", 1).await.unwrap();
+		assert_eq!(received(&mut receiver), vec![
+			("a".to_string(), ChangeOp::Put, 1),
+			("b".to_string(), ChangeOp::Put, 2),
+			("a".to_string(), ChangeOp::Delete, 2),
+		]);
+		assert_eq!(inner.global_version_reads(), 0);
+
+		// Once reset, the global version deletes were applied at is read again.
+		bus.reset("store");
+		assert!(matches!(receiver.try_recv(), Ok(BusMessage::Reset)));
+		delete(&store, "store", "b", 1).await.unwrap();
+		assert_eq!(received(&mut receiver), vec![("b".to_string(), ChangeOp::Delete, 2)]);
+		assert_eq!(inner.global_version_reads(), 1);
+	}
+
+	/// Reads the next `count` events of an SSE response body, as their ids and data.
+	async fn read_events(body: &mut axum::body::BoxBody, count: usize) -> Vec<(String, serde_json::Value)> {
+		let mut text = String::new();
+		while text.matches("\n\n").count() < count {
+			let chunk = body.data().await.unwrap().unwrap();
+			text.push_str(std::str::from_utf8(&chunk).unwrap());
+		}
+		text.split("\n\n").take(count).map(|event| {
+			let field = |name: &str| event.lines().find_map(|line| line.strip_prefix(name)).unwrap().trim().to_string();
+			(field("id:"), serde_json::from_str(&field("data:")).unwrap())
+		}).collect()
+	}
+
+	async fn subscribe_request(router: &Router, last_event_id: Option<&str>) -> axum::body::BoxBody {
+		let mut request = Request::get("/subscribe?storeId=store");
+		if let Some(last_event_id) = last_event_id {
+			request = request.header("last-event-id", last_event_id);
+		}
+		router.clone().oneshot(request.body(Body::empty()).unwrap()).await.unwrap().into_body()
+	}
+
+	#[tokio::test]
+	async fn resumes_transactions_interrupted_by_disconnection() {
+		let bus = Arc::new(ChangeBus::new(16));
+		let store = NotifyingStore::new(Arc::new(MemoryStore::default()), Arc::clone(&bus));
+		let (_shutdown_sender, shutdown) = watch::channel(false);
+		let router = router(Arc::clone(&bus), shutdown);
+
+		put(&store, "store", "a", 0, b"a").await.unwrap();
+		let mut body = subscribe_request(&router, None).await;
+		store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("b", 0, b"b"), kv("c", 0, b"c"), kv("d", 0, b"d")],
+			..Default::default()
+		}).await.unwrap();
+		// Disconnects after the first event of the transaction.
+		let events = read_events(&mut body, 1).await;
+		assert_eq!(events[0].0, "2");
+		assert_eq!(events[0].1["key"], "b");
+		drop(body);
+
+		let mut body = subscribe_request(&router, Some(&events[0].0)).await;
+		let keys: Vec<_> = read_events(&mut body, 3).await.into_iter().map(|(id, data)| (id, data["key"].as_str().unwrap().to_string())).collect();
+		assert_eq!(keys, vec![
+			("2".to_string(), "b".to_string()),
+			("2".to_string(), "c".to_string()),
+			("2".to_string(), "d".to_string()),
+		]);
+	}
+}
@@ -0,0 +1,388 @@
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Server configuration, read from `VSS_*` environment variables.
+pub struct Config {
+	/// Address the HTTP server listens on (`VSS_BIND_ADDRESS`).
+	pub bind_address: SocketAddr,
+	/// Address the gRPC `VssService` listens on (`VSS_GRPC_BIND_ADDRESS`). If unset, gRPC requests
+	/// are served on `bind_address` alongside HTTP requests.
+	pub grpc_bind_address: Option<SocketAddr>,
+	/// Present when the server terminates TLS itself.
+	pub tls: Option<TlsConfig>,
+	/// DynamoDB endpoint override (`VSS_DYNAMODB_ENDPOINT`). Set to an empty string to use the
+	/// default AWS endpoint resolution.
+	pub dynamodb_endpoint: Option<String>,
+	/// Name of the DynamoDB table holding VSS items (`VSS_DYNAMODB_TABLE`).
+	pub dynamodb_table: String,
+	/// How the DynamoDB tables are checked at startup (`VSS_DYNAMODB_TABLE_SETUP`).
+	pub dynamodb_table_setup: TableSetup,
+	/// Provisioned `(read, write)` capacity units for tables created at startup
+	/// (`VSS_DYNAMODB_READ_CAPACITY`, `VSS_DYNAMODB_WRITE_CAPACITY`). On-demand billing is used if unset.
+	pub dynamodb_throughput: Option<(i64, i64)>,
+	/// Present when online migration to another backend is enabled.
+	pub migration: Option<MigrationConfig>,
+	/// Present when previous versions of keys are retained.
+	pub history: Option<HistoryConfig>,
+	/// Present when deleted items are retained for recovery before being purged.
+	pub soft_delete: Option<SoftDeleteConfig>,
+	/// How often expired items not removed by the backend on its own are purged, `None` if never
+	/// (`VSS_EXPIRY_SWEEP_INTERVAL_SECS`, unset or 0 to disable). Each sweep scans the whole table.
+	pub expiry_sweep_interval: Option<Duration>,
+	/// Bearer token required by the admin API listing, inspecting and deleting stores
+	/// (`VSS_ADMIN_TOKEN`). The admin API is disabled if unset.
+	pub admin_token: Option<String>,
+	/// Present when puts and deletes are recorded in an audit log.
+	pub audit_log: Option<AuditLogConfig>,
+	/// Present when values are cached in memory.
+	pub cache: Option<CacheConfig>,
+	pub telemetry: TelemetryConfig,
+	pub limits: RequestLimits,
+	/// Minimum size in bytes of response bodies compressed for clients accepting gzip, brotli or
+	/// zstd (`VSS_COMPRESSION_MIN_SIZE`).
+	pub compression_min_size: u16,
+	/// Number of recent writes kept for change subscribers to resume from (`VSS_CHANGE_BUFFER_SIZE`).
+	pub change_buffer_size: usize,
+	/// Source of the writes sent to change subscribers (`VSS_CHANGE_FEED`).
+	pub change_feed: ChangeFeed,
+	/// Time allowed for the backend check of `/health/ready` (`VSS_READINESS_TIMEOUT_MS`).
+	pub readiness_timeout: Duration,
+	/// Time allowed for in-flight requests to complete once a shutdown signal is received
+	/// (`VSS_SHUTDOWN_TIMEOUT_SECS`).
+	pub shutdown_timeout: Duration,
+}
+
+/// TLS configuration, enabled by setting `VSS_TLS_CERT_FILE` and `VSS_TLS_KEY_FILE`.
+#[derive(Clone)]
+pub struct TlsConfig {
+	/// PEM file holding the server certificate chain (`VSS_TLS_CERT_FILE`).
+	pub cert_file: PathBuf,
+	/// PEM file holding the server private key (`VSS_TLS_KEY_FILE`).
+	pub key_file: PathBuf,
+	/// PEM file holding the CA certificates client certificates are verified against
+	/// (`VSS_TLS_CLIENT_CA_FILE`). Setting it enables mutual TLS, rejecting clients without a
+	/// valid certificate.
+	pub client_ca_file: Option<PathBuf>,
+	/// File mapping client certificate subjects to the `store_id`s they may access
+	/// (`VSS_TLS_CLIENT_ACCESS_FILE`). Requires mutual TLS. Any authenticated client may access all
+	/// stores if unset.
+	pub client_access_file: Option<PathBuf>,
+	/// How often the files above are checked for changes to reload (`VSS_TLS_RELOAD_INTERVAL_SECS`).
+	pub reload_interval: Duration,
+}
+
+/// Limits protecting the server from oversized or slow requests.
+pub struct RequestLimits {
+	/// Maximum size in bytes of request bodies (`VSS_MAX_BODY_SIZE`), except for `PutObjects`.
+	pub max_body_size: usize,
+	/// Maximum size in bytes of `PutObjects` request bodies (`VSS_MAX_PUT_BODY_SIZE`).
+	pub max_put_body_size: usize,
+	/// Time allowed for a data-plane request, HTTP or gRPC, to be answered (`VSS_REQUEST_TIMEOUT_MS`).
+	pub request_timeout: Duration,
+}
+
+/// Logging and tracing configuration.
+pub struct TelemetryConfig {
+	/// Format of logs written to stdout (`VSS_LOG_FORMAT`), `json` (default) or `text`.
+	pub log_format: LogFormat,
+	/// How `store_id`s appear in logs and spans (`VSS_LOG_STORE_ID`), `plain`, `hashed` (default)
+	/// or `redacted`.
+	pub store_id_logging: StoreIdLogging,
+	/// OTLP/gRPC endpoint of an OpenTelemetry collector to export spans to (`VSS_OTLP_ENDPOINT`).
+	/// Spans are not exported if unset.
+	pub otlp_endpoint: Option<String>,
+	/// Value of the `service.name` resource attribute of exported spans (`VSS_OTLP_SERVICE_NAME`).
+	pub service_name: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+	Json,
+	Text,
+}
+
+impl std::str::FromStr for LogFormat {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"json" => Ok(LogFormat::Json),
+			"text" => Ok(LogFormat::Text),
+			_ => Err(()),
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StoreIdLogging {
+	Plain,
+	Hashed,
+	Redacted,
+}
+
+impl std::str::FromStr for StoreIdLogging {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"plain" => Ok(StoreIdLogging::Plain),
+			"hashed" => Ok(StoreIdLogging::Hashed),
+			"redacted" => Ok(StoreIdLogging::Redacted),
+			_ => Err(()),
+		}
+	}
+}
+
+/// Startup check of the DynamoDB tables used by the server.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TableSetup {
+	/// Tables are assumed to exist (`none`).
+	None,
+	/// Tables must exist with the expected key schema, startup fails otherwise (`verify`).
+	Verify,
+	/// Like `Verify`, but missing tables are created (`create`).
+	Create,
+}
+
+impl std::str::FromStr for TableSetup {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"none" => Ok(TableSetup::None),
+			"verify" => Ok(TableSetup::Verify),
+			"create" => Ok(TableSetup::Create),
+			_ => Err(()),
+		}
+	}
+}
+
+/// Retention of previous versions of keys, enabled by setting `VSS_HISTORY_MAX_VERSIONS`,
+/// `VSS_HISTORY_MAX_AGE_SECS` or both. Versions are retained until either limit is exceeded.
+#[derive(Clone)]
+pub struct HistoryConfig {
+	/// Number of previous versions retained per key (`VSS_HISTORY_MAX_VERSIONS`).
+	pub max_versions: Option<usize>,
+	/// How long previous versions are retained once superseded (`VSS_HISTORY_MAX_AGE_SECS`).
+	pub max_age: Option<Duration>,
+	/// How often previous versions past `max_age` are pruned, including those of keys not written
+	/// to again (`VSS_HISTORY_PRUNE_INTERVAL_SECS`).
+	pub prune_interval: Duration,
+}
+
+/// Soft deletion of items, enabled by setting `VSS_SOFT_DELETE_RETENTION_SECS`.
+#[derive(Clone)]
+pub struct SoftDeleteConfig {
+	/// How long deleted items can be restored before being purged (`VSS_SOFT_DELETE_RETENTION_SECS`).
+	pub retention: Duration,
+	/// How often deleted items past their retention are purged (`VSS_SOFT_DELETE_REAP_INTERVAL_SECS`).
+	pub reap_interval: Duration,
+}
+
+/// In-memory cache of values, enabled by setting `VSS_CACHE_MAX_BYTES`.
+#[derive(Clone)]
+pub struct CacheConfig {
+	/// Size of the values and keys cached, beyond which the least recently used are evicted
+	/// (`VSS_CACHE_MAX_BYTES`).
+	pub max_bytes: usize,
+	/// Whether the version of cached values is checked against the backend before serving them
+	/// (`VSS_CACHE_VALIDATE`, default `true`). Must be set if other instances write to the backend.
+	pub validate: bool,
+	/// How long values are served without checking them, when not validated
+	/// (`VSS_CACHE_MAX_AGE_SECS`). Bounds how long values written with an expiry are served after
+	/// it, since reads do not return the expiry of values.
+	pub max_age: Duration,
+}
+
+/// Destination of the audit log, set with either `VSS_AUDIT_LOG_FILE` or `VSS_AUDIT_LOG_TABLE`.
+#[derive(Clone)]
+pub enum AuditLogConfig {
+	/// File the entries are appended to, which must not be shared with other instances.
+	File(PathBuf),
+	/// DynamoDB table the entries are appended to, which may be shared with other instances. It is
+	/// checked or created at startup along with the other tables, as per `VSS_DYNAMODB_TABLE_SETUP`.
+	DynamoDb {
+		table_name: String,
+		/// Id of the chain of entries of this instance (`VSS_AUDIT_CHAIN_ID`, the `HOSTNAME` by
+		/// default). Instances appending to the same chain compete to append each entry.
+		chain_id: String,
+	},
+}
+
+/// Source of change events.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ChangeFeed {
+	/// Writes made through this server (`local`).
+	Local,
+	/// The stream of the DynamoDB table, with writes made through any server (`dynamodb-streams`).
+	/// The table must have a stream with new and old images, tables created at startup are given one.
+	DynamoDbStreams,
+}
+
+impl std::str::FromStr for ChangeFeed {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"local" => Ok(ChangeFeed::Local),
+			"dynamodb-streams" => Ok(ChangeFeed::DynamoDbStreams),
+			_ => Err(()),
+		}
+	}
+}
+
+/// Configuration for migrating data to a new backend while serving traffic.
+///
+/// Migration is enabled by setting `VSS_MIGRATION_TARGET_TABLE`. The target is always a DynamoDB
+/// table: other backends, such as Postgres, are not supported as migration targets.
+pub struct MigrationConfig {
+	/// DynamoDB endpoint of the target backend (`VSS_MIGRATION_TARGET_ENDPOINT`), defaults to the
+	/// source endpoint.
+	pub target_dynamodb_endpoint: Option<String>,
+	/// Name of the target DynamoDB table (`VSS_MIGRATION_TARGET_TABLE`).
+	pub target_table: String,
+	/// Comma-separated list of `store_id`s to backfill and reconcile at startup
+	/// (`VSS_MIGRATION_STORE_IDS`).
+	pub store_ids: Vec<String>,
+	/// Whether divergence found during reconciliation is repaired (`VSS_MIGRATION_REPAIR`).
+	pub repair: bool,
+}
+
+impl Config {
+	pub fn from_env() -> std::io::Result<Self> {
+		let bind_address = parse_var("VSS_BIND_ADDRESS")?.unwrap_or(SocketAddr::from(([127, 0, 0, 1], 3000)));
+		let grpc_bind_address = parse_var("VSS_GRPC_BIND_ADDRESS")?;
+		let tls = match (env::var("VSS_TLS_CERT_FILE"), env::var("VSS_TLS_KEY_FILE")) {
+			(Ok(cert_file), Ok(key_file)) => Some(TlsConfig {
+				cert_file: cert_file.into(),
+				key_file: key_file.into(),
+				client_ca_file: env::var("VSS_TLS_CLIENT_CA_FILE").ok().map(PathBuf::from),
+				client_access_file: env::var("VSS_TLS_CLIENT_ACCESS_FILE").ok().map(PathBuf::from),
+				reload_interval: Duration::from_secs(parse_var("VSS_TLS_RELOAD_INTERVAL_SECS")?.unwrap_or(30)),
+			}),
+			(Err(_), Err(_)) => None,
+			_ => return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"VSS_TLS_CERT_FILE and VSS_TLS_KEY_FILE must be set together",
+			)),
+		};
+		if tls.as_ref().is_some_and(|tls| tls.client_access_file.is_some() && tls.client_ca_file.is_none()) {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"VSS_TLS_CLIENT_ACCESS_FILE requires VSS_TLS_CLIENT_CA_FILE to be set",
+			));
+		}
+		let dynamodb_endpoint = match env::var("VSS_DYNAMODB_ENDPOINT") {
+			Ok(endpoint) if endpoint.is_empty() => None,
+			Ok(endpoint) => Some(endpoint),
+			Err(_) => Some("http://localhost:8000".to_string()),
+		};
+		let dynamodb_table = env::var("VSS_DYNAMODB_TABLE").unwrap_or_else(|_| "VSS".to_string());
+		let dynamodb_table_setup = parse_var("VSS_DYNAMODB_TABLE_SETUP")?.unwrap_or(TableSetup::None);
+		let dynamodb_throughput = match (parse_var("VSS_DYNAMODB_READ_CAPACITY")?, parse_var("VSS_DYNAMODB_WRITE_CAPACITY")?) {
+			(Some(read_capacity_units), Some(write_capacity_units)) => Some((read_capacity_units, write_capacity_units)),
+			(None, None) => None,
+			_ => return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"VSS_DYNAMODB_READ_CAPACITY and VSS_DYNAMODB_WRITE_CAPACITY must be set together",
+			)),
+		};
+
+		let migration = match env::var("VSS_MIGRATION_TARGET_TABLE") {
+			Ok(target_table) => Some(MigrationConfig {
+				target_dynamodb_endpoint: env::var("VSS_MIGRATION_TARGET_ENDPOINT").ok().or_else(|| dynamodb_endpoint.clone()),
+				target_table,
+				store_ids: parse_list("VSS_MIGRATION_STORE_IDS"),
+				repair: parse_var("VSS_MIGRATION_REPAIR")?.unwrap_or(false),
+			}),
+			Err(_) => None,
+		};
+
+		let history = match (parse_var("VSS_HISTORY_MAX_VERSIONS")?, parse_var::<u64>("VSS_HISTORY_MAX_AGE_SECS")?) {
+			(None, None) => None,
+			(max_versions, max_age_secs) => Some(HistoryConfig {
+				max_versions,
+				max_age: max_age_secs.map(Duration::from_secs),
+				prune_interval: Duration::from_secs(parse_var("VSS_HISTORY_PRUNE_INTERVAL_SECS")?.unwrap_or(300)),
+			}),
+		};
+
+		let soft_delete = match parse_var("VSS_SOFT_DELETE_RETENTION_SECS")? {
+			Some(retention_secs) => Some(SoftDeleteConfig {
+				retention: Duration::from_secs(retention_secs),
+				reap_interval: Duration::from_secs(parse_var("VSS_SOFT_DELETE_REAP_INTERVAL_SECS")?.unwrap_or(300)),
+			}),
+			None => None,
+		};
+
+		let expiry_sweep_interval = parse_var("VSS_EXPIRY_SWEEP_INTERVAL_SECS")?
+			.map(Duration::from_secs)
+			.filter(|interval| !interval.is_zero());
+
+		let admin_token = env::var("VSS_ADMIN_TOKEN").ok().filter(|token| !token.is_empty());
+		let cache = match parse_var("VSS_CACHE_MAX_BYTES")? {
+			Some(max_bytes) => Some(CacheConfig {
+				max_bytes,
+				validate: parse_var("VSS_CACHE_VALIDATE")?.unwrap_or(true),
+				max_age: Duration::from_secs(parse_var("VSS_CACHE_MAX_AGE_SECS")?.unwrap_or(60)),
+			}),
+			None => None,
+		};
+		let audit_log = match (env::var("VSS_AUDIT_LOG_FILE"), env::var("VSS_AUDIT_LOG_TABLE")) {
+			(Ok(file), Err(_)) => Some(AuditLogConfig::File(file.into())),
+			(Err(_), Ok(table_name)) => Some(AuditLogConfig::DynamoDb {
+				table_name,
+				chain_id: env::var("VSS_AUDIT_CHAIN_ID").or_else(|_| env::var("HOSTNAME")).unwrap_or_else(|_| "vss".to_string()),
+			}),
+			(Err(_), Err(_)) => None,
+			(Ok(_), Ok(_)) => return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"Only one of VSS_AUDIT_LOG_FILE and VSS_AUDIT_LOG_TABLE may be set",
+			)),
+		};
+
+		let telemetry = TelemetryConfig {
+			log_format: parse_var("VSS_LOG_FORMAT")?.unwrap_or(LogFormat::Json),
+			store_id_logging: parse_var("VSS_LOG_STORE_ID")?.unwrap_or(StoreIdLogging::Hashed),
+			otlp_endpoint: env::var("VSS_OTLP_ENDPOINT").ok(),
+			service_name: env::var("VSS_OTLP_SERVICE_NAME").unwrap_or_else(|_| "vss".to_string()),
+		};
+
+		let limits = RequestLimits {
+			max_body_size: parse_var("VSS_MAX_BODY_SIZE")?.unwrap_or(64 * 1024),
+			// Transactions hold up to 3.5 MB of values, which grow by a third when base64-encoded in JSON.
+			max_put_body_size: parse_var("VSS_MAX_PUT_BODY_SIZE")?.unwrap_or(8 * 1024 * 1024),
+			request_timeout: Duration::from_millis(parse_var("VSS_REQUEST_TIMEOUT_MS")?.unwrap_or(10_000)),
+		};
+
+		let compression_min_size = parse_var("VSS_COMPRESSION_MIN_SIZE")?.unwrap_or(1024);
+		let change_buffer_size = parse_var("VSS_CHANGE_BUFFER_SIZE")?.unwrap_or(10_000);
+		let change_feed = parse_var("VSS_CHANGE_FEED")?.unwrap_or(ChangeFeed::Local);
+
+		let readiness_timeout = Duration::from_millis(parse_var("VSS_READINESS_TIMEOUT_MS")?.unwrap_or(2000));
+		let shutdown_timeout = Duration::from_secs(parse_var("VSS_SHUTDOWN_TIMEOUT_SECS")?.unwrap_or(30));
+
+		Ok(Self {
+			bind_address, grpc_bind_address, tls, dynamodb_endpoint, dynamodb_table, dynamodb_table_setup, dynamodb_throughput, migration, history, soft_delete, expiry_sweep_interval, admin_token, audit_log, cache,
+			telemetry,
+			limits, compression_min_size, change_buffer_size, change_feed, readiness_timeout, shutdown_timeout,
+		})
+	}
+}
+
+fn parse_var<T: std::str::FromStr>(name: &str) -> std::io::Result<Option<T>> {
+	match env::var(name) {
+		Ok(value) => value.parse::<T>().map(Some).map_err(|_| {
+			std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid value for {}: {}", name, value))
+		}),
+		Err(_) => Ok(None),
+	}
+}
+
+fn parse_list(name: &str) -> Vec<String> {
+	env::var(name)
+		.map(|value| value.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect())
+		.unwrap_or_default()
+}
@@ -1,118 +1,744 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use aws_sdk_dynamodb::Client;
 use aws_sdk_dynamodb::primitives::Blob;
-use aws_sdk_dynamodb::types::{AttributeValue, Delete, Put, TransactWriteItem};
+use aws_sdk_dynamodb::types::{AttributeDefinition, AttributeValue, BillingMode, CancellationReason, Delete, DeleteRequest, KeySchemaElement, KeyType, KeysAndAttributes, ProvisionedThroughput, Put, PutRequest, ReturnValue, ScalarAttributeType, StreamSpecification, StreamViewType, TableDescription, TableStatus, TimeToLiveSpecification, TimeToLiveStatus, TransactWriteItem, Update, WriteRequest};
+use aws_sdk_dynamodb::operation::transact_write_items::TransactWriteItemsError;
 use axum::async_trait;
-use crate::store::KvStore;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
 
-use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, KeyValue, ListKeyVersionsRequest, ListKeyVersionsResponse, PutObjectRequest, PutObjectResponse};
+use crate::history::{history_not_enabled, is_history_store_id};
+use crate::metrics::observe_backend_call;
+use crate::snapshot::is_snapshot_store_id;
+use crate::soft_delete::is_deleted_store_id;
+use crate::store::{HealthStatus, KvStore, MAX_GET_OBJECTS_KEYS, conflict_error, next_version};
 
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, ItemExpiry, KeyValue, GetObjectVersionRequest, GetObjectVersionResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, PutObjectRequest, PutObjectResponse, StoreMetadata, StoreStats};
+
+/// A `KvStore` backed by a single DynamoDB table.
+///
+/// Values larger than `MAX_INLINE_VALUE_SIZE` do not fit in a single DynamoDB item (capped at
+/// 400 KB), so they are split into chunk items. The item under the logical key then acts as a
+/// manifest: it carries the `version`, the number of chunks and an id unique to the write
+/// (`chunk_set`), but no `value`. Chunk items are stored in a separate partition
+/// (`<store_id>\0chunks`) so they never show up in `list_key_versions`, with sort keys
+/// `<key>\0<chunk_set>\0<index>`. Since every write uses a fresh `chunk_set`, a reader racing with
+/// a write either sees all chunks of the manifest it read or notices that some are gone and retries.
+///
+/// Writes store the version following the one they expect, see [`next_version`]. They are made
+/// in a transaction requiring each item to be in the state read just before, so that concurrent
+/// writes conflict and the chunks removed are always those of the values replaced.
+///
+/// The `global_version` of a store is kept in an item of another partition (`<store_id>\0meta`),
+/// incremented in the transaction of every put, which requires it to be at the version the put
+/// was made at: that given by the request, or the one read just before, the put being attempted
+/// again if it changed meanwhile. That item also records when the store was first
+/// and last written to, in `created_at` and `last_modified_at`. The items written by a put carry
+/// the `global_version` it created, so that writes read from the stream of the table can be told
+/// apart by it.
+///
+/// Items written with an expiry, and their chunks, carry it in the `expires_at` attribute in seconds
+/// since the Unix epoch. Expired items are hidden from reads right away, and removed either by
+/// DynamoDB's native TTL if enabled on that attribute or by [`KvStore::purge_expired`] otherwise.
 pub struct DynamoDbStore {
 	pub client: Client,
+	table_name: String,
+	native_ttl: AtomicBool,
 }
 /*
+The table can be created at startup by setting `VSS_DYNAMODB_TABLE_SETUP=create`, or manually with:
 ```bash
 aws dynamodb create-table \
     --table-name VSS \
     --attribute-definitions \
         AttributeName=store_id,AttributeType=S \
         AttributeName=key,AttributeType=S \
-    --key-schema AttributeName=storeId,KeyType=HASH AttributeName=key,KeyType=RANGE \
-    --provisioned-throughput ReadCapacityUnits=1,WriteCapacityUnits=1 \
+    --key-schema AttributeName=store_id,KeyType=HASH AttributeName=key,KeyType=RANGE \
+    --billing-mode PAY_PER_REQUEST \
     --table-class STANDARD \
     --endpoint-url http://localhost:8000
 ```
 */
-const VSS_TABLE: &'static str = "VSS";
+// Value of the `backend` label of metrics recorded by this store.
+const BACKEND: &str = "dynamodb";
+// BatchWriteItem accepts at most 25 put or delete requests per call.
+const MAX_BATCH_WRITE_ITEMS: usize = 25;
+// BatchGetItem accepts at most 100 keys per call.
+const MAX_BATCH_GET_ITEMS: usize = 100;
+// Values up to this size are stored inline, larger values are split into chunks of this size.
+// Leaves headroom below DynamoDB's 400 KB item limit for keys and other attributes.
+const MAX_INLINE_VALUE_SIZE: usize = 350 * 1024;
+/// DynamoDB rejects transactions larger than 4 MB in aggregate, the values in a single
+/// `PutObjectRequest` must fit within this size.
+pub const MAX_TRANSACTION_VALUE_SIZE: usize = 3 * 1024 * 1024 + 512 * 1024;
+/// DynamoDB rejects `TransactWriteItems` calls with more than 100 actions. Every put, delete and
+/// value chunk written or removed by a `PutObjectRequest` counts as one action, as does the update
+/// of the `global_version`.
+pub const MAX_TRANSACT_WRITE_ITEMS: usize = 100;
+// Sort key of the item holding the `global_version` of a store, in its metadata partition.
+const GLOBAL_VERSION_KEY: &str = "global_version";
+// Total size of the values returned in a `ListKeyVersions` page with `include_values`. A page may
+// exceed it by its last value.
+const MAX_LIST_VALUES_PAGE_SIZE: usize = 4 * 1024 * 1024;
+// Number of times a put is attempted when items, or the `global_version` of a put not requiring one,
+// change between reading them and writing the items.
+const MAX_PUT_ATTEMPTS: usize = 5;
+// Number of times a chunked read is retried when it races with a write replacing the chunks.
+const MAX_CHUNKED_READ_ATTEMPTS: usize = 3;
+// How long to wait for a table to become ACTIVE after creating it or finding it being created.
+const TABLE_ACTIVE_TIMEOUT: Duration = Duration::from_secs(300);
+// Attribute holding the expiry of items, in seconds since the Unix epoch.
+const EXPIRES_AT: &str = "expires_at";
+// Attributes of the metadata item, in milliseconds since the Unix epoch.
+const CREATED_AT: &str = "created_at";
+const LAST_MODIFIED_AT: &str = "last_modified_at";
+// Attribute holding the size of the value of items in bytes, whether stored inline or in chunks.
+// Items written before it was introduced do not have it, and count as empty in store stats.
+const SIZE: &str = "size";
+/// Attribute of the items written by a put holding the `global_version` it created. Items written
+/// by [`KvStore::backfill`] do not have it.
+pub const GLOBAL_VERSION: &str = "global_version";
+// Number of stores listed in a `ListStores` page when the request does not set `page_size`.
+const DEFAULT_LIST_STORES_PAGE_SIZE: usize = 100;
+// Number of `Scan` calls made for a `ListStores` page at most, each reading up to 1 MB of items.
+const MAX_LIST_STORES_SCANS: usize = 10;
 
 impl DynamoDbStore {
-	pub fn new(client: Client) -> Self {
-		Self { client }
+	pub fn new(client: Client, table_name: String) -> Self {
+		Self { client, table_name, native_ttl: AtomicBool::new(false) }
+	}
+
+	/// Verifies that the table exists with the key schema this store expects, waiting for it to become
+	/// ACTIVE. If `create` is set, a missing table is created first, with the given provisioned
+	/// `(read, write)` throughput or on-demand billing if `throughput` is `None`.
+	///
+	/// If `stream` is set, the table must have a stream with new and old images, which tables
+	/// created here are given.
+	pub async fn ensure_table(&self, create: bool, throughput: Option<(i64, i64)>, stream: bool) -> std::io::Result<()> {
+		match self.client.describe_table().table_name(&self.table_name).send().await {
+			Ok(output) => {
+				let table = output.table.ok_or_else(|| std::io::Error::other("DescribeTable returned no table description"))?;
+				verify_table_schema(&self.table_name, &table)?;
+				if stream {
+					verify_table_stream(&self.table_name, &table)?;
+				}
+			}
+			Err(err) => {
+				let err = err.into_service_error();
+				if !(create && err.is_resource_not_found_exception()) {
+					return Err(std::io::Error::other(format!("Failed to describe table {}: {:?}", self.table_name, err)));
+				}
+				self.create_table(throughput, stream).await?;
+			}
+		}
+		self.wait_for_active_table().await
+	}
+
+	/// Reads whether DynamoDB's native TTL removes expired items from the table, first enabling it on
+	/// the `expires_at` attribute if `enable` is set and the table has no TTL. Until then, expired
+	/// items are assumed not to be removed on their own.
+	pub async fn ensure_ttl(&self, enable: bool) -> std::io::Result<()> {
+		let output = self.client.describe_time_to_live()
+			.table_name(&self.table_name)
+			.send()
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to describe TTL of table {}: {:?}", self.table_name, err.into_service_error())))?;
+		let description = output.time_to_live_description;
+		let status = description.as_ref().and_then(|description| description.time_to_live_status());
+		let attribute_name = description.as_ref().and_then(|description| description.attribute_name());
+		let native_ttl = match status {
+			Some(TimeToLiveStatus::Enabled) | Some(TimeToLiveStatus::Enabling) => attribute_name == Some(EXPIRES_AT),
+			Some(TimeToLiveStatus::Disabled) | None if enable => {
+				self.client.update_time_to_live()
+					.table_name(&self.table_name)
+					.time_to_live_specification(TimeToLiveSpecification::builder().enabled(true).attribute_name(EXPIRES_AT).build().unwrap())
+					.send()
+					.await
+					.map_err(|err| std::io::Error::other(format!("Failed to enable TTL of table {}: {:?}", self.table_name, err.into_service_error())))?;
+				true
+			}
+			_ => false,
+		};
+		self.native_ttl.store(native_ttl, Ordering::Relaxed);
+		Ok(())
+	}
+
+	/// Whether expired items are removed by DynamoDB's native TTL, as last read by [`Self::ensure_ttl`].
+	pub fn native_ttl(&self) -> bool {
+		self.native_ttl.load(Ordering::Relaxed)
+	}
+
+	async fn create_table(&self, throughput: Option<(i64, i64)>, stream: bool) -> std::io::Result<()> {
+		let mut query = self.client.create_table()
+			.table_name(&self.table_name)
+			.attribute_definitions(AttributeDefinition::builder().attribute_name("store_id").attribute_type(ScalarAttributeType::S).build().unwrap())
+			.attribute_definitions(AttributeDefinition::builder().attribute_name("key").attribute_type(ScalarAttributeType::S).build().unwrap())
+			.key_schema(KeySchemaElement::builder().attribute_name("store_id").key_type(KeyType::Hash).build().unwrap())
+			.key_schema(KeySchemaElement::builder().attribute_name("key").key_type(KeyType::Range).build().unwrap());
+
+		query = match throughput {
+			Some((read_capacity_units, write_capacity_units)) => query
+				.billing_mode(BillingMode::Provisioned)
+				.provisioned_throughput(ProvisionedThroughput::builder()
+					.read_capacity_units(read_capacity_units)
+					.write_capacity_units(write_capacity_units)
+					.build().unwrap()),
+			None => query.billing_mode(BillingMode::PayPerRequest),
+		};
+		if stream {
+			query = query.stream_specification(StreamSpecification::builder()
+				.stream_enabled(true)
+				.stream_view_type(StreamViewType::NewAndOldImages)
+				.build().unwrap());
+		}
+
+		match query.send().await.map_err(|err| err.into_service_error()) {
+			Ok(_) => Ok(()),
+			// Another instance created the table concurrently.
+			Err(err) if err.is_resource_in_use_exception() => Ok(()),
+			Err(err) => Err(std::io::Error::other(format!("Failed to create table {}: {:?}", self.table_name, err))),
+		}
+	}
+
+	/// Returns the ARN of the current stream of the table.
+	pub async fn latest_stream_arn(&self) -> std::io::Result<String> {
+		let output = self.client.describe_table()
+			.table_name(&self.table_name)
+			.send()
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to describe table {}: {:?}", self.table_name, err.into_service_error())))?;
+		output.table.and_then(|table| table.latest_stream_arn)
+			.ok_or_else(|| std::io::Error::other(format!("Table {} has no stream", self.table_name)))
+	}
+
+	async fn wait_for_active_table(&self) -> std::io::Result<()> {
+		let deadline = tokio::time::Instant::now() + TABLE_ACTIVE_TIMEOUT;
+		loop {
+			let output = self.client.describe_table()
+				.table_name(&self.table_name)
+				.send()
+				.await
+				.map_err(|err| std::io::Error::other(format!("Failed to describe table {}: {:?}", self.table_name, err)))?;
+			match output.table.as_ref().and_then(|table| table.table_status()) {
+				Some(TableStatus::Active) => return Ok(()),
+				Some(TableStatus::Creating) | Some(TableStatus::Updating) if tokio::time::Instant::now() < deadline => {
+					tokio::time::sleep(Duration::from_secs(1)).await;
+				}
+				status => return Err(std::io::Error::other(format!("Table {} is not active, status: {:?}", self.table_name, status))),
+			}
+		}
+	}
+
+	async fn get_item(&self, store_id: &str, key: &str) -> std::io::Result<Option<HashMap<String, AttributeValue>>> {
+		let query = self.client.get_item()
+			.table_name(&self.table_name)
+			.key("store_id".to_string(), AttributeValue::S(store_id.to_string()))
+			.key("key".to_string(), AttributeValue::S(key.to_string()))
+			.consistent_read(true);
+		observe_backend_call(BACKEND, "GetItem", query.send())
+			.await
+			.map(|output| output.item)
+			.map_err(|err| std::io::Error::other(format!("Failed to get object: {:?}", err)))
+	}
+
+	/// Reads and concatenates the chunks of a value, returns `None` if some of them no longer exist
+	/// because the value was overwritten or deleted concurrently.
+	async fn read_chunks(&self, store_id: &str, key: &str, chunk_ref: &ChunkRef) -> std::io::Result<Option<Vec<u8>>> {
+		let mut value = Vec::new();
+		let mut chunk_count = 0;
+		let mut exclusive_start_key = None;
+		loop {
+			let query = self.client.query()
+				.table_name(&self.table_name)
+				.key_condition_expression("store_id = :storeIdVal and #key BETWEEN :firstChunk AND :lastChunk")
+				.expression_attribute_names("#key", "key")
+				.expression_attribute_values(":storeIdVal", AttributeValue::S(chunk_partition(store_id)))
+				.expression_attribute_values(":firstChunk", AttributeValue::S(chunk_key(key, &chunk_ref.id, 0)))
+				.expression_attribute_values(":lastChunk", AttributeValue::S(chunk_key(key, &chunk_ref.id, chunk_ref.count - 1)))
+				.consistent_read(true)
+				.set_exclusive_start_key(exclusive_start_key);
+			let output = observe_backend_call(BACKEND, "Query", query.send())
+				.await
+				.map_err(|err| std::io::Error::other(format!("Failed to read value chunks: {:?}", err)))?;
+			for item in output.items.unwrap_or_default() {
+				value.extend(item.get("value").and_then(|av| av.as_b().ok().cloned().map(Blob::into_inner)).unwrap_or_default());
+				chunk_count += 1;
+			}
+			exclusive_start_key = output.last_evaluated_key;
+			if exclusive_start_key.is_none() {
+				break;
+			}
+		}
+		Ok(if chunk_count == chunk_ref.count { Some(value) } else { None })
+	}
+
+	/// Fetches the chunk references of those `keys` which are currently stored chunked.
+	async fn fetch_chunk_refs(&self, store_id: &str, keys: &[&str]) -> std::io::Result<HashMap<String, ChunkRef>> {
+		Ok(self.fetch_item_states(store_id, keys).await?
+			.into_iter()
+			.filter_map(|(key, state)| state.chunk_ref.map(|chunk_ref| (key, chunk_ref)))
+			.collect())
+	}
+
+	/// Fetches the version, chunk reference and expiry of those `keys` which exist, expired or not.
+	async fn fetch_item_states(&self, store_id: &str, keys: &[&str]) -> std::io::Result<HashMap<String, ItemState>> {
+		let items = self.batch_get_items(store_id, keys, Some("#key, version, chunk_set, chunk_count, expires_at")).await
+			.map_err(|err| std::io::Error::other(format!("Failed to read current versions: {}", err)))?;
+		let mut states = HashMap::new();
+		for item in items {
+			if let Some(key) = item.get("key").and_then(|av| av.as_s().ok()) {
+				let version = item.get("version").and_then(|av| av.as_n().ok()).and_then(|v| v.parse::<i64>().ok()).unwrap_or(0);
+				states.insert(key.clone(), ItemState { version, chunk_ref: ChunkRef::from_item(&item), expired: is_expired(&item) });
+			}
+		}
+		Ok(states)
+	}
+
+	/// Reads the items of those `keys` which exist, with consistent reads, in no particular order.
+	/// Only the attributes in `projection` are read if set, in which `#key` refers to `key`.
+	async fn batch_get_items(&self, store_id: &str, keys: &[&str], projection: Option<&str>) -> std::io::Result<Vec<HashMap<String, AttributeValue>>> {
+		let mut items = Vec::new();
+		for batch in keys.chunks(MAX_BATCH_GET_ITEMS) {
+			let mut keys_and_attributes = KeysAndAttributes::builder()
+				.set_keys(Some(batch.iter().map(|key| item_key(store_id, key)).collect()))
+				.consistent_read(true);
+			if let Some(projection) = projection {
+				keys_and_attributes = keys_and_attributes
+					.projection_expression(projection)
+					.expression_attribute_names("#key", "key");
+			}
+			let mut keys_and_attributes = keys_and_attributes.build().unwrap();
+
+			// DynamoDB may leave part of a batch unprocessed under load, request those until done.
+			let mut attempt = 0;
+			loop {
+				if attempt > 0 {
+					tokio::time::sleep(Duration::from_millis(50 << attempt.min(6))).await;
+				}
+				attempt += 1;
+				let query = self.client.batch_get_item().request_items(self.table_name.clone(), keys_and_attributes);
+				let output = observe_backend_call(BACKEND, "BatchGetItem", query.send())
+					.await
+					.map_err(|err| std::io::Error::other(format!("Failed to get batch: {:?}", err)))?;
+				items.extend(output.responses.and_then(|mut responses| responses.remove(&self.table_name)).unwrap_or_default());
+				match output.unprocessed_keys.and_then(|mut unprocessed| unprocessed.remove(&self.table_name)) {
+					Some(unprocessed) if !unprocessed.keys().is_empty() => keys_and_attributes = unprocessed,
+					_ => break,
+				}
+			}
+		}
+		Ok(items)
+	}
+
+	/// Writes and deletes items outside of a transaction, in as few calls as DynamoDB allows.
+	async fn batch_write(&self, requests: Vec<WriteRequest>) -> std::io::Result<()> {
+		for batch in requests.chunks(MAX_BATCH_WRITE_ITEMS) {
+			let mut write_requests = batch.to_vec();
+
+			// DynamoDB may leave part of a batch unprocessed under load, resend those until done.
+			let mut attempt = 0;
+			while !write_requests.is_empty() {
+				if attempt > 0 {
+					tokio::time::sleep(Duration::from_millis(50 << attempt.min(6))).await;
+				}
+				attempt += 1;
+				let query = self.client.batch_write_item().request_items(self.table_name.clone(), write_requests);
+				let output = observe_backend_call(BACKEND, "BatchWriteItem", query.send())
+					.await
+					.map_err(|err| std::io::Error::other(format!("Failed to write batch: {:?}", err)))?;
+				write_requests = output.unprocessed_items
+					.and_then(|mut unprocessed| unprocessed.remove(&self.table_name))
+					.unwrap_or_default();
+			}
+		}
+		Ok(())
+	}
+
+	/// Records that `store_id` was written to, for writes not updating its `global_version`.
+	async fn touch(&self, store_id: &str) -> std::io::Result<()> {
+		let now = AttributeValue::N(now_millis().to_string());
+		let query = self.client.update_item()
+			.table_name(&self.table_name)
+			.set_key(Some(item_key(&meta_partition(store_id), GLOBAL_VERSION_KEY)))
+			.update_expression("SET created_at = if_not_exists(created_at, :now), last_modified_at = :now")
+			.expression_attribute_values(":now", now);
+		observe_backend_call(BACKEND, "UpdateItem", query.send())
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to update store metadata: {:?}", err)))?;
+		Ok(())
+	}
+
+	/// Writes and deletes the items of `request` in a single transaction, along with the update of
+	/// the `global_version`, provided the items are still in the `states` read before and the
+	/// `global_version` is still `global_version`.
+	///
+	/// Requiring each item to be in the state it was read in covers the version checks of the
+	/// request, and ensures that the chunks removed are those of the values replaced or deleted, even
+	/// for writes skipping version checks.
+	async fn transact_put(&self, request: &PutObjectRequest, expiries: &HashMap<&str, i64>, states: &HashMap<String, ItemState>, global_version: i64) -> Result<(), PutFailure> {
+		let mut all_transact_items: Vec<TransactWriteItem> = Vec::new();
+		// Stores that were never written to are at global version 0.
+		let condition = if global_version == 0 { "attribute_not_exists(version) OR version = :gv" } else { "version = :gv" };
+		let update = Update::builder()
+			.set_key(Some(item_key(&meta_partition(&request.store_id), GLOBAL_VERSION_KEY)))
+			.table_name(&self.table_name)
+			.update_expression("SET version = if_not_exists(version, :zero) + :one, created_at = if_not_exists(created_at, :now), last_modified_at = :now")
+			.expression_attribute_values(":zero".to_string(), AttributeValue::N("0".to_string()))
+			.expression_attribute_values(":one".to_string(), AttributeValue::N("1".to_string()))
+			.expression_attribute_values(":now".to_string(), AttributeValue::N(now_millis().to_string()))
+			.condition_expression(condition)
+			.expression_attribute_values(":gv".to_string(), AttributeValue::N(global_version.to_string()));
+		all_transact_items.push(TransactWriteItem::builder().update(update.build().unwrap()).build());
+
+		for kv in &request.transaction_items {
+			let stored = KeyValue { version: next_version(kv.version), ..kv.clone() };
+			let (mut record, chunks) = build_vss_items(&request.store_id, &stored, expiries.get(kv.key.as_str()).copied());
+			record.insert(GLOBAL_VERSION.to_string(), AttributeValue::N((global_version + 1).to_string()));
+			let condition = unchanged_condition(states.get(&kv.key));
+			let put = Put::builder()
+				.set_item(Some(record))
+				.table_name(&self.table_name)
+				.condition_expression(condition.expression)
+				.set_expression_attribute_names(condition.names)
+				.set_expression_attribute_values(condition.values)
+				.build().unwrap();
+			all_transact_items.push(TransactWriteItem::builder().put(put).build());
+			for chunk in chunks {
+				let put = Put::builder().set_item(Some(chunk)).table_name(&self.table_name).build().unwrap();
+				all_transact_items.push(TransactWriteItem::builder().put(put).build());
+			}
+		}
+
+		for kv in &request.delete_items {
+			let condition = unchanged_condition(states.get(&kv.key));
+			let delete = Delete::builder()
+				.set_key(Some(item_key(&request.store_id, &kv.key)))
+				.table_name(&self.table_name)
+				.condition_expression(condition.expression)
+				.set_expression_attribute_names(condition.names)
+				.set_expression_attribute_values(condition.values)
+				.build().unwrap();
+			all_transact_items.push(TransactWriteItem::builder().delete(delete).build());
+		}
+
+		// Chunks of the values being replaced or deleted are removed in the same transaction.
+		for (key, state) in states {
+			for chunk_key in state.chunk_ref.iter().flat_map(|chunk_ref| chunk_ref.chunk_keys(&request.store_id, key)) {
+				let delete = Delete::builder().set_key(Some(chunk_key)).table_name(&self.table_name).build().unwrap();
+				all_transact_items.push(TransactWriteItem::builder().delete(delete).build());
+			}
+		}
+
+		if all_transact_items.len() > MAX_TRANSACT_WRITE_ITEMS {
+			return Err(PutFailure::Other(too_many_transact_items()));
+		}
+
+		let query = self.client.transact_write_items().set_transact_items(Some(all_transact_items));
+		observe_backend_call(BACKEND, "TransactWriteItems", query.send())
+			.await
+			.map_err(|err| match err.into_service_error() {
+				TransactWriteItemsError::TransactionCanceledException(err) => {
+					let failed = |reason: &CancellationReason| reason.code() == Some("ConditionalCheckFailed");
+					// The update of the `global_version` comes first.
+					match err.cancellation_reasons().iter().position(failed) {
+						Some(0) => PutFailure::GlobalVersionMismatch,
+						Some(_) => PutFailure::ItemChanged,
+						None => PutFailure::Other(std::io::Error::other(format!("Failed to put object: {:?}", err))),
+					}
+				}
+				err => PutFailure::Other(std::io::Error::other(format!("Failed to put object: {:?}", err))),
+			})?;
+		Ok(())
+	}
+
+	/// Reads a page of the items of `partition`, with only the attributes in `projection`, in which
+	/// `#key`, `#value` and `#size` refer to `key`, `value` and `size`. Returns the start key of the
+	/// next page if any.
+	async fn query_page(
+		&self, partition: &str, projection: &str, exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+	) -> std::io::Result<(Vec<HashMap<String, AttributeValue>>, Option<HashMap<String, AttributeValue>>)> {
+		let mut query = self.client.query()
+			.table_name(&self.table_name)
+			.key_condition_expression("store_id = :storeIdVal")
+			.expression_attribute_values(":storeIdVal", AttributeValue::S(partition.to_string()))
+			.projection_expression(projection)
+			.consistent_read(true)
+			.set_exclusive_start_key(exclusive_start_key);
+		// DynamoDB rejects names not used in the expression.
+		for name in ["key", "value", SIZE] {
+			if projection.contains(&format!("#{}", name)) {
+				query = query.expression_attribute_names(format!("#{}", name), name);
+			}
+		}
+		let output = observe_backend_call(BACKEND, "Query", query.send())
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to query partition: {:?}", err)))?;
+		Ok((output.items.unwrap_or_default(), output.last_evaluated_key))
+	}
+
+	/// Sums the number of unexpired items of `partition` and the sizes recorded on them.
+	async fn partition_stats(&self, partition: &str) -> std::io::Result<(i64, i64)> {
+		let (mut count, mut size) = (0, 0);
+		let mut exclusive_start_key = None;
+		loop {
+			let (items, last_evaluated_key) = self.query_page(partition, "#size, expires_at", exclusive_start_key).await?;
+			for item in items.iter().filter(|item| !is_expired(item)) {
+				count += 1;
+				size += recorded_size(item);
+			}
+			exclusive_start_key = last_evaluated_key;
+			if exclusive_start_key.is_none() {
+				return Ok((count, size));
+			}
+		}
+	}
+
+	/// Whether `partition` holds any item, expired or not.
+	async fn has_items(&self, partition: &str) -> std::io::Result<bool> {
+		let query = self.client.query()
+			.table_name(&self.table_name)
+			.key_condition_expression("store_id = :storeIdVal")
+			.expression_attribute_values(":storeIdVal", AttributeValue::S(partition.to_string()))
+			.projection_expression("store_id")
+			.limit(1);
+		let output = observe_backend_call(BACKEND, "Query", query.send())
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to query partition: {:?}", err)))?;
+		Ok(output.count > 0)
+	}
+
+	/// Returns the stats of the store whose partition was counted up to `position`, if it is to be
+	/// listed. Stores written to but since emptied are only found through their metadata partition,
+	/// and are listed from it unless their item partition is, which comes elsewhere in the scan.
+	async fn listed_store(&self, position: ListStoresPosition) -> std::io::Result<Option<StoreStats>> {
+		if is_item_partition(&position.store_id) {
+			return Ok(Some(StoreStats { store_id: position.store_id, key_count: position.key_count, total_size: position.total_size }));
+		}
+		match position.store_id.strip_suffix("\0meta") {
+			Some(store_id) if is_item_partition(store_id) && !self.has_items(store_id).await? => {
+				Ok(Some(StoreStats { store_id: store_id.to_string(), ..Default::default() }))
+			}
+			_ => Ok(None),
+		}
+	}
+
+	/// Deletes all items of `partition`, returning their number.
+	async fn delete_partition(&self, partition: &str) -> std::io::Result<u64> {
+		let mut deleted = 0;
+		let mut exclusive_start_key = None;
+		loop {
+			let (items, last_evaluated_key) = self.query_page(partition, "store_id, #key", exclusive_start_key).await?;
+			deleted += items.len() as u64;
+			let delete_requests = items.into_iter()
+				.map(|key| WriteRequest::builder().delete_request(DeleteRequest::builder().set_key(Some(key)).build().unwrap()).build())
+				.collect();
+			self.batch_write(delete_requests).await?;
+			exclusive_start_key = last_evaluated_key;
+			if exclusive_start_key.is_none() {
+				return Ok(deleted);
+			}
+		}
 	}
 }
 
 #[async_trait]
 impl KvStore for DynamoDbStore {
 	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
-		match self.client.get_item()
-			.table_name(VSS_TABLE)
-			.key("store_id".to_string(), AttributeValue::S(request.store_id))
-			.key("key".to_string(), AttributeValue::S(request.key.clone()))
-			.consistent_read(true)
-			.send().await {
-			Ok(output) => match output.item {
-				Some(item) => {
-					let version = item.get("version").cloned().and_then(|av| av.as_n().ok().and_then(|v| v.parse::<i64>().ok())).unwrap_or(0);
-					let value = item.get("value").and_then(|av| av.as_b().ok().cloned().map(Blob::into_inner)).unwrap_or_default();
-					let response = GetObjectResponse {
-						value: Some(KeyValue { version, value, key: request.key }),
-						..Default::default()
-					};
-					Ok(response)
-				}
-				None => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Key not found")),
-			},
-			Err(err) => Err(std::io::Error::new(std::io::ErrorKind::Other, err.to_string())),
+		for _ in 0..MAX_CHUNKED_READ_ATTEMPTS {
+			let item = match self.get_item(&request.store_id, &request.key).await? {
+				Some(item) if !is_expired(&item) => item,
+				_ => return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Key not found")),
+			};
+			let version = item.get("version").cloned().and_then(|av| av.as_n().ok().and_then(|v| v.parse::<i64>().ok())).unwrap_or(0);
+			if request.known_version == Some(version) {
+				// The client already has this value, chunks are not read.
+				let response = GetObjectResponse {
+					value: Some(KeyValue { version, key: request.key, ..Default::default() }),
+					not_modified: true,
+				};
+				return Ok(response);
+			}
+			let value = match ChunkRef::from_item(&item) {
+				Some(chunk_ref) => match self.read_chunks(&request.store_id, &request.key, &chunk_ref).await? {
+					Some(value) => value,
+					None => continue,
+				},
+				None => item.get("value").and_then(|av| av.as_b().ok().cloned().map(Blob::into_inner)).unwrap_or_default(),
+			};
+			let response = GetObjectResponse {
+				value: Some(KeyValue { version, value, key: request.key }),
+				..Default::default()
+			};
+			return Ok(response);
 		}
+		Err(std::io::Error::other("Failed to get object: value kept changing while reading its chunks"))
 	}
-	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
-		let put_transact_items: Vec<TransactWriteItem> = request.transaction_items.iter()
-			.map(|kv| {
-				let record = build_vss_item(&request.store_id, kv);
-				let put = Put::builder()
-					.set_item(Some(record))
-					.table_name(VSS_TABLE)
-					.condition_expression("attribute_not_exists(id) OR version = :v")
-					.expression_attribute_values(":v".to_string(), AttributeValue::N(kv.version.to_string()))
-					.build().unwrap();
-				TransactWriteItem::builder().put(put).build()
-			})
-			.collect();
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+		if request.keys.len() > MAX_GET_OBJECTS_KEYS {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("At most {} keys can be fetched at once", MAX_GET_OBJECTS_KEYS),
+			));
+		}
+		let mut keys: Vec<&str> = Vec::with_capacity(request.keys.len());
+		for key in &request.keys {
+			if !keys.contains(&key.as_str()) {
+				keys.push(key);
+			}
+		}
 
-		let delete_transact_items: Vec<TransactWriteItem> = request.delete_items.iter()
-			.map(|kv| {
-				let mut key: HashMap<String, AttributeValue> = HashMap::new();
-				key.insert("store_id".to_string(), AttributeValue::S(request.store_id.clone()));
-				key.insert("key".to_string(), AttributeValue::S(kv.key.clone()));
-				let delete = Delete::builder()
-					.set_key(Some(key))
-					.table_name(VSS_TABLE)
-					.condition_expression("version = :v")
-					.expression_attribute_values(":v".to_string(), AttributeValue::N(kv.version.to_string()))
-					.build().unwrap();
-				TransactWriteItem::builder().delete(delete).build()
-			})
+		let mut items: HashMap<String, HashMap<String, AttributeValue>> = self.batch_get_items(&request.store_id, &keys, None).await?
+			.into_iter()
+			.filter_map(|item| item.get("key").and_then(|av| av.as_s().ok()).cloned().map(|key| (key, item)))
 			.collect();
+		let mut response = GetObjectsResponse::default();
+		for key in keys {
+			let item = match items.remove(key) {
+				Some(item) if !is_expired(&item) => item,
+				_ => {
+					response.missing_keys.push(key.to_string());
+					continue;
+				}
+			};
+			let version = item.get("version").cloned().and_then(|av| av.as_n().ok().and_then(|v| v.parse::<i64>().ok())).unwrap_or(0);
+			let value = match ChunkRef::from_item(&item) {
+				Some(chunk_ref) => match self.read_chunks(&request.store_id, key, &chunk_ref).await? {
+					Some(value) => value,
+					// Overwritten or deleted since the batch was read, read it again on its own.
+					None => {
+						match self.get(GetObjectRequest { store_id: request.store_id.clone(), key: key.to_string(), ..Default::default() }).await {
+							Ok(GetObjectResponse { value: Some(kv), .. }) => response.values.push(kv),
+							Ok(GetObjectResponse { value: None, .. }) => response.missing_keys.push(key.to_string()),
+							Err(err) if err.kind() == std::io::ErrorKind::NotFound => response.missing_keys.push(key.to_string()),
+							Err(err) => return Err(err),
+						}
+						continue;
+					}
+				},
+				None => item.get("value").and_then(|av| av.as_b().ok().cloned().map(Blob::into_inner)).unwrap_or_default(),
+			};
+			response.values.push(KeyValue { key: key.to_string(), version, value });
+		}
+		Ok(response)
+	}
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+		let mut unique_keys: Vec<&str> = Vec::with_capacity(keys.len());
+		for key in &keys {
+			if !unique_keys.contains(&key.as_str()) {
+				unique_keys.push(key);
+			}
+		}
+		let items = self.batch_get_items(store_id, &unique_keys, Some("#key, version, expires_at")).await?;
+		Ok(items.into_iter()
+			.filter(|item| !is_expired(item))
+			.filter_map(|item| {
+				let key = item.get("key")?.as_s().ok()?.clone();
+				let version = item.get("version")?.as_n().ok()?.parse::<i64>().ok()?;
+				Some((key, version))
+			})
+			.collect())
+	}
+	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+		let value_size: usize = request.transaction_items.iter().map(|kv| kv.value.len()).sum();
+		if value_size > MAX_TRANSACTION_VALUE_SIZE {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				format!("Total size of values in a PutObjectRequest must not exceed {} bytes", MAX_TRANSACTION_VALUE_SIZE),
+			));
+		}
+		if request.transaction_items.len() + request.delete_items.len() + 1 > MAX_TRANSACT_WRITE_ITEMS {
+			return Err(too_many_transact_items());
+		}
 
-		let mut all_transact_items = put_transact_items;
-		all_transact_items.extend(delete_transact_items);
+		let mut written_keys = HashSet::new();
+		if !request.transaction_items.iter().chain(request.delete_items.iter()).all(|kv| written_keys.insert(kv.key.as_str())) {
+			return Err(std::io::Error::new(
+				std::io::ErrorKind::InvalidInput,
+				"Each key must appear at most once across `transaction_items` and `delete_items`",
+			));
+		}
 
-		self.client.transact_write_items()
-			.set_transact_items(Some(all_transact_items))
-			.send()
-			.await
-			.map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to put object: {:?}", err)))?;
+		let mut expiries = HashMap::new();
+		for item_expiry in &request.item_expiries {
+			if !request.transaction_items.iter().any(|kv| kv.key == item_expiry.key) || expiries.insert(item_expiry.key.as_str(), item_expiry.expires_at).is_some() {
+				return Err(std::io::Error::new(
+					std::io::ErrorKind::InvalidInput,
+					"Each key in `item_expiries` must be that of an item in `transaction_items`, at most once",
+				));
+			}
+		}
 
-		Ok(PutObjectResponse::default())
+		let keys: Vec<&str> = request.transaction_items.iter().chain(request.delete_items.iter()).map(|kv| kv.key.as_str()).collect();
+		for _ in 0..MAX_PUT_ATTEMPTS {
+			let states = self.fetch_item_states(&request.store_id, &keys).await?;
+			// Checked here as well as in the transaction, so that a failed transaction can be told
+			// apart from a mismatch of the requested versions.
+			let mismatched = request.transaction_items.iter().chain(request.delete_items.iter())
+				.find(|kv| !version_matches(kv.version, states.get(&kv.key)));
+			if let Some(kv) = mismatched {
+				return Err(conflict_error(format!("Version mismatch for key {}", kv.key)));
+			}
+			// The `global_version` written is returned, so it is read when not given by the request.
+			let global_version = match request.global_version {
+				Some(global_version) => global_version,
+				None => self.global_version(&request.store_id).await?,
+			};
+			match self.transact_put(&request, &expiries, &states, global_version).await {
+				// Some item changed since its state was read, read it again.
+				Err(PutFailure::ItemChanged) => continue,
+				Err(PutFailure::GlobalVersionMismatch) if request.global_version.is_some() => {
+					return Err(conflict_error("Global version mismatch".to_string()));
+				}
+				// Written to concurrently, read the `global_version` again.
+				Err(PutFailure::GlobalVersionMismatch) => continue,
+				Err(PutFailure::Other(err)) => return Err(err),
+				Ok(()) => return Ok(PutObjectResponse { global_version: Some(global_version + 1) }),
+			}
+		}
+		Err(conflict_error("Items kept changing while being written".to_string()))
 	}
 	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
-		let mut query = self.client.delete_item().table_name(VSS_TABLE)
-			.key("store_id".to_string(), AttributeValue::S(request.store_id))
-			.key("key".to_string(), AttributeValue::S(request.key_value.as_ref().unwrap().key.clone()));
+		let mut query = self.client.delete_item().table_name(&self.table_name)
+			.key("store_id".to_string(), AttributeValue::S(request.store_id.clone()))
+			.key("key".to_string(), AttributeValue::S(request.key_value.as_ref().unwrap().key.clone()))
+			.return_values(ReturnValue::AllOld);
 
 		if request.key_value.as_ref().unwrap().version != -1 {
-			query = query.condition_expression("version = :v")
-				.expression_attribute_values(":v".to_string(), AttributeValue::N(request.key_value.unwrap().version.to_string()));
+			// Expired items are deleted as if they no longer existed.
+			query = query.condition_expression("version = :v AND (attribute_not_exists(expires_at) OR expires_at > :now)")
+				.expression_attribute_values(":v".to_string(), AttributeValue::N(request.key_value.as_ref().unwrap().version.to_string()))
+				.expression_attribute_values(":now".to_string(), AttributeValue::N(now_secs().to_string()));
 		}
-		match query
-			.send()
-			.await {
-			Ok(_) => Ok(DeleteObjectResponse {}),
-			Err(err) => Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to delete object: {:?}", err))),
+		let output = observe_backend_call(BACKEND, "DeleteItem", query.send())
+			.await
+			.map_err(|err| match err.into_service_error() {
+				err if err.is_conditional_check_failed_exception() => conflict_error(format!("Version mismatch: {:?}", err)),
+				err => std::io::Error::other(format!("Failed to delete object: {:?}", err)),
+			})?;
+
+		if let Some(chunk_ref) = output.attributes.as_ref().and_then(ChunkRef::from_item) {
+			let key = &request.key_value.as_ref().unwrap().key;
+			let delete_requests = chunk_ref.chunk_keys(&request.store_id, key).into_iter()
+				.map(|chunk_key| WriteRequest::builder().delete_request(DeleteRequest::builder().set_key(Some(chunk_key)).build().unwrap()).build())
+				.collect();
+			self.batch_write(delete_requests).await?;
 		}
+		if output.attributes.is_some() && is_item_partition(&request.store_id) {
+			self.touch(&request.store_id).await?;
+		}
+		Ok(DeleteObjectResponse {})
 	}
 	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+		// Read before the keys, so that all keys listed were written at this global version or later.
+		let global_version = match request.page_token {
+			None => Some(self.global_version(&request.store_id).await?),
+			Some(_) => None,
+		};
+
 		let mut expr_attr_values: HashMap<String, AttributeValue> = HashMap::new();
 		let mut expr_attr_names: HashMap<String, String> = HashMap::new();
 
@@ -126,45 +752,749 @@ impl KvStore for DynamoDbStore {
 		expr_attr_values.insert(":storeIdVal".into(), AttributeValue::S(request.store_id.clone()));
 
 		let mut query = self.client.query()
-			.table_name(VSS_TABLE)
+			.table_name(&self.table_name)
 			.key_condition_expression(key_cond_expr);
 
-		if let Some(start_key) = &request.page_token {
-			let mut expr_start_key: HashMap<String, AttributeValue> = HashMap::new();
-			expr_start_key.insert("store_id".into(), AttributeValue::S(request.store_id.clone()));
-			expr_start_key.insert("#key".into(), AttributeValue::S(start_key.clone()));
+		if !request.include_values {
+			// Inline values take up most of the 1 MB DynamoDB reads per page, skip them.
 			expr_attr_names.insert("#key".into(), "key".to_string());
-			query = query.set_exclusive_start_key(Some(expr_start_key));
+			query = query.projection_expression("#key, version, expires_at");
+		}
+		if let Some(page_size) = request.page_size.filter(|page_size| *page_size > 0) {
+			query = query.limit(page_size);
+		}
+		if let Some(start_key) = &request.page_token {
+			query = query.set_exclusive_start_key(Some(item_key(&request.store_id, start_key)));
 		}
 
 		query = query.set_expression_attribute_values(Some(expr_attr_values));
-		query = query.set_expression_attribute_names(Some(expr_attr_names));
+		if !expr_attr_names.is_empty() {
+			query = query.set_expression_attribute_names(Some(expr_attr_names));
+		}
 
-		match query
-			.send()
-			.await {
-			Ok(output) => {
-				let key_versions = output.items.unwrap_or_default().into_iter().map(|item| {
-					KeyValue {
-						key: item.get("key").and_then(|av| av.as_s().ok()).unwrap().to_string(),
-						version: item.get("version").cloned().and_then(|av| av.as_n().ok().and_then(|v| v.parse::<i64>().ok())).unwrap_or(0),
-						..Default::default()
+		let output = observe_backend_call(BACKEND, "Query", query.send())
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to list key versions: {:?}", err)))?;
+		let mut next_page_token = output.last_evaluated_key.map(|lek| lek.get("key").and_then(|av| av.as_s().ok()).unwrap().to_string());
+
+		let mut key_versions: Vec<KeyValue> = Vec::new();
+		let mut item_expiries = Vec::new();
+		let mut values_size = 0;
+		for item in output.items.unwrap_or_default().into_iter().filter(|item| !is_expired(item)) {
+			let key = item.get("key").and_then(|av| av.as_s().ok()).unwrap().to_string();
+			let version = item.get("version").cloned().and_then(|av| av.as_n().ok().and_then(|v| v.parse::<i64>().ok())).unwrap_or(0);
+			let item_expiry = expires_at_millis(&item).map(|expires_at| ItemExpiry { key: key.clone(), expires_at });
+			if !request.include_values {
+				key_versions.push(KeyValue { key, version, ..Default::default() });
+				item_expiries.extend(item_expiry);
+				continue;
+			}
+
+			if values_size >= MAX_LIST_VALUES_PAGE_SIZE {
+				// The next page starts after the last key returned.
+				next_page_token = key_versions.last().map(|kv| kv.key.clone());
+				break;
+			}
+			let kv = match ChunkRef::from_item(&item) {
+				Some(chunk_ref) => match self.read_chunks(&request.store_id, &key, &chunk_ref).await? {
+					Some(value) => KeyValue { key, version, value },
+					// Overwritten or deleted since the page was read, read it again on its own.
+					None => match self.get(GetObjectRequest { store_id: request.store_id.clone(), key, ..Default::default() }).await {
+						Ok(GetObjectResponse { value: Some(kv), .. }) => kv,
+						Ok(GetObjectResponse { value: None, .. }) => continue,
+						Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+						Err(err) => return Err(err),
+					},
+				},
+				None => {
+					let value = item.get("value").and_then(|av| av.as_b().ok().cloned().map(Blob::into_inner)).unwrap_or_default();
+					KeyValue { key, version, value }
+				}
+			};
+			values_size += kv.value.len();
+			key_versions.push(kv);
+			item_expiries.extend(item_expiry);
+		}
+		Ok(ListKeyVersionsResponse { key_versions, next_page_token, global_version, item_expiries })
+	}
+	async fn list_object_versions(&self, _request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+		Err(history_not_enabled())
+	}
+	async fn get_object_version(&self, _request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+		Err(history_not_enabled())
+	}
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+		let item = self.get_item(&meta_partition(store_id), GLOBAL_VERSION_KEY).await?;
+		Ok(item.and_then(|item| item.get("version").and_then(|av| av.as_n().ok()).and_then(|v| v.parse::<i64>().ok())).unwrap_or(0))
+	}
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+		let now = AttributeValue::N(now_millis().to_string());
+		let query = self.client.update_item()
+			.table_name(&self.table_name)
+			.set_key(Some(item_key(&meta_partition(store_id), GLOBAL_VERSION_KEY)))
+			.update_expression("SET version = :gv, created_at = if_not_exists(created_at, :now), last_modified_at = :now")
+			.condition_expression("attribute_not_exists(version) OR version < :gv")
+			.expression_attribute_values(":gv", AttributeValue::N(global_version.to_string()))
+			.expression_attribute_values(":now", now);
+		match observe_backend_call(BACKEND, "UpdateItem", query.send()).await.map_err(|err| err.into_service_error()) {
+			Ok(_) => Ok(()),
+			// Already at `global_version` or higher.
+			Err(err) if err.is_conditional_check_failed_exception() => Ok(()),
+			Err(err) => Err(std::io::Error::other(format!("Failed to backfill global version: {:?}", err))),
+		}
+	}
+	async fn purge_expired(&self) -> std::io::Result<usize> {
+		if self.native_ttl() {
+			return Ok(0);
+		}
+		let now = AttributeValue::N(now_secs().to_string());
+		let mut purged = 0;
+		let mut exclusive_start_key = None;
+		loop {
+			let query = self.client.scan()
+				.table_name(&self.table_name)
+				.filter_expression("expires_at <= :now")
+				.projection_expression("store_id, #key")
+				.expression_attribute_names("#key", "key")
+				.expression_attribute_values(":now", now.clone())
+				.set_exclusive_start_key(exclusive_start_key);
+			let output = observe_backend_call(BACKEND, "Scan", query.send())
+				.await
+				.map_err(|err| std::io::Error::other(format!("Failed to scan for expired items: {:?}", err)))?;
+			// Chunks carry the expiry of their item, so they are purged along with it.
+			for item in output.items.unwrap_or_default() {
+				let store_id = item.get("store_id").and_then(|av| av.as_s().ok()).cloned().unwrap_or_default();
+				let query = self.client.delete_item()
+					.table_name(&self.table_name)
+					.set_key(Some(item))
+					// The item may have been overwritten since the scan.
+					.condition_expression("expires_at <= :now")
+					.expression_attribute_values(":now", now.clone());
+				match observe_backend_call(BACKEND, "DeleteItem", query.send()).await.map_err(|err| err.into_service_error()) {
+					Ok(_) if is_item_partition(&store_id) => purged += 1,
+					Ok(_) => {}
+					Err(err) if err.is_conditional_check_failed_exception() => {}
+					Err(err) => return Err(std::io::Error::other(format!("Failed to purge expired item: {:?}", err))),
+				}
+			}
+			exclusive_start_key = output.last_evaluated_key;
+			if exclusive_start_key.is_none() {
+				return Ok(purged);
+			}
+		}
+	}
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+		let page_size = request.page_size.filter(|page_size| *page_size > 0).map_or(DEFAULT_LIST_STORES_PAGE_SIZE, |page_size| page_size as usize);
+		// Partition being counted, up to the last item read.
+		let mut position = match request.page_token {
+			Some(token) if !token.is_empty() => Some(ListStoresPosition::decode(&token)?),
+			_ => None,
+		};
+		let mut stores = Vec::new();
+		for _ in 0..MAX_LIST_STORES_SCANS {
+			// Items of a partition are scanned one after the other, so it is counted in full once the
+			// scan moves on to another.
+			let query = self.client.scan()
+				.table_name(&self.table_name)
+				.projection_expression("store_id, #key, #size, expires_at")
+				.expression_attribute_names("#key", "key")
+				.expression_attribute_names("#size", SIZE)
+				.set_exclusive_start_key(position.as_ref().map(|position| item_key(&position.store_id, &position.key)));
+			let output = observe_backend_call(BACKEND, "Scan", query.send())
+				.await
+				.map_err(|err| std::io::Error::other(format!("Failed to scan for stores: {:?}", err)))?;
+			for item in output.items.unwrap_or_default() {
+				let string = |name: &str| item.get(name).and_then(|av| av.as_s().ok()).cloned().unwrap_or_default();
+				let partition = string("store_id");
+				let counted = match position.take() {
+					Some(counted) if counted.store_id == partition => counted,
+					previous => {
+						if let Some(previous) = previous {
+							stores.extend(self.listed_store(previous).await?);
+						}
+						ListStoresPosition { store_id: partition, ..Default::default() }
 					}
-				}).collect();
+				};
+				let counted = position.insert(ListStoresPosition { key: string("key"), ..counted });
+				if !is_expired(&item) {
+					counted.key_count += 1;
+					counted.total_size += recorded_size(&item);
+				}
+				if stores.len() >= page_size {
+					return Ok(ListStoresResponse { stores, next_page_token: Some(counted.encode()) });
+				}
+			}
+			if output.last_evaluated_key.is_none() {
+				if let Some(counted) = position {
+					stores.extend(self.listed_store(counted).await?);
+				}
+				return Ok(ListStoresResponse { stores, next_page_token: None });
+			}
+		}
+		Ok(ListStoresResponse { stores, next_page_token: position.map(|position| position.encode()) })
+	}
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+		let meta = self.get_item(&meta_partition(store_id), GLOBAL_VERSION_KEY).await?;
+		let (key_count, total_size) = self.partition_stats(store_id).await?;
+		if meta.is_none() && key_count == 0 {
+			return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Store not found"));
+		}
+		let meta = meta.unwrap_or_default();
+		let number = |name: &str| meta.get(name).and_then(|av| av.as_n().ok()).and_then(|v| v.parse::<i64>().ok());
+		Ok(StoreMetadata {
+			stats: Some(StoreStats { store_id: store_id.to_string(), key_count, total_size }),
+			global_version: number("version").unwrap_or(0),
+			created_at: number(CREATED_AT),
+			last_modified_at: number(LAST_MODIFIED_AT),
+		})
+	}
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+		// Items are deleted before their chunks, so that readers never see an item without them, and
+		// the metadata last, so that the store stays listed until it is fully deleted.
+		let deleted = self.delete_partition(store_id).await?;
+		self.delete_partition(&chunk_partition(store_id)).await?;
+		self.delete_partition(&meta_partition(store_id)).await?;
+		Ok(deleted)
+	}
+	async fn check_health(&self) -> std::io::Result<HealthStatus> {
+		let output = observe_backend_call(BACKEND, "DescribeTable", self.client.describe_table().table_name(&self.table_name).send())
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to describe table {}: {:?}", self.table_name, err)))?;
+		match output.table.as_ref().and_then(|table| table.table_status()) {
+			Some(TableStatus::Active) => Ok(HealthStatus::Healthy),
+			// Tables remain readable and writable while being updated, e.g. during a capacity change.
+			Some(TableStatus::Updating) => Ok(HealthStatus::Degraded(format!("Table {} is being updated", self.table_name))),
+			status => Err(std::io::Error::other(format!("Table {} is not active, status: {:?}", self.table_name, status))),
+		}
+	}
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+		let keys: Vec<&str> = key_values.iter().map(|kv| kv.key.as_str()).collect();
+		let old_chunk_refs = self.fetch_chunk_refs(&store_id, &keys).await?;
+		let expiries: HashMap<&str, i64> = item_expiries.iter().map(|item_expiry| (item_expiry.key.as_str(), item_expiry.expires_at)).collect();
 
-				let next_page_token = output.last_evaluated_key.map(|lek| lek.get("key").and_then(|av| av.as_s().ok()).unwrap().to_string());
-				Ok(ListKeyVersionsResponse { key_versions, next_page_token, ..Default::default() })
+		// Chunks are written before the items referencing them and old chunks are only removed
+		// afterwards, so that concurrent readers never see a manifest without its chunks.
+		let mut chunk_requests = Vec::new();
+		let mut item_requests = Vec::new();
+		for kv in &key_values {
+			let (record, chunks) = build_vss_items(&store_id, kv, expiries.get(kv.key.as_str()).copied());
+			for chunk in chunks {
+				chunk_requests.push(WriteRequest::builder().put_request(PutRequest::builder().set_item(Some(chunk)).build().unwrap()).build());
 			}
-			Err(err) => Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to list key versions: {:?}", err))),
+			item_requests.push(WriteRequest::builder().put_request(PutRequest::builder().set_item(Some(record)).build().unwrap()).build());
+		}
+		let delete_requests = old_chunk_refs.iter()
+			.flat_map(|(key, chunk_ref)| chunk_ref.chunk_keys(&store_id, key))
+			.map(|chunk_key| WriteRequest::builder().delete_request(DeleteRequest::builder().set_key(Some(chunk_key)).build().unwrap()).build())
+			.collect();
+
+		self.batch_write(chunk_requests).await?;
+		self.batch_write(item_requests).await?;
+		self.batch_write(delete_requests).await
+	}
+}
+
+/// Position of a `ListStores` scan, carried by its page tokens: the last item read, along with the
+/// stats of its partition counted so far, as partitions may span pages.
+#[derive(Default, Serialize, Deserialize)]
+struct ListStoresPosition {
+	store_id: String,
+	key: String,
+	key_count: i64,
+	total_size: i64,
+}
+
+impl ListStoresPosition {
+	fn encode(&self) -> String {
+		URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+	}
+
+	fn decode(token: &str) -> std::io::Result<Self> {
+		URL_SAFE_NO_PAD.decode(token).ok()
+			.and_then(|json| serde_json::from_slice(&json).ok())
+			.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid page_token"))
+	}
+}
+
+fn verify_table_schema(table_name: &str, table: &TableDescription) -> std::io::Result<()> {
+	let mut key_schema: Vec<(&str, &KeyType)> = table.key_schema().iter().map(|element| (element.attribute_name(), element.key_type())).collect();
+	key_schema.sort_by_key(|(_, key_type)| key_type.as_str());
+	if key_schema != [("store_id", &KeyType::Hash), ("key", &KeyType::Range)] {
+		return Err(std::io::Error::other(format!(
+			"Table {} has key schema {:?}, expected `store_id` (HASH) and `key` (RANGE)", table_name, key_schema,
+		)));
+	}
+	for attribute_definition in table.attribute_definitions() {
+		if attribute_definition.attribute_type() != &ScalarAttributeType::S {
+			return Err(std::io::Error::other(format!(
+				"Table {} defines attribute `{}` as {:?}, expected a string (S)",
+				table_name, attribute_definition.attribute_name(), attribute_definition.attribute_type(),
+			)));
 		}
 	}
+	Ok(())
+}
+
+fn verify_table_stream(table_name: &str, table: &TableDescription) -> std::io::Result<()> {
+	let view_type = table.stream_specification()
+		.filter(|stream_specification| stream_specification.stream_enabled())
+		.and_then(|stream_specification| stream_specification.stream_view_type());
+	if view_type != Some(&StreamViewType::NewAndOldImages) {
+		return Err(std::io::Error::other(format!(
+			"Table {} has stream view type {:?}, expected a stream with new and old images (NEW_AND_OLD_IMAGES)", table_name, view_type,
+		)));
+	}
+	Ok(())
+}
+
+fn too_many_transact_items() -> std::io::Error {
+	std::io::Error::new(
+		std::io::ErrorKind::InvalidInput,
+		format!("A PutObjectRequest must not result in more than {} writes, counting `transaction_items`, \
+			`delete_items` and one write per {} bytes of large values written or replaced", MAX_TRANSACT_WRITE_ITEMS, MAX_INLINE_VALUE_SIZE),
+	)
+}
+
+/// Identifies the chunk items holding the value of a chunked item.
+struct ChunkRef {
+	id: String,
+	count: usize,
 }
 
-fn build_vss_item(store_id: &str, kv: &KeyValue) -> HashMap<String, AttributeValue> {
-	let mut item: HashMap<String, AttributeValue> = HashMap::new();
-	item.insert("store_id".to_string(), AttributeValue::S(store_id.to_owned()));
-	item.insert("key".to_string(), AttributeValue::S(kv.key.clone()));
-	item.insert("value".to_string(), AttributeValue::B(Blob::new(kv.value.clone())));
+impl ChunkRef {
+	fn from_item(item: &HashMap<String, AttributeValue>) -> Option<Self> {
+		let id = item.get("chunk_set").and_then(|av| av.as_s().ok())?.clone();
+		let count = item.get("chunk_count").and_then(|av| av.as_n().ok()).and_then(|v| v.parse::<usize>().ok())?;
+		Some(Self { id, count })
+	}
+
+	fn chunk_keys(&self, store_id: &str, key: &str) -> Vec<HashMap<String, AttributeValue>> {
+		(0..self.count).map(|index| chunk_item_key(store_id, key, &self.id, index)).collect()
+	}
+}
+
+/// State of an item read before writing it.
+struct ItemState {
+	version: i64,
+	chunk_ref: Option<ChunkRef>,
+	expired: bool,
+}
+
+/// Outcome of a failed put transaction.
+enum PutFailure {
+	/// The `global_version` of the store is no longer that the put was made at.
+	GlobalVersionMismatch,
+	/// An item is no longer in the state it was read in.
+	ItemChanged,
+	Other(std::io::Error),
+}
+
+/// Whether an item in `state` can be written or deleted by a request expecting `version`: it must
+/// not exist (or have expired) for version 0, and must be at `version` otherwise, unless version
+/// checks are skipped with version -1.
+fn version_matches(version: i64, state: Option<&ItemState>) -> bool {
+	let current = state.filter(|state| !state.expired).map(|state| state.version);
+	match version {
+		-1 => true,
+		0 => current.is_none(),
+		version => current == Some(version),
+	}
+}
+
+/// Condition expression, along with the names and values it uses.
+struct Condition {
+	expression: &'static str,
+	names: Option<HashMap<String, String>>,
+	values: Option<HashMap<String, AttributeValue>>,
+}
+
+/// Condition on an item being still in `state`, or still missing if `None`.
+fn unchanged_condition(state: Option<&ItemState>) -> Condition {
+	let Some(state) = state else {
+		let names = HashMap::from([("#key".to_string(), "key".to_string())]);
+		return Condition { expression: "attribute_not_exists(#key)", names: Some(names), values: None };
+	};
+	let mut values = HashMap::from([(":v".to_string(), AttributeValue::N(state.version.to_string()))]);
+	// Writes skipping version checks reset versions, so the chunks of values at the same version may differ.
+	let expression = match &state.chunk_ref {
+		Some(chunk_ref) => {
+			values.insert(":chunkSet".to_string(), AttributeValue::S(chunk_ref.id.clone()));
+			"version = :v AND chunk_set = :chunkSet"
+		}
+		None => "version = :v AND attribute_not_exists(chunk_set)",
+	};
+	Condition { expression, names: None, values: Some(values) }
+}
+
+fn item_key(store_id: &str, key: &str) -> HashMap<String, AttributeValue> {
+	let mut item_key: HashMap<String, AttributeValue> = HashMap::new();
+	item_key.insert("store_id".to_string(), AttributeValue::S(store_id.to_string()));
+	item_key.insert("key".to_string(), AttributeValue::S(key.to_string()));
+	item_key
+}
+
+fn now_secs() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs() as i64
+}
+
+fn now_millis() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as i64
+}
+
+/// Size of the value of `item` recorded when it was written, 0 if it was written before sizes were.
+fn recorded_size(item: &HashMap<String, AttributeValue>) -> i64 {
+	item.get(SIZE).and_then(|av| av.as_n().ok()).and_then(|v| v.parse::<i64>().ok()).unwrap_or(0)
+}
+
+/// Expiry of `item` in milliseconds since the Unix epoch, if it has one.
+fn expires_at_millis(item: &HashMap<String, AttributeValue>) -> Option<i64> {
+	item.get(EXPIRES_AT)
+		.and_then(|av| av.as_n().ok())
+		.and_then(|v| v.parse::<i64>().ok())
+		.map(|expires_at| expires_at * 1000)
+}
+
+/// Whether `item` expired, though it may not have been removed yet.
+fn is_expired(item: &HashMap<String, AttributeValue>) -> bool {
+	item.get(EXPIRES_AT)
+		.and_then(|av| av.as_n().ok())
+		.and_then(|v| v.parse::<i64>().ok())
+		.is_some_and(|expires_at| expires_at <= now_secs())
+}
+
+fn chunk_partition(store_id: &str) -> String {
+	format!("{}\0chunks", store_id)
+}
+
+/// Whether `partition` holds the items of a store, rather than value chunks, metadata, or the
+/// previous versions, deleted items or snapshots kept by the `KvStore` wrappers.
+pub fn is_item_partition(partition: &str) -> bool {
+	!partition.ends_with("\0chunks") && !partition.ends_with("\0meta") && !is_history_store_id(partition)
+		&& !is_deleted_store_id(partition) && !is_snapshot_store_id(partition)
+}
+
+fn meta_partition(store_id: &str) -> String {
+	format!("{}\0meta", store_id)
+}
+
+fn chunk_key(key: &str, chunk_set: &str, index: usize) -> String {
+	format!("{}\0{}\0{:08}", key, chunk_set, index)
+}
+
+fn chunk_item_key(store_id: &str, key: &str, chunk_set: &str, index: usize) -> HashMap<String, AttributeValue> {
+	item_key(&chunk_partition(store_id), &chunk_key(key, chunk_set, index))
+}
+
+/// Builds the item storing `kv`, along with the chunk items holding its value if it is too large
+/// to be stored inline.
+fn build_vss_items(store_id: &str, kv: &KeyValue, expires_at: Option<i64>) -> (HashMap<String, AttributeValue>, Vec<HashMap<String, AttributeValue>>) {
+	// Rounded up, so that items do not expire early.
+	let expires_at = expires_at.map(|expires_at| AttributeValue::N(((expires_at + 999).div_euclid(1000)).to_string()));
+	let mut item = item_key(store_id, &kv.key);
 	item.insert("version".to_string(), AttributeValue::N(kv.version.to_string()));
-	item
-}
\ No newline at end of file
+	item.insert(SIZE.to_string(), AttributeValue::N(kv.value.len().to_string()));
+	if let Some(expires_at) = &expires_at {
+		item.insert(EXPIRES_AT.to_string(), expires_at.clone());
+	}
+	if kv.value.len() <= MAX_INLINE_VALUE_SIZE {
+		item.insert("value".to_string(), AttributeValue::B(Blob::new(kv.value.clone())));
+		return (item, Vec::new());
+	}
+
+	let chunk_set = uuid::Uuid::new_v4().to_string();
+	let chunks: Vec<HashMap<String, AttributeValue>> = kv.value.chunks(MAX_INLINE_VALUE_SIZE).enumerate()
+		.map(|(index, chunk)| {
+			let mut chunk_item = chunk_item_key(store_id, &kv.key, &chunk_set, index);
+			chunk_item.insert("value".to_string(), AttributeValue::B(Blob::new(chunk.to_vec())));
+			if let Some(expires_at) = &expires_at {
+				chunk_item.insert(EXPIRES_AT.to_string(), expires_at.clone());
+			}
+			chunk_item
+		})
+		.collect();
+	item.insert("chunk_set".to_string(), AttributeValue::S(chunk_set));
+	item.insert("chunk_count".to_string(), AttributeValue::N(chunks.len().to_string()));
+	(item, chunks)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::store::is_conflict;
+	use crate::testing::{delete, dynamodb_client, dynamodb_store, get, kv, put};
+
+	#[tokio::test]
+	async fn rejects_keys_written_more_than_once() {
+		// Rejected before any request is sent, so no table is needed.
+		let store = DynamoDbStore::new(dynamodb_client().await, "unused".to_string());
+		let requests = [
+			(vec![kv("k", 0, b"a"), kv("k", 1, b"b")], vec![]),
+			(vec![], vec![kv("k", 1, b""), kv("k", 1, b"")]),
+			(vec![kv("k", 1, b"a")], vec![kv("k", 1, b"")]),
+		];
+		for (transaction_items, delete_items) in requests {
+			let request = PutObjectRequest { store_id: "store".to_string(), transaction_items, delete_items, ..Default::default() };
+			assert_eq!(store.put(request).await.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+		}
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn puts_and_deletes_check_versions() {
+		let store = dynamodb_store().await;
+		put(&store, "store", "k", 0, b"a").await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 1, b"a"));
+		// The key exists, so writing it for the first time conflicts.
+		assert!(is_conflict(&put(&store, "store", "k", 0, b"b").await.unwrap_err()));
+		assert!(is_conflict(&put(&store, "store", "k", 2, b"b").await.unwrap_err()));
+
+		put(&store, "store", "k", 1, b"b").await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 2, b"b"));
+		assert!(is_conflict(&put(&store, "store", "k", 1, b"c").await.unwrap_err()));
+
+		put(&store, "store", "k", -1, b"c").await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 1, b"c"));
+
+		assert!(is_conflict(&delete(&store, "store", "k", 2).await.unwrap_err()));
+		let conflicting_delete = store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			delete_items: vec![kv("k", 2, b"")],
+			..Default::default()
+		}).await;
+		assert!(is_conflict(&conflicting_delete.unwrap_err()));
+		delete(&store, "store", "k", 1).await.unwrap();
+		assert!(is_conflict(&put(&store, "store", "k", 1, b"d").await.unwrap_err()));
+		put(&store, "store", "k", 0, b"d").await.unwrap();
+		assert_eq!(store.global_version("store").await.unwrap(), 4);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn conflicting_transactions_leave_items_unchanged() {
+		let store = dynamodb_store().await;
+		put(&store, "store", "a", 0, b"a").await.unwrap();
+		let result = store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("a", 1, b"a2"), kv("b", 1, b"b")],
+			..Default::default()
+		}).await;
+		assert!(is_conflict(&result.unwrap_err()));
+		assert_eq!(get(&store, "store", "a").await.unwrap(), kv("a", 1, b"a"));
+		assert_eq!(get(&store, "store", "b").await.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+		let result = store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			global_version: Some(0),
+			transaction_items: vec![kv("b", 0, b"b")],
+			..Default::default()
+		}).await;
+		assert!(is_conflict(&result.unwrap_err()));
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn writes_transactions_of_exactly_max_actions() {
+		let store = dynamodb_store().await;
+		put(&store, "store", "deleted", 0, b"v").await.unwrap();
+		// With the update of the global version, one action short of the limit.
+		let mut transaction_items: Vec<KeyValue> = (0..MAX_TRANSACT_WRITE_ITEMS - 2).map(|i| kv(&format!("k{:03}", i), 0, b"v")).collect();
+		store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: transaction_items.clone(),
+			delete_items: vec![kv("deleted", 1, b"")],
+			..Default::default()
+		}).await.unwrap();
+		let listed = store.list_key_versions(ListKeyVersionsRequest { store_id: "store".to_string(), ..Default::default() }).await.unwrap();
+		assert_eq!(listed.key_versions.len(), MAX_TRANSACT_WRITE_ITEMS - 2);
+		assert_eq!(listed.global_version, Some(2));
+
+		// A chunked value counts as one action per chunk, on top of the item referencing them.
+		transaction_items.truncate(MAX_TRANSACT_WRITE_ITEMS - 4);
+		for kv in &mut transaction_items {
+			kv.version = 1;
+		}
+		transaction_items.push(kv("large", 0, &vec![0; MAX_INLINE_VALUE_SIZE + 1]));
+		store.put(PutObjectRequest { store_id: "store".to_string(), transaction_items, ..Default::default() }).await.unwrap();
+		assert_eq!(get(&store, "store", "large").await.unwrap().value.len(), MAX_INLINE_VALUE_SIZE + 1);
+		assert_eq!(store.global_version("store").await.unwrap(), 3);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn rejects_transactions_over_max_actions() {
+		let store = dynamodb_store().await;
+		let transaction_items: Vec<KeyValue> = (0..MAX_TRANSACT_WRITE_ITEMS).map(|i| kv(&format!("k{:03}", i), 0, b"v")).collect();
+		let result = store.put(PutObjectRequest { store_id: "store".to_string(), transaction_items: transaction_items.clone(), ..Default::default() }).await;
+		assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+		// Within the limit by items, over it once the chunks of a large value are counted.
+		let mut transaction_items = transaction_items[..MAX_TRANSACT_WRITE_ITEMS - 3].to_vec();
+		transaction_items.push(kv("large", 0, &vec![0; MAX_INLINE_VALUE_SIZE + 1]));
+		let result = store.put(PutObjectRequest { store_id: "store".to_string(), transaction_items, ..Default::default() }).await;
+		assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+		let listed = store.list_key_versions(ListKeyVersionsRequest { store_id: "store".to_string(), ..Default::default() }).await.unwrap();
+		assert!(listed.key_versions.is_empty());
+		assert_eq!(listed.global_version, Some(0));
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn chunked_values_round_trip_and_replace_their_chunks() {
+		let store = dynamodb_store().await;
+		let large: Vec<u8> = (0..MAX_INLINE_VALUE_SIZE * 2 + 10).map(|i| (i % 251) as u8).collect();
+		put(&store, "store", "k", 0, &large).await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 1, &large));
+		let listed = store.list_key_versions(ListKeyVersionsRequest { store_id: "store".to_string(), include_values: true, ..Default::default() }).await.unwrap();
+		assert_eq!(listed.key_versions, vec![kv("k", 1, &large)]);
+
+		let replacement: Vec<u8> = large.iter().rev().copied().collect();
+		put(&store, "store", "k", -1, &replacement).await.unwrap();
+		let response = store.get_objects(GetObjectsRequest { store_id: "store".to_string(), keys: vec!["k".to_string()] }).await.unwrap();
+		assert_eq!(response.values, vec![kv("k", 1, &replacement)]);
+		let (chunk_count, _) = store.partition_stats(&chunk_partition("store")).await.unwrap();
+		assert_eq!(chunk_count, 3);
+
+		put(&store, "store", "k", 1, b"small").await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 2, b"small"));
+		let (chunk_count, _) = store.partition_stats(&chunk_partition("store")).await.unwrap();
+		assert_eq!(chunk_count, 0);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn puts_increment_global_version() {
+		let store = dynamodb_store().await;
+		assert_eq!(put(&store, "store", "a", 0, b"a").await.unwrap().global_version, Some(1));
+		assert_eq!(put(&store, "store", "b", 0, b"b").await.unwrap().global_version, Some(2));
+		let stale = store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			global_version: Some(1),
+			transaction_items: vec![kv("a", 1, b"a2")],
+			..Default::default()
+		}).await;
+		assert!(is_conflict(&stale.unwrap_err()));
+		assert_eq!(get(&store, "store", "a").await.unwrap(), kv("a", 1, b"a"));
+
+		delete(&store, "store", "b", 1).await.unwrap();
+		assert_eq!(store.global_version("store").await.unwrap(), 2);
+
+		let puts = (0..4).map(|i| {
+			let store = &store;
+			async move { put(store, "store", &format!("k{}", i), 0, b"v").await.unwrap().global_version.unwrap() }
+		});
+		let mut global_versions = futures_util::future::join_all(puts).await;
+		global_versions.sort();
+		assert_eq!(global_versions, vec![3, 4, 5, 6]);
+
+		let request = ListKeyVersionsRequest { store_id: "store".to_string(), page_size: Some(2), ..Default::default() };
+		let first = store.list_key_versions(request.clone()).await.unwrap();
+		assert_eq!(first.global_version, Some(6));
+		let second = store.list_key_versions(ListKeyVersionsRequest { page_token: first.next_page_token, ..request }).await.unwrap();
+		assert_eq!(second.global_version, None);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn lists_stores_a_page_at_a_time() {
+		let store = dynamodb_store().await;
+		let large = vec![7; MAX_INLINE_VALUE_SIZE + 1];
+		for store_id in ["a", "b", "c"] {
+			put(&store, store_id, "k1", 0, b"value").await.unwrap();
+			put(&store, store_id, "k2", 0, &large).await.unwrap();
+		}
+		put(&store, "emptied", "k", 0, b"value").await.unwrap();
+		delete(&store, "emptied", "k", 1).await.unwrap();
+		// Written before sizes were recorded.
+		let mut legacy = item_key("legacy", "k");
+		legacy.insert("version".to_string(), AttributeValue::N("1".to_string()));
+		legacy.insert("value".to_string(), AttributeValue::B(Blob::new(b"value".to_vec())));
+		store.client.put_item().table_name(&store.table_name).set_item(Some(legacy)).send().await.unwrap();
+
+		let mut stores = Vec::new();
+		let mut page_token = None;
+		loop {
+			let page = store.list_stores(ListStoresRequest { page_size: Some(1), page_token }).await.unwrap();
+			assert!(page.stores.len() <= 1);
+			stores.extend(page.stores);
+			match page.next_page_token {
+				Some(token) => page_token = Some(token),
+				None => break,
+			}
+		}
+		stores.sort_by(|a, b| a.store_id.cmp(&b.store_id));
+		let stats = |store_id: &str, key_count: i64, total_size: usize| StoreStats { store_id: store_id.to_string(), key_count, total_size: total_size as i64 };
+		let total_size = 5 + large.len();
+		assert_eq!(stores, vec![stats("a", 2, total_size), stats("b", 2, total_size), stats("c", 2, total_size), stats("emptied", 0, 0), stats("legacy", 1, 0)]);
+
+		let metadata = store.store_metadata("a").await.unwrap();
+		assert_eq!(metadata.stats, Some(stats("a", 2, total_size)));
+		assert_eq!(metadata.global_version, 2);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn lists_and_backfills_item_expiries() {
+		let store = dynamodb_store().await;
+		let expires_at = now_millis() + 60_000;
+		store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("a", 0, b"a"), kv("b", 0, b"b")],
+			item_expiries: vec![ItemExpiry { key: "a".to_string(), expires_at }],
+			..Default::default()
+		}).await.unwrap();
+		// Kept in seconds, rounded up.
+		let expected = vec![ItemExpiry { key: "a".to_string(), expires_at: (expires_at + 999) / 1000 * 1000 }];
+		let listed = store.list_key_versions(ListKeyVersionsRequest { store_id: "store".to_string(), ..Default::default() }).await.unwrap();
+		assert_eq!(listed.item_expiries, expected);
+		let listed = store.list_key_versions(ListKeyVersionsRequest { store_id: "store".to_string(), include_values: true, ..Default::default() }).await.unwrap();
+		assert_eq!(listed.item_expiries, expected);
+
+		let expired = ItemExpiry { key: "c".to_string(), expires_at: now_millis() - 1000 };
+		store.backfill("copy".to_string(), listed.key_versions, [expected.clone(), vec![expired]].concat()).await.unwrap();
+		store.backfill("copy".to_string(), vec![kv("c", 1, b"c")], Vec::new()).await.unwrap();
+		let listed = store.list_key_versions(ListKeyVersionsRequest { store_id: "copy".to_string(), ..Default::default() }).await.unwrap();
+		assert_eq!(listed.key_versions.len(), 3);
+		assert_eq!(listed.item_expiries, expected);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn reads_native_ttl_whatever_the_table_setup() {
+		let store = dynamodb_store().await;
+		store.ensure_ttl(false).await.unwrap();
+		assert!(!store.native_ttl());
+		store.ensure_ttl(true).await.unwrap();
+		assert!(store.native_ttl());
+
+		// A table set up elsewhere, whose TTL is only read.
+		let other = DynamoDbStore::new(store.client.clone(), store.table_name.clone());
+		other.ensure_ttl(false).await.unwrap();
+		assert!(other.native_ttl());
+		assert_eq!(other.purge_expired().await.unwrap(), 0);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn backfills_global_version_monotonically() {
+		let store = dynamodb_store().await;
+		store.backfill_global_version("store", 5).await.unwrap();
+		assert_eq!(store.global_version("store").await.unwrap(), 5);
+		store.backfill_global_version("store", 3).await.unwrap();
+		assert_eq!(store.global_version("store").await.unwrap(), 5);
+		put(&store, "store", "k", 0, b"a").await.unwrap();
+		assert_eq!(store.global_version("store").await.unwrap(), 6);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn gets_versions_of_existing_keys() {
+		let store = dynamodb_store().await;
+		put(&store, "store", "a", 0, b"a").await.unwrap();
+		put(&store, "store", "a", 1, &vec![0; 3 * MAX_INLINE_VALUE_SIZE]).await.unwrap();
+		put(&store, "store", "b", 0, b"b").await.unwrap();
+		let keys = ["a", "b", "c", "a"].map(String::from).to_vec();
+		let versions = store.get_versions("store", keys).await.unwrap();
+		assert_eq!(versions, HashMap::from([("a".to_string(), 2), ("b".to_string(), 1)]));
+	}
+}
@@ -0,0 +1,263 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use axum::async_trait;
+use axum::Router;
+use axum::body::Body;
+use axum::http::Request;
+use axum::middleware;
+use tonic::Status;
+use tonic::server::NamedService;
+use tower::Service;
+
+use crate::api::{StoreRequest, store_error_code};
+use crate::audit::{ANONYMOUS_PRINCIPAL, with_principal};
+use crate::metrics::track_http_metrics;
+use crate::store::{KvStore, validate_store_id};
+use crate::telemetry::{record_store_id, trace_request};
+use crate::tls::ClientIdentity;
+use crate::types::vss_service_server::{VssService, VssServiceServer};
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, ErrorCode, GetObjectRequest, GetObjectResponse, GetObjectVersionRequest, GetObjectVersionResponse, GetObjectsRequest, GetObjectsResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, PutObjectRequest, PutObjectResponse};
+
+tokio::task_local! {
+	// `ErrorCode` of the failed request being handled, set by the `VssService` methods.
+	static ERROR_CODE: OnceLock<ErrorCode>;
+}
+
+/// Routes of the gRPC `VssService`, backed by `kvstore`. They can be merged into the HTTP router,
+/// gRPC requests being told apart by their path, or served on their own.
+///
+/// Request messages larger than `max_message_size` are rejected with `OUT_OF_RANGE`, and calls not
+/// answered within `request_timeout` fail with `DEADLINE_EXCEEDED`.
+///
+/// Like the HTTP handlers, the `ErrorCode` of failed requests is added to the response extensions
+/// for the metrics and tracing middlewares.
+pub fn router(kvstore: Arc<dyn KvStore>, max_message_size: usize, request_timeout: Duration) -> Router {
+	let server = VssServiceServer::new(GrpcService { kvstore }).max_decoding_message_size(max_message_size);
+	let service = tower::service_fn(move |request: Request<Body>| {
+		let mut server = server.clone();
+		ERROR_CODE.scope(OnceLock::new(), async move {
+			let mut response = match tokio::time::timeout(request_timeout, server.call(request)).await {
+				Ok(response) => response?,
+				Err(_) => {
+					tracing::info!(timeout_ms = request_timeout.as_millis() as u64, "Request did not complete within the deadline");
+					set_error_code(ErrorCode::RequestTimeoutException);
+					Status::deadline_exceeded(format!("Request did not complete within {}ms", request_timeout.as_millis())).to_http()
+				}
+			};
+			if let Some(code) = ERROR_CODE.with(|code| code.get().copied()) {
+				response.extensions_mut().insert(code);
+			}
+			Ok::<_, Infallible>(response)
+		})
+	});
+	let path = |method: &str| format!("/{}/{}", VssServiceServer::<GrpcService>::NAME, method);
+	Router::new()
+		.route_service(&path("GetObject"), service.clone())
+		.route_service(&path("GetObjects"), service.clone())
+		.route_service(&path("PutObject"), service.clone())
+		.route_service(&path("DeleteObject"), service.clone())
+		.route_service(&path("ListKeyVersions"), service.clone())
+		.route_service(&path("ListObjectVersions"), service.clone())
+		.route_service(&path("GetObjectVersion"), service)
+		.route_layer(middleware::from_fn(track_http_metrics))
+		.route_layer(middleware::from_fn(trace_request))
+}
+
+/// Implementation of the `VssService` generated from `vss.proto`.
+struct GrpcService {
+	kvstore: Arc<dyn KvStore>,
+}
+
+#[async_trait]
+impl VssService for GrpcService {
+	async fn get_object(&self, request: tonic::Request<GetObjectRequest>) -> Result<tonic::Response<GetObjectResponse>, Status> {
+		unary(request, |request| self.kvstore.get(request), "Failed to get object").await
+	}
+	async fn get_objects(&self, request: tonic::Request<GetObjectsRequest>) -> Result<tonic::Response<GetObjectsResponse>, Status> {
+		unary(request, |request| self.kvstore.get_objects(request), "Failed to get objects").await
+	}
+	async fn put_object(&self, request: tonic::Request<PutObjectRequest>) -> Result<tonic::Response<PutObjectResponse>, Status> {
+		unary(request, |request| self.kvstore.put(request), "Failed to put object").await
+	}
+	async fn delete_object(&self, request: tonic::Request<DeleteObjectRequest>) -> Result<tonic::Response<DeleteObjectResponse>, Status> {
+		unary(request, |request| self.kvstore.delete(request), "Failed to delete object").await
+	}
+	async fn list_key_versions(&self, request: tonic::Request<ListKeyVersionsRequest>) -> Result<tonic::Response<ListKeyVersionsResponse>, Status> {
+		unary(request, |request| self.kvstore.list_key_versions(request), "Failed to list key versions").await
+	}
+	async fn list_object_versions(&self, request: tonic::Request<ListObjectVersionsRequest>) -> Result<tonic::Response<ListObjectVersionsResponse>, Status> {
+		unary(request, |request| self.kvstore.list_object_versions(request), "Failed to list object versions").await
+	}
+	async fn get_object_version(&self, request: tonic::Request<GetObjectVersionRequest>) -> Result<tonic::Response<GetObjectVersionResponse>, Status> {
+		unary(request, |request| self.kvstore.get_object_version(request), "Failed to get object version").await
+	}
+}
+
+/// Validates and authorizes a unary gRPC `request`, then passes it to `call` on behalf of the client,
+/// mapping its error to a gRPC status.
+async fn unary<Req, Resp, F, Fut>(request: tonic::Request<Req>, call: F, message: &'static str) -> Result<tonic::Response<Resp>, Status>
+where
+	Req: StoreRequest,
+	F: FnOnce(Req) -> Fut,
+	Fut: Future<Output = std::io::Result<Resp>>,
+{
+	let identity = request.extensions().get::<ClientIdentity>().cloned();
+	let request = request.into_inner();
+	record_store_id(request.store_id());
+	if let Err(err) = validate_store_id(request.store_id()) {
+		set_error_code(ErrorCode::InvalidRequestException);
+		return Err(Status::invalid_argument(err.to_string()));
+	}
+	if identity.as_ref().is_some_and(|identity| !identity.may_access(request.store_id())) {
+		set_error_code(ErrorCode::PermissionDeniedException);
+		return Err(Status::permission_denied("Client is not authorized to access this store_id"));
+	}
+	let principal = identity.map_or_else(|| ANONYMOUS_PRINCIPAL.to_string(), |identity| identity.subject);
+
+	match with_principal(principal, call(request)).await {
+		Ok(response) => Ok(tonic::Response::new(response)),
+		Err(err) => {
+			let code = store_error_code(&err);
+			set_error_code(code);
+			Err(map_store_error(err, code, message))
+		}
+	}
+}
+
+fn set_error_code(code: ErrorCode) {
+	ERROR_CODE.try_with(|error_code| error_code.set(code).ok()).ok();
+}
+
+/// Maps an error returned by the `KvStore` to a gRPC status. As with `ErrorResponse`s, internal
+/// errors are reported with `message` to avoid leaking internals.
+fn map_store_error(err: std::io::Error, error_code: ErrorCode, message: &str) -> Status {
+	if error_code == ErrorCode::InternalServerException {
+		tracing::error!(error = ?err, "{}", message);
+		return Status::internal(message);
+	}
+	tracing::info!(error = %err, "{}", message);
+	match error_code {
+		ErrorCode::ConflictException => Status::aborted(err.to_string()),
+		ErrorCode::InvalidRequestException => Status::invalid_argument(err.to_string()),
+		ErrorCode::NoSuchKeyException => Status::not_found(err.to_string()),
+		ErrorCode::AuthException => Status::unauthenticated(err.to_string()),
+		ErrorCode::PermissionDeniedException => Status::permission_denied(err.to_string()),
+		ErrorCode::PayloadTooLargeException => Status::resource_exhausted(err.to_string()),
+		ErrorCode::RequestTimeoutException => Status::deadline_exceeded(err.to_string()),
+		ErrorCode::InternalServerException | ErrorCode::Unknown => Status::internal(message),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use ::prost::Message;
+	use axum::body::HttpBody;
+	use axum::http::HeaderMap;
+	use tower::ServiceExt;
+
+	use super::*;
+	use crate::testing::{MemoryStore, kv};
+
+	/// Outcome of a gRPC call: its response or status code, along with the `ErrorCode` recorded.
+	struct Call<Resp> {
+		result: Result<Resp, tonic::Code>,
+		error_code: Option<ErrorCode>,
+	}
+
+	async fn call<Req: Message, Resp: Message + Default>(router: &Router, method: &str, request: Req) -> Call<Resp> {
+		call_as(router, method, request, None).await
+	}
+
+	/// Makes a call on behalf of the client authenticated with `identity`, if any.
+	async fn call_as<Req: Message, Resp: Message + Default>(router: &Router, method: &str, request: Req, identity: Option<ClientIdentity>) -> Call<Resp> {
+		let message = request.encode_to_vec();
+		let mut frame = vec![0];
+		frame.extend((message.len() as u32).to_be_bytes());
+		frame.extend(message);
+		let mut request = Request::post(format!("/vss.VssService/{}", method))
+			.header("content-type", "application/grpc")
+			.header("te", "trailers")
+			.body(Body::from(frame))
+			.unwrap();
+		if let Some(identity) = identity {
+			request.extensions_mut().insert(identity);
+		}
+		let response = router.clone().oneshot(request).await.unwrap();
+		let error_code = response.extensions().get::<ErrorCode>().copied();
+		let headers = response.headers().clone();
+		let mut body = response.into_body();
+		let mut data = Vec::new();
+		while let Some(chunk) = body.data().await {
+			data.extend(chunk.unwrap());
+		}
+		// Failed calls are answered with their status in the headers, others in the trailers.
+		let trailers = body.trailers().await.unwrap().unwrap_or_else(HeaderMap::new);
+		let status = Status::from_header_map(&headers).or_else(|| Status::from_header_map(&trailers)).unwrap();
+		let result = match status.code() {
+			tonic::Code::Ok => Ok(Resp::decode(&data[5..]).unwrap()),
+			code => Err(code),
+		};
+		Call { result, error_code }
+	}
+
+	#[tokio::test]
+	async fn serves_vss_service() {
+		let router = router(Arc::new(MemoryStore::default()), 1024, Duration::from_secs(10));
+		let request = PutObjectRequest { store_id: "store".to_string(), transaction_items: vec![kv("k", 0, b"v")], ..Default::default() };
+		let put: Call<PutObjectResponse> = call(&router, "PutObject", request).await;
+		assert_eq!(put.result.unwrap().global_version, Some(1));
+
+		let request = GetObjectRequest { store_id: "store".to_string(), key: "k".to_string(), ..Default::default() };
+		let get: Call<GetObjectResponse> = call(&router, "GetObject", request).await;
+		assert_eq!(get.result.unwrap().value, Some(kv("k", 1, b"v")));
+		assert_eq!(get.error_code, None);
+	}
+
+	#[tokio::test]
+	async fn times_out_slow_calls() {
+		let router = router(Arc::new(MemoryStore::with_get_latency(Duration::from_secs(60))), 1024, Duration::from_millis(10));
+		let request = GetObjectRequest { store_id: "store".to_string(), key: "k".to_string(), ..Default::default() };
+		let get: Call<GetObjectResponse> = call(&router, "GetObject", request).await;
+		assert_eq!(get.result, Err(tonic::Code::DeadlineExceeded));
+		assert_eq!(get.error_code, Some(ErrorCode::RequestTimeoutException));
+	}
+
+	#[tokio::test]
+	async fn maps_errors_to_status_codes() {
+		let router = router(Arc::new(MemoryStore::default()), 1024, Duration::from_secs(10));
+		let get = |store_id: &str, key: &str| GetObjectRequest { store_id: store_id.to_string(), key: key.to_string(), ..Default::default() };
+
+		let invalid: Call<GetObjectResponse> = call(&router, "GetObject", get("store\0history", "k")).await;
+		assert_eq!(invalid.result, Err(tonic::Code::InvalidArgument));
+		assert_eq!(invalid.error_code, Some(ErrorCode::InvalidRequestException));
+
+		let missing: Call<GetObjectResponse> = call(&router, "GetObject", get("store", "k")).await;
+		assert_eq!(missing.result, Err(tonic::Code::NotFound));
+		assert_eq!(missing.error_code, Some(ErrorCode::NoSuchKeyException));
+
+		let request = PutObjectRequest { store_id: "store".to_string(), transaction_items: vec![kv("k", 5, b"v")], ..Default::default() };
+		let conflict: Call<PutObjectResponse> = call(&router, "PutObject", request).await;
+		assert_eq!(conflict.result, Err(tonic::Code::Aborted));
+		assert_eq!(conflict.error_code, Some(ErrorCode::ConflictException));
+
+		let request = PutObjectRequest { store_id: "store".to_string(), transaction_items: vec![kv("k", 0, &[0; 2048])], ..Default::default() };
+		let oversized: Call<PutObjectResponse> = call(&router, "PutObject", request).await;
+		assert_eq!(oversized.result, Err(tonic::Code::OutOfRange));
+	}
+
+	#[tokio::test]
+	async fn authorizes_clients_per_store() {
+		let router = router(Arc::new(MemoryStore::default()), 1024, Duration::from_secs(10));
+		let identity = ClientIdentity::allowed("CN=wallet-1", &["store-a"]);
+		let request = |store_id: &str| PutObjectRequest { store_id: store_id.to_string(), transaction_items: vec![kv("k", 0, b"v")], ..Default::default() };
+
+		let allowed: Call<PutObjectResponse> = call_as(&router, "PutObject", request("store-a"), Some(identity.clone())).await;
+		assert!(allowed.result.is_ok());
+		let denied: Call<PutObjectResponse> = call_as(&router, "PutObject", request("store-b"), Some(identity)).await;
+		assert_eq!(denied.result, Err(tonic::Code::PermissionDenied));
+		assert_eq!(denied.error_code, Some(ErrorCode::PermissionDeniedException));
+	}
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Response, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use tokio::sync::watch;
+
+use crate::store::{HealthStatus, KvStore};
+
+#[derive(Clone)]
+struct HealthState {
+	kvstore: Arc<dyn KvStore>,
+	readiness_timeout: Duration,
+	shutdown: watch::Receiver<bool>,
+}
+
+/// Routes for orchestrator probes:
+/// * `/health/live` responds as long as the server is running.
+/// * `/health/ready` checks the `KvStore` backend within `readiness_timeout`, and responds with
+///   `503 Service Unavailable` if it is unavailable or too slow. A degraded backend is still
+///   reported as ready, with the reason in the body. Once shutdown has been requested through
+///   `shutdown`, it responds with `503 Service Unavailable` while in-flight requests drain, so that
+///   load balancers stop routing traffic to the instance.
+pub fn router(kvstore: Arc<dyn KvStore>, readiness_timeout: Duration, shutdown: watch::Receiver<bool>) -> Router {
+	Router::new()
+		.route("/health/live", get(live))
+		.route("/health/ready", get(ready))
+		.with_state(HealthState { kvstore, readiness_timeout, shutdown })
+}
+
+async fn live() -> impl IntoResponse {
+	Response::builder().status(StatusCode::OK).body(Body::from("live")).unwrap()
+}
+
+async fn ready(State(state): State<HealthState>) -> impl IntoResponse {
+	if *state.shutdown.borrow() {
+		return Response::builder().status(StatusCode::SERVICE_UNAVAILABLE).body(Body::from("unavailable: shutting down")).unwrap();
+	}
+	let (status, body) = match tokio::time::timeout(state.readiness_timeout, state.kvstore.check_health()).await {
+		Ok(Ok(HealthStatus::Healthy)) => (StatusCode::OK, "ready".to_string()),
+		Ok(Ok(HealthStatus::Degraded(reason))) => {
+			tracing::warn!(reason, "Backend is degraded");
+			(StatusCode::OK, format!("degraded: {}", reason))
+		}
+		Ok(Err(err)) => {
+			tracing::warn!(error = %err, "Backend is unavailable");
+			(StatusCode::SERVICE_UNAVAILABLE, "unavailable: backend check failed".to_string())
+		}
+		Err(_) => {
+			tracing::warn!(timeout_ms = state.readiness_timeout.as_millis() as u64, "Backend health check timed out");
+			(StatusCode::SERVICE_UNAVAILABLE, "unavailable: backend check timed out".to_string())
+		}
+	};
+	Response::builder().status(status).body(Body::from(body)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use axum::http::Request;
+	use tower::ServiceExt;
+
+	use crate::testing::MemoryStore;
+
+	async fn get(router: &Router, path: &str) -> (StatusCode, String) {
+		let response = router.clone().oneshot(Request::get(path).body(Body::empty()).unwrap()).await.unwrap();
+		let status = response.status();
+		let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+		(status, String::from_utf8(body.to_vec()).unwrap())
+	}
+
+	fn health_router(kvstore: MemoryStore) -> (Router, watch::Sender<bool>) {
+		let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+		(router(Arc::new(kvstore), Duration::from_millis(50), shutdown_receiver), shutdown_sender)
+	}
+
+	#[tokio::test]
+	async fn reports_backend_readiness() {
+		let (router, _shutdown) = health_router(MemoryStore::default());
+		assert_eq!(get(&router, "/health/live").await, (StatusCode::OK, "live".to_string()));
+		assert_eq!(get(&router, "/health/ready").await, (StatusCode::OK, "ready".to_string()));
+
+		let degraded = MemoryStore::with_health(Ok(HealthStatus::Degraded("throttled".to_string())), Duration::ZERO);
+		let (router, _shutdown) = health_router(degraded);
+		assert_eq!(get(&router, "/health/ready").await, (StatusCode::OK, "degraded: throttled".to_string()));
+
+		let (router, _shutdown) = health_router(MemoryStore::with_health(Err("unreachable".to_string()), Duration::ZERO));
+		assert_eq!(get(&router, "/health/ready").await, (StatusCode::SERVICE_UNAVAILABLE, "unavailable: backend check failed".to_string()));
+		assert_eq!(get(&router, "/health/live").await.0, StatusCode::OK);
+
+		let (router, _shutdown) = health_router(MemoryStore::with_health(Ok(HealthStatus::Healthy), Duration::from_secs(60)));
+		assert_eq!(get(&router, "/health/ready").await, (StatusCode::SERVICE_UNAVAILABLE, "unavailable: backend check timed out".to_string()));
+	}
+
+	#[tokio::test]
+	async fn reports_unavailable_while_draining() {
+		let (router, shutdown) = health_router(MemoryStore::default());
+		assert_eq!(get(&router, "/health/ready").await.0, StatusCode::OK);
+
+		shutdown.send(true).unwrap();
+		assert_eq!(get(&router, "/health/ready").await, (StatusCode::SERVICE_UNAVAILABLE, "unavailable: shutting down".to_string()));
+		assert_eq!(get(&router, "/health/live").await.0, StatusCode::OK);
+	}
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::async_trait;
+
+use crate::config::HistoryConfig;
+use crate::store::{HealthStatus, KvStore, get_all_objects, is_conflict, list_all_key_versions};
+use crate::telemetry::loggable_store_id;
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, ItemExpiry, KeyValue, GetObjectVersionRequest, GetObjectVersionResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, ObjectVersion, PutObjectRequest, PutObjectResponse, StoreMetadata};
+
+const HISTORY_SUFFIX: &str = "\0history";
+// Store listing the stores holding previous versions which expire, so that the reaper does not have
+// to scan the backend. Keys are store ids and versions the time of their latest recorded version.
+const HISTORY_STORES_ID: &str = "\0history";
+const REPLACED: &str = "replaced";
+const DELETED: &str = "deleted";
+
+/// Error returned by the version history APIs when history is not enabled.
+pub fn history_not_enabled() -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidInput, "Version history is not enabled")
+}
+
+/// Whether `store_id` holds the previous versions of the keys of another store.
+pub fn is_history_store_id(store_id: &str) -> bool {
+	store_id.ends_with(HISTORY_SUFFIX)
+}
+
+fn history_store_id(store_id: &str) -> String {
+	format!("{}{}", store_id, HISTORY_SUFFIX)
+}
+
+/// Key of a previous version of `key`. Versions superseded within the same millisecond are told
+/// apart by their own version.
+fn history_key(key: &str, superseded_at: i64, version: i64, deleted: bool) -> String {
+	format!("{}\0{:020}\0{:020}\0{}", key, superseded_at, version, if deleted { DELETED } else { REPLACED })
+}
+
+/// A previous version of a key, as recorded in the history store.
+struct HistoryEntry {
+	key: String,
+	history_key: String,
+	version: ObjectVersion,
+}
+
+impl HistoryEntry {
+	fn parse(kv: KeyValue) -> Option<Self> {
+		let mut parts = kv.key.rsplitn(4, '\0');
+		let deleted = match parts.next()? {
+			DELETED => true,
+			REPLACED => false,
+			_ => return None,
+		};
+		// The version superseded, also that of the entry.
+		parts.next()?.parse::<i64>().ok()?;
+		let superseded_at = parts.next()?.parse().ok()?;
+		let key = parts.next()?.to_string();
+		Some(Self { key, version: ObjectVersion { version: kv.version, superseded_at, deleted }, history_key: kv.key })
+	}
+}
+
+fn now_millis() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as i64
+}
+
+/// A `KvStore` retaining the versions of keys overwritten or deleted through it, as configured by
+/// [`HistoryConfig`].
+///
+/// Previous versions are kept by the `backend` in a separate store id, so they are neither listed
+/// nor counted against the limits of the store, and are written and pruned bypassing the wrappers
+/// in `inner` which act on writes made by clients. They are recorded once the write has been
+/// applied; failing to record them does not fail the write.
+pub struct HistoryStore {
+	inner: Arc<dyn KvStore>,
+	backend: Arc<dyn KvStore>,
+	config: HistoryConfig,
+}
+
+impl HistoryStore {
+	pub fn new(inner: Arc<dyn KvStore>, backend: Arc<dyn KvStore>, config: HistoryConfig) -> Self {
+		Self { inner, backend, config }
+	}
+
+	async fn record(&self, store_id: &str, superseded: Vec<(KeyValue, bool)>) {
+		if superseded.is_empty() {
+			return;
+		}
+		let superseded_at = now_millis();
+		let keys: Vec<String> = superseded.iter().map(|(kv, _)| kv.key.clone()).collect();
+		let entries = superseded.into_iter()
+			.map(|(kv, deleted)| KeyValue { key: history_key(&kv.key, superseded_at, kv.version, deleted), ..kv })
+			.collect();
+		// Registered first, so that the reaper finds every version which expires.
+		if self.config.max_age.is_some() {
+			let registration = KeyValue { key: store_id.to_string(), version: superseded_at, value: Vec::new() };
+			if let Err(err) = self.backend.backfill(HISTORY_STORES_ID.to_string(), vec![registration], Vec::new()).await {
+				tracing::warn!(store_id = loggable_store_id(store_id), error = %err, "Failed to record previous versions");
+				return;
+			}
+		}
+		if let Err(err) = self.backend.backfill(history_store_id(store_id), entries, Vec::new()).await {
+			tracing::warn!(store_id = loggable_store_id(store_id), error = %err, "Failed to record previous versions");
+			return;
+		}
+		for key in keys {
+			if let Err(err) = self.prune(store_id, &key).await {
+				tracing::warn!(store_id = loggable_store_id(store_id), error = %err, "Failed to prune previous versions");
+			}
+		}
+	}
+
+	/// Lists the previous versions of `key`, most recently superseded first, including expired ones.
+	async fn entries(&self, store_id: &str, key: &str) -> std::io::Result<Vec<HistoryEntry>> {
+		let mut entries = self.list_entries(store_id, Some(format!("{}\0", key))).await?;
+		entries.retain(|entry| entry.key == key);
+		entries.sort_by_key(|entry| std::cmp::Reverse((entry.version.superseded_at, entry.version.version)));
+		Ok(entries)
+	}
+
+	async fn list_entries(&self, store_id: &str, key_prefix: Option<String>) -> std::io::Result<Vec<HistoryEntry>> {
+		let mut entries = Vec::new();
+		let mut page_token = None;
+		loop {
+			let response = self.backend.list_key_versions(ListKeyVersionsRequest {
+				store_id: history_store_id(store_id),
+				key_prefix: key_prefix.clone(),
+				page_token,
+				..Default::default()
+			}).await?;
+			entries.extend(response.key_versions.into_iter().filter_map(HistoryEntry::parse));
+			match response.next_page_token {
+				Some(token) if !token.is_empty() => page_token = Some(token),
+				_ => return Ok(entries),
+			}
+		}
+	}
+
+	fn is_expired(&self, entry: &HistoryEntry, now: i64) -> bool {
+		self.config.max_age.is_some_and(|max_age| now - entry.version.superseded_at > max_age.as_millis() as i64)
+	}
+
+	async fn retained_entries(&self, store_id: &str, key: &str) -> std::io::Result<Vec<HistoryEntry>> {
+		let now = now_millis();
+		let mut entries = self.entries(store_id, key).await?;
+		entries.retain(|entry| !self.is_expired(entry, now));
+		if let Some(max_versions) = self.config.max_versions {
+			entries.truncate(max_versions);
+		}
+		Ok(entries)
+	}
+
+	async fn prune(&self, store_id: &str, key: &str) -> std::io::Result<()> {
+		let now = now_millis();
+		let max_versions = self.config.max_versions.unwrap_or(usize::MAX);
+		let entries = self.entries(store_id, key).await?;
+		for (index, entry) in entries.into_iter().enumerate() {
+			if index >= max_versions || self.is_expired(&entry, now) {
+				self.remove_entry(store_id, entry.history_key).await?;
+			}
+		}
+		Ok(())
+	}
+
+	async fn remove_entry(&self, store_id: &str, history_key: String) -> std::io::Result<()> {
+		self.backend.delete(DeleteObjectRequest {
+			store_id: history_store_id(store_id),
+			key_value: Some(KeyValue { key: history_key, version: -1, value: Vec::new() }),
+		}).await?;
+		Ok(())
+	}
+
+	/// Prunes the previous versions past [`HistoryConfig::max_age`] of all stores, returning the
+	/// number of versions pruned. Writes only prune the versions of the keys they write to.
+	pub async fn reap(&self) -> std::io::Result<usize> {
+		let now = now_millis();
+		let mut pruned = 0;
+		for store in list_all_key_versions(self.backend.as_ref(), HISTORY_STORES_ID).await? {
+			let mut remaining = 0;
+			for entry in self.list_entries(&store.key, None).await? {
+				if self.is_expired(&entry, now) {
+					self.remove_entry(&store.key, entry.history_key).await?;
+					pruned += 1;
+				} else {
+					remaining += 1;
+				}
+			}
+			if remaining == 0 {
+				// Only succeeds if no version of the store was recorded since it was listed.
+				let result = self.backend.delete(DeleteObjectRequest {
+					store_id: HISTORY_STORES_ID.to_string(),
+					key_value: Some(KeyValue { key: store.key.clone(), version: store.version, value: Vec::new() }),
+				}).await;
+				match result {
+					Err(err) if !is_conflict(&err) => return Err(err),
+					_ => {}
+				}
+			}
+		}
+		Ok(pruned)
+	}
+}
+
+/// Runs [`HistoryStore::reap`] every [`HistoryConfig::prune_interval`].
+pub async fn run_reaper(store: Arc<HistoryStore>) {
+	let mut interval = tokio::time::interval(store.config.prune_interval);
+	interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+	loop {
+		interval.tick().await;
+		match store.reap().await {
+			Ok(0) => {}
+			Ok(pruned) => tracing::info!(pruned, "Pruned previous versions past their maximum age"),
+			Err(err) => tracing::warn!(error = %err, "Failed to prune previous versions"),
+		}
+	}
+}
+
+#[async_trait]
+impl KvStore for HistoryStore {
+	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
+		self.inner.get(request).await
+	}
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+		self.inner.get_objects(request).await
+	}
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+		self.inner.get_versions(store_id, keys).await
+	}
+	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+		let store_id = request.store_id.clone();
+		let deleted_keys: Vec<&str> = request.delete_items.iter().map(|kv| kv.key.as_str()).collect();
+		let keys = request.transaction_items.iter().chain(&request.delete_items).map(|kv| kv.key.clone()).collect();
+		let current_values = get_all_objects(self.inner.as_ref(), &store_id, keys).await?;
+		let superseded = current_values.into_iter()
+			.map(|kv| {
+				let deleted = deleted_keys.contains(&kv.key.as_str());
+				(kv, deleted)
+			})
+			.collect();
+
+		let response = self.inner.put(request).await?;
+		self.record(&store_id, superseded).await;
+		Ok(response)
+	}
+	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
+		let store_id = request.store_id.clone();
+		let current_value = match &request.key_value {
+			Some(kv) => get_all_objects(self.inner.as_ref(), &store_id, vec![kv.key.clone()]).await?.pop(),
+			None => None,
+		};
+
+		let response = self.inner.delete(request).await?;
+		self.record(&store_id, current_value.into_iter().map(|kv| (kv, true)).collect()).await;
+		Ok(response)
+	}
+	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+		self.inner.list_key_versions(request).await
+	}
+	async fn list_object_versions(&self, request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+		let entries = self.retained_entries(&request.store_id, &request.key).await?;
+		Ok(ListObjectVersionsResponse { versions: entries.into_iter().map(|entry| entry.version).collect() })
+	}
+	async fn get_object_version(&self, request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+		let not_found = || std::io::Error::new(std::io::ErrorKind::NotFound, format!("No retained version {} of the requested key", request.version));
+		let entry = self.retained_entries(&request.store_id, &request.key).await?
+			.into_iter()
+			.find(|entry| entry.version.version == request.version)
+			.ok_or_else(not_found)?;
+		let response = self.backend.get(GetObjectRequest {
+			store_id: history_store_id(&request.store_id),
+			key: entry.history_key,
+			..Default::default()
+		}).await?;
+		// The entry may have been pruned since it was listed.
+		let value = response.value.ok_or_else(not_found)?;
+		Ok(GetObjectVersionResponse { value: Some(KeyValue { key: request.key, ..value }) })
+	}
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+		self.inner.global_version(store_id).await
+	}
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+		self.inner.backfill(store_id, key_values, item_expiries).await
+	}
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+		self.inner.backfill_global_version(store_id, global_version).await
+	}
+	async fn purge_expired(&self) -> std::io::Result<usize> {
+		self.inner.purge_expired().await
+	}
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+		self.inner.list_stores(request).await
+	}
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+		self.inner.store_metadata(store_id).await
+	}
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+		let deleted = self.inner.delete_store(store_id).await?;
+		self.backend.delete_store(&history_store_id(store_id)).await?;
+		self.backend.delete(DeleteObjectRequest {
+			store_id: HISTORY_STORES_ID.to_string(),
+			key_value: Some(KeyValue { key: store_id.to_string(), version: -1, value: Vec::new() }),
+		}).await?;
+		Ok(deleted)
+	}
+	async fn check_health(&self) -> std::io::Result<HealthStatus> {
+		self.inner.check_health().await
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::SoftDeleteConfig;
+	use crate::soft_delete::SoftDeleteStore;
+	use crate::testing::{MemoryStore, delete, put};
+
+	fn list_request(key: &str) -> ListObjectVersionsRequest {
+		ListObjectVersionsRequest { store_id: "store".to_string(), key: key.to_string() }
+	}
+
+	#[tokio::test]
+	async fn retains_and_prunes_previous_versions() {
+		let backend: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		let config = HistoryConfig { max_versions: Some(2), max_age: None, prune_interval: Duration::from_secs(60) };
+		let store = HistoryStore::new(Arc::clone(&backend), backend, config);
+		for version in 0..3 {
+			put(&store, "store", "k", version, format!("v{}", version).as_bytes()).await.unwrap();
+		}
+		delete(&store, "store", "k", 3).await.unwrap();
+
+		let versions = store.list_object_versions(list_request("k")).await.unwrap().versions;
+		assert_eq!(versions.iter().map(|version| (version.version, version.deleted)).collect::<Vec<_>>(), vec![(3, true), (2, false)]);
+		let response = store.get_object_version(GetObjectVersionRequest { store_id: "store".to_string(), key: "k".to_string(), version: 2 }).await.unwrap();
+		assert_eq!(response.value.unwrap().value, b"v1");
+	}
+
+	#[tokio::test]
+	async fn reaps_expired_versions_of_keys_not_written_again() {
+		let backend: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		let config = HistoryConfig { max_versions: None, max_age: Some(Duration::from_millis(1)), prune_interval: Duration::from_secs(60) };
+		let store = HistoryStore::new(Arc::clone(&backend), Arc::clone(&backend), config);
+		for key in ["a", "b"] {
+			put(&store, "store", key, 0, b"v").await.unwrap();
+			put(&store, "store", key, 1, b"v").await.unwrap();
+		}
+		tokio::time::sleep(Duration::from_millis(5)).await;
+
+		assert_eq!(store.reap().await.unwrap(), 2);
+		let history = backend.list_key_versions(ListKeyVersionsRequest { store_id: history_store_id("store"), ..Default::default() }).await.unwrap();
+		assert!(history.key_versions.is_empty());
+		assert!(list_all_key_versions(backend.as_ref(), HISTORY_STORES_ID).await.unwrap().is_empty());
+		assert_eq!(store.reap().await.unwrap(), 0);
+	}
+
+	#[tokio::test]
+	async fn prunes_previous_versions_without_soft_deleting_them() {
+		let backend: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		let soft_delete_config = SoftDeleteConfig { retention: Duration::from_secs(60), reap_interval: Duration::from_secs(60) };
+		let soft_delete: Arc<dyn KvStore> = Arc::new(SoftDeleteStore::new(Arc::clone(&backend), Arc::clone(&backend), soft_delete_config));
+		let config = HistoryConfig { max_versions: Some(1), max_age: None, prune_interval: Duration::from_secs(60) };
+		let store = HistoryStore::new(soft_delete, Arc::clone(&backend), config);
+		for version in 0..3 {
+			put(&store, "store", "k", version, b"v").await.unwrap();
+		}
+
+		assert_eq!(store.list_object_versions(list_request("k")).await.unwrap().versions.len(), 1);
+		let deleted_history = backend.list_key_versions(ListKeyVersionsRequest {
+			store_id: format!("{}\0deleted", history_store_id("store")),
+			..Default::default()
+		}).await.unwrap();
+		assert!(deleted_history.key_versions.is_empty());
+	}
+}
@@ -0,0 +1,104 @@
+//! Serde helpers for fields whose proto3 JSON mapping differs from their Rust type, referenced
+//! from the attributes generated into `types.rs`.
+//!
+//! Ref: https://protobuf.dev/programming-guides/proto3/#json
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// JSON value parsed as a number, which proto3 JSON allows as either a string or a number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StringOrNumber {
+	String(String),
+	Number(i64),
+}
+
+impl StringOrNumber {
+	fn parse<E: serde::de::Error>(self) -> Result<i64, E> {
+		match self {
+			StringOrNumber::String(value) => value.parse().map_err(E::custom),
+			StringOrNumber::Number(value) => Ok(value),
+		}
+	}
+}
+
+/// `int64` fields, written as strings.
+pub mod int64 {
+	use super::*;
+
+	pub fn serialize<S: Serializer>(value: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(value)
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+		StringOrNumber::deserialize(deserializer)?.parse()
+	}
+}
+
+/// `optional int64` fields, written as strings.
+pub mod optional_int64 {
+	use super::*;
+
+	pub fn serialize<S: Serializer>(value: &Option<i64>, serializer: S) -> Result<S::Ok, S::Error> {
+		match value {
+			Some(value) => serializer.collect_str(value),
+			None => serializer.serialize_none(),
+		}
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+		Option::<StringOrNumber>::deserialize(deserializer)?.map(StringOrNumber::parse).transpose()
+	}
+}
+
+/// `bytes` fields, written in standard base64 with padding. Both the standard and URL-safe
+/// alphabets are accepted, with or without padding.
+pub mod bytes {
+	use base64::Engine;
+	use base64::alphabet;
+	use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+
+	use super::*;
+
+	const DECODE_CONFIG: GeneralPurposeConfig = GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+	const STANDARD: GeneralPurpose = GeneralPurpose::new(&alphabet::STANDARD, DECODE_CONFIG);
+	const URL_SAFE: GeneralPurpose = GeneralPurpose::new(&alphabet::URL_SAFE, DECODE_CONFIG);
+
+	pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(value))
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		STANDARD.decode(&value).or_else(|_| URL_SAFE.decode(&value)).map_err(serde::de::Error::custom)
+	}
+}
+
+/// `ErrorCode` fields, written as the name of the enum value. Unknown values are written as numbers.
+pub mod error_code {
+	use super::*;
+	use crate::types::ErrorCode;
+
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum NameOrNumber {
+		Name(String),
+		Number(i32),
+	}
+
+	pub fn serialize<S: Serializer>(value: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+		match ErrorCode::from_i32(*value) {
+			Some(error_code) => serializer.serialize_str(error_code.as_str_name()),
+			None => serializer.serialize_i32(*value),
+		}
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+		match NameOrNumber::deserialize(deserializer)? {
+			NameOrNumber::Name(name) => ErrorCode::from_str_name(&name)
+				.map(i32::from)
+				.ok_or_else(|| serde::de::Error::custom(format!("unknown ErrorCode {}", name))),
+			NameOrNumber::Number(value) => Ok(value),
+		}
+	}
+}
@@ -1,35 +1,345 @@
+use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
+use aws_config::SdkConfig;
 use aws_sdk_dynamodb::Client;
-use axum::Router;
-use axum::routing::post;
+use axum::{BoxError, Router};
+use axum::error_handling::HandleErrorLayer;
+use axum::http::StatusCode;
+use axum::extract::DefaultBodyLimit;
+use axum::middleware;
+use axum::routing::{get, post};
+use tokio::sync::watch;
+use tower::ServiceBuilder;
+use tower_http::compression::CompressionLayer;
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::decompression::RequestDecompressionLayer;
 
-use crate::api::{delete_object, get_object, list_key_versions, put_object};
+use crate::api::{delete_object, enforce_deadline, get_object, get_object_version, get_objects, list_key_versions, list_object_versions, put_object};
+use crate::audit::{AuditSink, AuditStore, DynamoDbSink, FileSink};
+use crate::cache::CachingStore;
+use crate::changes::{ChangeBus, NotifyingStore};
+use crate::config::{AuditLogConfig, ChangeFeed, Config, MigrationConfig, TableSetup, TlsConfig};
 use crate::dynamodb_store::DynamoDbStore;
+use crate::metrics::{metrics, track_compression, track_http_metrics};
+use crate::history::HistoryStore;
+use crate::migration::MigratingStore;
+use crate::snapshot::Snapshots;
+use crate::soft_delete::SoftDeleteStore;
+use crate::store::KvStore;
+use crate::telemetry::{loggable_store_id, trace_request};
 
 pub(crate) mod api;
+pub(crate) mod config;
+#[allow(dead_code, clippy::doc_lazy_continuation)]
 pub(crate) mod types;
 pub(crate) mod store;
 pub(crate) mod dynamodb_store;
+pub(crate) mod migration;
+pub(crate) mod metrics;
+pub(crate) mod telemetry;
+pub(crate) mod health;
+pub(crate) mod tls;
+pub(crate) mod grpc;
+pub(crate) mod json;
+pub(crate) mod changes;
+pub(crate) mod streams;
+pub(crate) mod history;
+pub(crate) mod soft_delete;
+pub(crate) mod snapshot;
+pub(crate) mod admin;
+pub(crate) mod audit;
+pub(crate) mod cache;
+#[cfg(test)]
+pub(crate) mod testing;
+
+const MIGRATION_RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() {
-	let shared_config = aws_config::from_env().endpoint_url("http://localhost:8000").load().await;
-	let client = Client::new(&shared_config);
+	let config = Config::from_env().expect("Failed to load configuration");
+	telemetry::init(&config.telemetry).expect("Failed to set up telemetry");
+	let sdk_config = aws_sdk_config(config.dynamodb_endpoint.as_deref()).await;
+	let client = Client::new(&sdk_config);
 
 	// Wrap DynamoDbBackend in Arc (Atomic Reference Counter) for sharing across threads
-	let store = Arc::new(DynamoDbStore::new(client));
+	let dynamodb_store = Arc::new(DynamoDbStore::new(client, config.dynamodb_table.clone()));
+	setup_table(&dynamodb_store, &config, config.change_feed == ChangeFeed::DynamoDbStreams).await;
+	let stream_arn = match config.change_feed {
+		ChangeFeed::DynamoDbStreams => Some(dynamodb_store.latest_stream_arn().await.expect("Failed to find the stream of the DynamoDB table")),
+		ChangeFeed::Local => None,
+	};
+	let store: Arc<dyn KvStore> = match config.migration {
+		Some(ref migration_config) => start_migration(dynamodb_store, migration_config, &config).await,
+		None => dynamodb_store,
+	};
+	// Below the other wrappers, so that their own writes keep it up to date.
+	let store: Arc<dyn KvStore> = match config.cache {
+		Some(ref cache_config) => Arc::new(CachingStore::new(store, cache_config.clone())),
+		None => store,
+	};
+	let backend = Arc::clone(&store);
+	let soft_delete_store = config.soft_delete.clone().map(|soft_delete_config| {
+		let soft_delete_store = Arc::new(SoftDeleteStore::new(Arc::clone(&store), Arc::clone(&backend), soft_delete_config));
+		tokio::spawn(soft_delete::run_reaper(Arc::clone(&soft_delete_store)));
+		soft_delete_store
+	});
+	let store: Arc<dyn KvStore> = match soft_delete_store {
+		Some(ref soft_delete_store) => Arc::clone(soft_delete_store) as Arc<dyn KvStore>,
+		None => store,
+	};
+	let store: Arc<dyn KvStore> = match config.history {
+		Some(ref history_config) => {
+			let history_store = Arc::new(HistoryStore::new(store, Arc::clone(&backend), history_config.clone()));
+			if history_config.max_age.is_some() {
+				tokio::spawn(history::run_reaper(Arc::clone(&history_store)));
+			}
+			history_store
+		}
+		None => store,
+	};
+	let change_bus = Arc::new(ChangeBus::new(config.change_buffer_size));
+	let store: Arc<dyn KvStore> = match stream_arn {
+		Some(stream_arn) => {
+			// DynamoDB Local serves the Streams API on the endpoint of the DynamoDB API, if one is set.
+			let streams_client = aws_sdk_dynamodbstreams::Client::new(&sdk_config);
+			tokio::spawn(streams::consume(streams_client, stream_arn, Arc::clone(&store), Arc::clone(&change_bus)));
+			store
+		}
+		None => Arc::new(NotifyingStore::new(store, Arc::clone(&change_bus))),
+	};
+	let audit_store = match config.audit_log {
+		Some(ref audit_log_config) => Some(Arc::new(open_audit_log(Arc::clone(&store), audit_log_config, &sdk_config, &config).await.expect("Failed to open audit log"))),
+		None => None,
+	};
+	let store: Arc<dyn KvStore> = match audit_store {
+		Some(ref audit_store) => Arc::clone(audit_store) as Arc<dyn KvStore>,
+		None => store,
+	};
+
+	if let Some(expiry_sweep_interval) = config.expiry_sweep_interval {
+		tokio::spawn(sweep_expired_items(Arc::clone(&store), expiry_sweep_interval));
+	}
 
+	let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+	tokio::spawn(async move {
+		shutdown_signal().await;
+		tracing::info!("Shutdown signal received, draining in-flight requests");
+		shutdown_sender.send(true).ok();
+	});
+
+	let snapshots = Arc::new(Snapshots::new(backend, Arc::clone(&store)));
 	let app = Router::new()
-		.route("/getObject", post(get_object))
-		.route("/putObjects", post(put_object))
-		.route("/listKeyVersions", post(list_key_versions))
-		.route("/deleteObject", post(delete_object))
-		.with_state(store);
-
-	let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-	axum::Server::bind(&addr)
-		.serve(app.into_make_service())
-		.await.unwrap();
+		.route("/getObject", post(get_object).layer(DefaultBodyLimit::max(config.limits.max_body_size)))
+		.route("/getObjects", post(get_objects).layer(DefaultBodyLimit::max(config.limits.max_body_size)))
+		.route("/putObjects", post(put_object).layer(ServiceBuilder::new()
+			// Errors are those of the handler, which are infallible.
+			.layer(HandleErrorLayer::new(|_: BoxError| async { StatusCode::INTERNAL_SERVER_ERROR }))
+			.layer(RequestDecompressionLayer::new())
+			// Applies to the decompressed body.
+			.layer(DefaultBodyLimit::max(config.limits.max_put_body_size))))
+		.route("/listKeyVersions", post(list_key_versions).layer(DefaultBodyLimit::max(config.limits.max_body_size)))
+		.route("/deleteObject", post(delete_object).layer(DefaultBodyLimit::max(config.limits.max_body_size)))
+		.route("/listObjectVersions", post(list_object_versions).layer(DefaultBodyLimit::max(config.limits.max_body_size)))
+		.route("/getObjectVersion", post(get_object_version).layer(DefaultBodyLimit::max(config.limits.max_body_size)))
+		.route_layer(middleware::from_fn_with_state(config.limits.request_timeout, enforce_deadline))
+		.route_layer(middleware::from_fn(track_http_metrics))
+		.route_layer(middleware::from_fn(trace_request))
+		.layer(CompressionLayer::new()
+			.compress_when(SizeAbove::new(config.compression_min_size).and(NotForContentType::GRPC)))
+		.layer(middleware::from_fn(track_compression))
+		.route("/metrics", get(metrics))
+		.with_state(Arc::clone(&store))
+		.merge(health::router(Arc::clone(&store), config.readiness_timeout, shutdown_receiver.clone()))
+		.merge(changes::router(change_bus, shutdown_receiver.clone()))
+		.merge(snapshot::router(Arc::clone(&snapshots), config.limits.max_body_size));
+	let app = match config.admin_token.clone() {
+		Some(admin_token) => app.merge(admin::router(Arc::clone(&store), soft_delete_store, audit_store, snapshots, admin_token, config.limits.max_body_size)),
+		None => app,
+	};
+	// gRPC requests are told apart by their path, so they can share the HTTP listener.
+	let grpc_router = grpc::router(store, config.limits.max_put_body_size, config.limits.request_timeout);
+	let (app, grpc_app) = match config.grpc_bind_address {
+		Some(grpc_bind_address) => (app, Some((grpc_router, grpc_bind_address))),
+		None => (app.merge(grpc_router), None),
+	};
+
+	let http_server = serve(app, config.bind_address, config.tls.as_ref(), shutdown_requested(shutdown_receiver.clone()));
+	let grpc_server = grpc_app.map(|(grpc_app, grpc_bind_address)| {
+		serve(grpc_app, grpc_bind_address, config.tls.as_ref(), shutdown_requested(shutdown_receiver.clone()))
+	});
+	let server = async {
+		match grpc_server {
+			Some(grpc_server) => tokio::try_join!(http_server, grpc_server).map(|_| ()),
+			None => http_server.await,
+		}
+	};
+	let drain_deadline = async {
+		shutdown_requested(shutdown_receiver).await;
+		tokio::time::sleep(config.shutdown_timeout).await;
+	};
+	tokio::select! {
+		result = server => result.unwrap(),
+		_ = drain_deadline => tracing::warn!("In-flight requests did not complete within the shutdown timeout, exiting anyway"),
+	}
+
+	tracing::info!("Server stopped");
+	telemetry::shutdown();
+}
+
+/// Serves `app` on `bind_address`, over TLS if configured, until `shutdown` completes and in-flight
+/// requests are handled.
+async fn serve(
+	app: Router, bind_address: SocketAddr, tls_config: Option<&TlsConfig>, shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+	match tls_config {
+		Some(tls_config) => tls::serve(app, bind_address, tls_config, shutdown).await,
+		None => axum::Server::bind(&bind_address)
+			.serve(app.into_make_service())
+			.with_graceful_shutdown(shutdown)
+			.await
+			.map_err(std::io::Error::other),
+	}
+}
+
+/// Completes on SIGTERM (sent by orchestrators on deploys) or Ctrl-C.
+async fn shutdown_signal() {
+	let ctrl_c = async {
+		tokio::signal::ctrl_c().await.expect("Failed to listen for Ctrl-C");
+	};
+	#[cfg(unix)]
+	let terminate = async {
+		tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+			.expect("Failed to listen for SIGTERM")
+			.recv()
+			.await;
+	};
+	#[cfg(not(unix))]
+	let terminate = std::future::pending::<()>();
+
+	tokio::select! {
+		_ = ctrl_c => {},
+		_ = terminate => {},
+	}
+}
+
+/// Completes once shutdown has been requested through `receiver`.
+async fn shutdown_requested(mut receiver: watch::Receiver<bool>) {
+	// An error means the sender is gone, in which case no shutdown will ever be requested.
+	if receiver.wait_for(|requested| *requested).await.is_err() {
+		std::future::pending::<()>().await;
+	}
+}
+
+/// Verifies, and creates if configured, the table of `store`, with a stream if `stream` is set.
+/// Exits the process if the table is unusable.
+///
+/// Whether the table has native TTL is read whatever the table setup, so that tables set up
+/// beforehand are not swept needlessly.
+async fn setup_table(store: &DynamoDbStore, config: &Config, stream: bool) {
+	let create = config.dynamodb_table_setup == TableSetup::Create;
+	if config.dynamodb_table_setup != TableSetup::None {
+		if let Err(err) = store.ensure_table(create, config.dynamodb_throughput, stream).await {
+			tracing::error!(error = %err, "DynamoDB table setup failed");
+			std::process::exit(1);
+		}
+	}
+	if let Err(err) = store.ensure_ttl(create).await {
+		tracing::warn!(error = %err, "Failed to read TTL of DynamoDB table, assuming it is disabled");
+	}
+	if !store.native_ttl() && config.expiry_sweep_interval.is_none() {
+		tracing::info!("TTL is not enabled on `expires_at`, expired items are hidden but only removed if VSS_EXPIRY_SWEEP_INTERVAL_SECS is set");
+	}
+}
+
+/// Wraps `store` in an [`AuditStore`] appending to the configured audit log, failing if the log was
+/// tampered with.
+async fn open_audit_log(store: Arc<dyn KvStore>, audit_log_config: &AuditLogConfig, sdk_config: &SdkConfig, config: &Config) -> std::io::Result<AuditStore> {
+	let sink: Arc<dyn AuditSink> = match audit_log_config {
+		AuditLogConfig::File(path) => Arc::new(FileSink::open(path).await?),
+		AuditLogConfig::DynamoDb { table_name, chain_id } => {
+			let sink = DynamoDbSink::new(Client::new(sdk_config), table_name.clone(), chain_id.clone());
+			if config.dynamodb_table_setup != TableSetup::None {
+				sink.ensure_table(config.dynamodb_table_setup == TableSetup::Create).await?;
+			}
+			Arc::new(sink)
+		}
+	};
+	AuditStore::new(store, sink).await
+}
+
+async fn aws_sdk_config(endpoint: Option<&str>) -> SdkConfig {
+	let mut loader = aws_config::from_env();
+	if let Some(endpoint) = endpoint {
+		loader = loader.endpoint_url(endpoint);
+	}
+	loader.load().await
+}
+
+/// Wraps `source` in a [`MigratingStore`] dual-writing to the configured target, and backfills and
+/// reconciles the configured stores in the background.
+async fn start_migration(source: Arc<dyn KvStore>, migration_config: &MigrationConfig, config: &Config) -> Arc<dyn KvStore> {
+	let target_client = Client::new(&aws_sdk_config(migration_config.target_dynamodb_endpoint.as_deref()).await);
+	let target = Arc::new(DynamoDbStore::new(target_client, migration_config.target_table.clone()));
+	setup_table(&target, config, false).await;
+	let migrating_store = Arc::new(MigratingStore::new(source, target));
+
+	let migrator = Arc::clone(&migrating_store);
+	let store_ids = migration_config.store_ids.clone();
+	let repair = migration_config.repair;
+	tokio::spawn(async move {
+		for store_id in &store_ids {
+			match migrator.backfill_store(store_id).await {
+				Ok(count) => tracing::info!(store_id = loggable_store_id(store_id), count, "Backfilled store"),
+				Err(err) => {
+					tracing::error!(store_id = loggable_store_id(store_id), error = ?err, "Failed to backfill store");
+					continue;
+				}
+			}
+			reconcile(&migrator, store_id, repair).await;
+		}
+
+		// Stores whose dual-writes failed are reconciled again until they converge.
+		let mut interval = tokio::time::interval(MIGRATION_RECONCILE_INTERVAL);
+		loop {
+			interval.tick().await;
+			for store_id in migrator.divergent_keys().into_keys() {
+				reconcile(&migrator, &store_id, repair).await;
+			}
+		}
+	});
+	migrating_store
+}
+
+/// Purges the expired items of `store` every `interval`.
+async fn sweep_expired_items(store: Arc<dyn KvStore>, interval: Duration) {
+	let mut interval = tokio::time::interval(interval);
+	interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+	loop {
+		interval.tick().await;
+		match store.purge_expired().await {
+			Ok(0) => {}
+			Ok(purged) => tracing::info!(purged, "Purged expired items"),
+			Err(err) => tracing::warn!(error = %err, "Failed to purge expired items"),
+		}
+	}
+}
+
+async fn reconcile(migrator: &MigratingStore, store_id: &str, repair: bool) {
+	match migrator.reconcile_store(store_id, repair).await {
+		Ok(report) if report.is_consistent() => {
+			tracing::info!(store_id = loggable_store_id(&report.store_id), "Store is consistent with migration target")
+		}
+		Ok(report) => tracing::warn!(
+			store_id = loggable_store_id(&report.store_id),
+			missing_in_target = report.missing_in_target.len(),
+			extra_in_target = report.extra_in_target.len(),
+			version_mismatches = report.version_mismatches.len(),
+			repaired = report.repaired,
+			"Store diverges from migration target",
+		),
+		Err(err) => tracing::error!(store_id = loggable_store_id(store_id), error = ?err, "Failed to reconcile store"),
+	}
 }
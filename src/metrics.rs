@@ -0,0 +1,285 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::LazyLock;
+use std::task::{Context, Poll, ready};
+use std::time::Instant;
+
+use axum::body::{Body, BoxBody, Bytes, HttpBody};
+use axum::extract::MatchedPath;
+use axum::http::header::CONTENT_ENCODING;
+use axum::http::{HeaderMap, Request, Response, StatusCode};
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use hyper::body::SizeHint;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use tracing::Instrument;
+
+use crate::types::ErrorCode;
+
+/// Prometheus metrics exposed on `/metrics`.
+///
+/// Conflict rates can be derived from `vss_http_errors_total{error_code="CONFLICT_EXCEPTION"}`
+/// relative to `vss_http_requests_total` of the same endpoint.
+pub struct Metrics {
+	registry: Registry,
+	http_requests: IntCounterVec,
+	http_request_duration: HistogramVec,
+	http_errors: IntCounterVec,
+	http_request_size: HistogramVec,
+	http_response_size: HistogramVec,
+	http_response_compression_ratio: HistogramVec,
+	http_compressed_requests: IntCounterVec,
+	backend_call_duration: HistogramVec,
+	cache_lookups: IntCounterVec,
+	cache_size: IntGauge,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+// Payload size buckets from 64 bytes to 16 MB.
+const SIZE_BUCKETS: [f64; 10] = [64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0];
+// Ratios of uncompressed to compressed sizes. Encrypted values hardly compress, listings do well.
+const COMPRESSION_RATIO_BUCKETS: [f64; 9] = [1.0, 1.1, 1.25, 1.5, 2.0, 3.0, 5.0, 10.0, 20.0];
+
+impl Metrics {
+	fn new() -> Self {
+		let registry = Registry::new();
+		let http_requests = IntCounterVec::new(
+			Opts::new("vss_http_requests_total", "Number of HTTP requests handled"),
+			&["endpoint", "status"],
+		).unwrap();
+		let http_request_duration = HistogramVec::new(
+			HistogramOpts::new("vss_http_request_duration_seconds", "Latency of HTTP requests"),
+			&["endpoint"],
+		).unwrap();
+		let http_errors = IntCounterVec::new(
+			Opts::new("vss_http_errors_total", "Number of HTTP requests failed with an ErrorResponse"),
+			&["endpoint", "error_code"],
+		).unwrap();
+		let http_request_size = HistogramVec::new(
+			HistogramOpts::new("vss_http_request_size_bytes", "Size of HTTP request bodies").buckets(SIZE_BUCKETS.to_vec()),
+			&["endpoint"],
+		).unwrap();
+		let http_response_size = HistogramVec::new(
+			HistogramOpts::new("vss_http_response_size_bytes", "Size of HTTP response bodies").buckets(SIZE_BUCKETS.to_vec()),
+			&["endpoint"],
+		).unwrap();
+		let http_response_compression_ratio = HistogramVec::new(
+			HistogramOpts::new("vss_http_response_compression_ratio", "Ratio of uncompressed to compressed size of compressed HTTP response bodies")
+				.buckets(COMPRESSION_RATIO_BUCKETS.to_vec()),
+			&["endpoint", "encoding"],
+		).unwrap();
+		let http_compressed_requests = IntCounterVec::new(
+			Opts::new("vss_http_compressed_requests_total", "Number of HTTP requests received with a compressed body"),
+			&["endpoint", "encoding"],
+		).unwrap();
+		let backend_call_duration = HistogramVec::new(
+			HistogramOpts::new("vss_backend_call_duration_seconds", "Latency of calls to the storage backend"),
+			&["backend", "operation", "outcome"],
+		).unwrap();
+
+		let cache_lookups = IntCounterVec::new(
+			Opts::new("vss_cache_lookups_total", "Number of reads looked up in the cache, by whether the value was cached and still current"),
+			&["outcome"],
+		).unwrap();
+		let cache_size = IntGauge::new("vss_cache_size_bytes", "Size of the values held by the cache").unwrap();
+
+		registry.register(Box::new(http_requests.clone())).unwrap();
+		registry.register(Box::new(http_request_duration.clone())).unwrap();
+		registry.register(Box::new(http_errors.clone())).unwrap();
+		registry.register(Box::new(http_request_size.clone())).unwrap();
+		registry.register(Box::new(http_response_size.clone())).unwrap();
+		registry.register(Box::new(http_response_compression_ratio.clone())).unwrap();
+		registry.register(Box::new(http_compressed_requests.clone())).unwrap();
+		registry.register(Box::new(backend_call_duration.clone())).unwrap();
+		registry.register(Box::new(cache_lookups.clone())).unwrap();
+		registry.register(Box::new(cache_size.clone())).unwrap();
+
+		Self {
+			registry, http_requests, http_request_duration, http_errors, http_request_size, http_response_size,
+			http_response_compression_ratio, http_compressed_requests, backend_call_duration, cache_lookups, cache_size,
+		}
+	}
+}
+
+/// Middleware recording request counts, latencies, payload sizes and errors per endpoint.
+///
+/// Handlers report the `ErrorCode` of failed requests by adding it to the response extensions.
+pub async fn track_http_metrics<B: HttpBody>(request: Request<B>, next: Next<B>) -> impl IntoResponse {
+	let endpoint = match request.extensions().get::<MatchedPath>() {
+		Some(matched_path) => matched_path.as_str().to_string(),
+		None => "unmatched".to_string(),
+	};
+	if let Some(size) = request.body().size_hint().exact() {
+		METRICS.http_request_size.with_label_values(&[&endpoint]).observe(size as f64);
+	}
+
+	let start = Instant::now();
+	let mut response = next.run(request).await;
+	METRICS.http_request_duration.with_label_values(&[&endpoint]).observe(start.elapsed().as_secs_f64());
+
+	METRICS.http_requests.with_label_values(&[&endpoint, response.status().as_str()]).inc();
+	if let Some(size) = response.body().size_hint().exact() {
+		METRICS.http_response_size.with_label_values(&[&endpoint]).observe(size as f64);
+		response.extensions_mut().insert(UncompressedSize(size));
+	}
+	if let Some(error_code) = response.extensions().get::<ErrorCode>() {
+		METRICS.http_errors.with_label_values(&[&endpoint, error_code.as_str_name()]).inc();
+	}
+	response
+}
+
+/// Size of a response body before compression, added to the response extensions by
+/// `track_http_metrics` for `track_compression`.
+#[derive(Clone, Copy)]
+struct UncompressedSize(u64);
+
+/// Middleware recording compressed requests and the compression ratio of responses. Installed
+/// outside the compression layers, so that it sees requests before decompression and responses
+/// after compression.
+///
+/// Compressed responses are measured as they are streamed to the client, and their ratio recorded
+/// once they have been sent in full.
+pub async fn track_compression<B>(request: Request<B>, next: Next<B>) -> Response<BoxBody> {
+	let endpoint = match request.extensions().get::<MatchedPath>() {
+		Some(matched_path) => matched_path.as_str().to_string(),
+		None => "unmatched".to_string(),
+	};
+	if let Some(encoding) = request.headers().get(CONTENT_ENCODING).and_then(|value| value.to_str().ok()) {
+		METRICS.http_compressed_requests.with_label_values(&[&endpoint, encoding]).inc();
+	}
+
+	let response = next.run(request).await;
+	let encoding = match response.headers().get(CONTENT_ENCODING).and_then(|value| value.to_str().ok()) {
+		Some(encoding) => encoding.to_string(),
+		None => return response,
+	};
+	let uncompressed_size = match response.extensions().get::<UncompressedSize>() {
+		Some(UncompressedSize(size)) => *size,
+		None => return response,
+	};
+	response.map(|inner| axum::body::boxed(CompressedBody { inner, endpoint, encoding, uncompressed_size, compressed_size: 0, finished: false }))
+}
+
+/// Body of a compressed response, counting its bytes as they are sent to record its compression
+/// ratio at the end.
+struct CompressedBody {
+	inner: BoxBody,
+	endpoint: String,
+	encoding: String,
+	uncompressed_size: u64,
+	compressed_size: u64,
+	finished: bool,
+}
+
+impl CompressedBody {
+	fn finish(&mut self) {
+		if self.finished {
+			return;
+		}
+		self.finished = true;
+		if self.compressed_size > 0 {
+			let ratio = self.uncompressed_size as f64 / self.compressed_size as f64;
+			METRICS.http_response_compression_ratio.with_label_values(&[&self.endpoint, &self.encoding]).observe(ratio);
+		}
+	}
+}
+
+impl HttpBody for CompressedBody {
+	type Data = Bytes;
+	type Error = axum::Error;
+
+	fn poll_data(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, axum::Error>>> {
+		let data = ready!(Pin::new(&mut self.inner).poll_data(cx));
+		match &data {
+			Some(Ok(chunk)) => {
+				self.compressed_size += chunk.len() as u64;
+				// The server may not poll bodies past their end.
+				if self.inner.is_end_stream() {
+					self.finish();
+				}
+			}
+			Some(Err(_)) => {}
+			None => self.finish(),
+		}
+		Poll::Ready(data)
+	}
+
+	fn poll_trailers(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Option<HeaderMap>, axum::Error>> {
+		Pin::new(&mut self.inner).poll_trailers(cx)
+	}
+
+	fn is_end_stream(&self) -> bool {
+		self.inner.is_end_stream()
+	}
+
+	fn size_hint(&self) -> SizeHint {
+		self.inner.size_hint()
+	}
+}
+
+/// Awaits a call to the storage backend in a tracing span, recording its latency and whether it succeeded.
+pub async fn observe_backend_call<T, E>(backend: &str, operation: &str, call: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+	let start = Instant::now();
+	let result = call.instrument(tracing::info_span!("backend_call", backend, operation)).await;
+	let outcome = if result.is_ok() { "success" } else { "error" };
+	METRICS.backend_call_duration.with_label_values(&[backend, operation, outcome]).observe(start.elapsed().as_secs_f64());
+	result
+}
+
+/// Records a lookup of the cache, whose `outcome` is `hit`, `miss` or `stale`.
+pub fn record_cache_lookup(outcome: &str) {
+	METRICS.cache_lookups.with_label_values(&[outcome]).inc();
+}
+
+pub fn set_cache_size(size: usize) {
+	METRICS.cache_size.set(size as i64);
+}
+
+pub async fn metrics() -> impl IntoResponse {
+	let mut buffer = Vec::new();
+	match TextEncoder::new().encode(&METRICS.registry.gather(), &mut buffer) {
+		Ok(()) => Response::builder()
+			.status(StatusCode::OK)
+			.header("Content-Type", TextEncoder::new().format_type())
+			.body(Body::from(buffer))
+			.unwrap(),
+		Err(err) => {
+			tracing::error!(error = ?err, "Failed to encode metrics");
+			Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::from("Failed to encode metrics")).unwrap()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use axum::Router;
+	use axum::http::header::ACCEPT_ENCODING;
+	use axum::middleware;
+	use axum::routing::get;
+	use tower::ServiceExt;
+	use tower_http::compression::CompressionLayer;
+
+	use super::*;
+
+	#[tokio::test]
+	async fn records_compression_ratio_of_streamed_responses() {
+		let router = Router::new()
+			.route("/compressible", get(|| async { "value ".repeat(10_000) }))
+			.route_layer(middleware::from_fn(track_http_metrics))
+			.layer(CompressionLayer::new())
+			.layer(middleware::from_fn(track_compression));
+		let ratio = METRICS.http_response_compression_ratio.with_label_values(&["/compressible", "gzip"]);
+		let (count, sum) = (ratio.get_sample_count(), ratio.get_sample_sum());
+
+		let request = Request::get("/compressible").header(ACCEPT_ENCODING, "gzip").body(Body::empty()).unwrap();
+		let response = router.oneshot(request).await.unwrap();
+		assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+		// Recorded once the body has been sent.
+		assert_eq!(ratio.get_sample_count(), count);
+		let compressed = hyper::body::to_bytes(response.into_body()).await.unwrap();
+		assert_eq!(ratio.get_sample_count(), count + 1);
+		let expected = 60_000.0 / compressed.len() as f64;
+		assert!((ratio.get_sample_sum() - sum - expected).abs() < 1e-9);
+	}
+}
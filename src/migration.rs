@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use axum::async_trait;
+
+use crate::store::{HealthStatus, KvStore, MAX_GET_OBJECTS_KEYS, list_all_key_versions};
+use crate::telemetry::loggable_store_id;
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, ItemExpiry, KeyValue, GetObjectVersionRequest, GetObjectVersionResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, PutObjectRequest, PutObjectResponse, StoreMetadata};
+
+/// A `KvStore` used while moving data from one backend to another without downtime.
+///
+/// The `source` backend remains the source of truth: all reads are served from it and writes
+/// are applied to it first. Successful writes are then mirrored by replaying them on the `target`
+/// backend with the same version checks, so that writes mirrored out of order fail rather than
+/// leave stale values. Writes skipping version checks cannot be told apart that way, so the values
+/// they wrote are compared with those of `source` once mirrored. Failures to mirror a write, and
+/// differences found, do not fail the request; the affected keys are recorded and fixed by
+/// [`MigratingStore::reconcile_store`].
+///
+/// Cutover is safe once [`MigratingStore::backfill_store`] has completed and
+/// [`MigratingStore::reconcile_store`] reports no divergence for every store.
+pub struct MigratingStore {
+	source: Arc<dyn KvStore>,
+	target: Arc<dyn KvStore>,
+	divergent_keys: Mutex<HashMap<String, HashSet<String>>>,
+}
+
+/// Differences found between the `source` and `target` backends of a store.
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+	pub store_id: String,
+	/// Keys present in `source` but not in `target`.
+	pub missing_in_target: Vec<String>,
+	/// Keys present in `target` but not in `source`.
+	pub extra_in_target: Vec<String>,
+	/// Keys present in both backends with different versions.
+	pub version_mismatches: Vec<String>,
+	/// Keys whose writes could not be mirrored, present in both backends with the same version but
+	/// different values.
+	pub value_mismatches: Vec<String>,
+	/// `global_version` of the store in `source`.
+	pub source_global_version: i64,
+	/// `global_version` of the store in `target`.
+	pub target_global_version: i64,
+	/// Whether the differences above were repaired.
+	pub repaired: bool,
+}
+
+impl ReconcileReport {
+	pub fn is_consistent(&self) -> bool {
+		self.missing_in_target.is_empty() && self.extra_in_target.is_empty() && self.version_mismatches.is_empty()
+			&& self.value_mismatches.is_empty() && self.source_global_version == self.target_global_version
+	}
+}
+
+impl MigratingStore {
+	pub fn new(source: Arc<dyn KvStore>, target: Arc<dyn KvStore>) -> Self {
+		Self { source, target, divergent_keys: Mutex::new(HashMap::new()) }
+	}
+
+	/// Copies all items of `store_id` and its `global_version` from `source` to `target`, returning
+	/// the number of items copied.
+	///
+	/// Writes racing with the backfill may leave stale items or a lower `global_version` in
+	/// `target`, run [`MigratingStore::reconcile_store`] afterwards to detect and repair them.
+	pub async fn backfill_store(&self, store_id: &str) -> std::io::Result<usize> {
+		let global_version = self.source.global_version(store_id).await?;
+		self.target.backfill_global_version(store_id, global_version).await?;
+		let mut copied = 0;
+		let mut page_token = None;
+		loop {
+			let page = self.source.list_key_versions(ListKeyVersionsRequest {
+				store_id: store_id.to_string(),
+				page_token,
+				include_values: true,
+				..Default::default()
+			}).await?;
+			if !page.key_versions.is_empty() {
+				copied += page.key_versions.len();
+				self.target.backfill(store_id.to_string(), page.key_versions, page.item_expiries).await?;
+			}
+			match page.next_page_token {
+				Some(token) if !token.is_empty() => page_token = Some(token),
+				_ => return Ok(copied),
+			}
+		}
+	}
+
+	/// Compares the keys, versions and `global_version` of `store_id` in both backends, and the
+	/// values of the keys whose writes could not be mirrored. If `repair` is set, missing and
+	/// mismatched items are copied again from `source`, extra items are removed from `target` and
+	/// the `global_version` of `target` is raised to that of `source`.
+	pub async fn reconcile_store(&self, store_id: &str, repair: bool) -> std::io::Result<ReconcileReport> {
+		let divergent_keys = self.divergent_keys.lock().unwrap().get(store_id).cloned().unwrap_or_default();
+		let source_global_version = self.source.global_version(store_id).await?;
+		let target_global_version = self.target.global_version(store_id).await?;
+		let (source_versions, source_expiries) = self.list_source_items(store_id).await?;
+		let target_versions: HashMap<String, i64> = list_all_key_versions(self.target.as_ref(), store_id).await?
+			.into_iter().map(|kv| (kv.key, kv.version)).collect();
+
+		let mut report = ReconcileReport { store_id: store_id.to_string(), source_global_version, target_global_version, ..Default::default() };
+		for (key, version) in &source_versions {
+			match target_versions.get(key) {
+				None => report.missing_in_target.push(key.clone()),
+				Some(target_version) if target_version != version => report.version_mismatches.push(key.clone()),
+				Some(_) => {}
+			}
+		}
+		let same_versions: Vec<String> = divergent_keys.into_iter()
+			.filter(|key| source_versions.get(key).is_some_and(|version| target_versions.get(key) == Some(version)))
+			.collect();
+		report.value_mismatches = self.compare_values(store_id, &same_versions).await?;
+		report.extra_in_target = target_versions.keys().filter(|key| !source_versions.contains_key(*key)).cloned().collect();
+
+		if repair {
+			let mut to_copy = report.missing_in_target.clone();
+			to_copy.extend(report.version_mismatches.iter().cloned());
+			to_copy.extend(report.value_mismatches.iter().cloned());
+			self.copy_keys(store_id, &to_copy, &source_expiries).await?;
+			for key in &report.extra_in_target {
+				self.target.delete(non_conditional_delete(store_id, key)).await?;
+			}
+			self.target.backfill_global_version(store_id, source_global_version).await?;
+			report.repaired = true;
+		}
+		if report.repaired || report.is_consistent() {
+			self.divergent_keys.lock().unwrap().remove(store_id);
+		}
+		Ok(report)
+	}
+
+	/// Returns the keys, per `store_id`, whose writes could not be mirrored to `target`, or were
+	/// mirrored with a different value.
+	pub fn divergent_keys(&self) -> HashMap<String, HashSet<String>> {
+		self.divergent_keys.lock().unwrap().clone()
+	}
+
+	/// Lists the versions and expiries of all items of `store_id` in `source`.
+	async fn list_source_items(&self, store_id: &str) -> std::io::Result<(HashMap<String, i64>, HashMap<String, i64>)> {
+		let mut versions = HashMap::new();
+		let mut expiries = HashMap::new();
+		let mut page_token = None;
+		loop {
+			let page = self.source.list_key_versions(ListKeyVersionsRequest {
+				store_id: store_id.to_string(),
+				page_token,
+				..Default::default()
+			}).await?;
+			versions.extend(page.key_versions.into_iter().map(|kv| (kv.key, kv.version)));
+			expiries.extend(page.item_expiries.into_iter().map(|item_expiry| (item_expiry.key, item_expiry.expires_at)));
+			match page.next_page_token {
+				Some(token) if !token.is_empty() => page_token = Some(token),
+				_ => return Ok((versions, expiries)),
+			}
+		}
+	}
+
+	/// Copies `keys` from `source` to `target` in batches of `MAX_GET_OBJECTS_KEYS`, with their
+	/// `expiries` as listed from `source`. Keys deleted since they were listed are skipped, the
+	/// delete is mirrored separately.
+	async fn copy_keys(&self, store_id: &str, keys: &[String], expiries: &HashMap<String, i64>) -> std::io::Result<()> {
+		for keys in keys.chunks(MAX_GET_OBJECTS_KEYS) {
+			let request = GetObjectsRequest { store_id: store_id.to_string(), keys: keys.to_vec() };
+			let key_values = self.source.get_objects(request).await?.values;
+			if key_values.is_empty() {
+				continue;
+			}
+			let item_expiries = key_values.iter()
+				.filter_map(|kv| expiries.get(&kv.key).map(|expires_at| ItemExpiry { key: kv.key.clone(), expires_at: *expires_at }))
+				.collect();
+			self.target.backfill(store_id.to_string(), key_values, item_expiries).await?;
+		}
+		Ok(())
+	}
+
+	async fn get_value(&self, store: &dyn KvStore, store_id: &str, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+		let request = GetObjectRequest { store_id: store_id.to_string(), key: key.to_string(), ..Default::default() };
+		match store.get(request).await {
+			Ok(response) => Ok(response.value.map(|kv| kv.value)),
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+			Err(err) => Err(err),
+		}
+	}
+
+	/// Returns those of `keys` whose values differ in both backends. Used for keys written without
+	/// version checks, whose concurrent writes may have been applied in different orders.
+	async fn compare_values(&self, store_id: &str, keys: &[String]) -> std::io::Result<Vec<String>> {
+		let mut mismatches = Vec::new();
+		for key in keys {
+			let source_value = self.get_value(self.source.as_ref(), store_id, key).await?;
+			if source_value != self.get_value(self.target.as_ref(), store_id, key).await? {
+				mismatches.push(key.clone());
+			}
+		}
+		Ok(mismatches)
+	}
+
+	fn record_divergence(&self, store_id: &str, keys: impl IntoIterator<Item = String>, err: &std::io::Error) {
+		tracing::warn!(store_id = loggable_store_id(store_id), error = ?err, "Failed to mirror write to migration target");
+		self.divergent_keys.lock().unwrap().entry(store_id.to_string()).or_default().extend(keys);
+	}
+}
+
+#[async_trait]
+impl KvStore for MigratingStore {
+	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
+		self.source.get(request).await
+	}
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+		self.source.get_objects(request).await
+	}
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+		self.source.get_versions(store_id, keys).await
+	}
+	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+		let response = self.source.put(request.clone()).await?;
+
+		let store_id = request.store_id.clone();
+		let keys: Vec<String> = request.transaction_items.iter().chain(&request.delete_items).map(|kv| kv.key.clone()).collect();
+		let unconditional: Vec<String> = request.transaction_items.iter().filter(|kv| kv.version == -1).map(|kv| kv.key.clone()).collect();
+		// The `global_version` was checked by `source`, that of `target` is reconciled separately.
+		let mirrored = PutObjectRequest { global_version: None, ..request };
+		match self.target.put(mirrored).await {
+			Ok(_) => match self.compare_values(&store_id, &unconditional).await {
+				Ok(mismatches) if mismatches.is_empty() => {}
+				Ok(mismatches) => {
+					tracing::warn!(store_id = loggable_store_id(&store_id), "Values mirrored to migration target differ from source");
+					self.divergent_keys.lock().unwrap().entry(store_id).or_default().extend(mismatches);
+				}
+				Err(err) => self.record_divergence(&store_id, unconditional, &err),
+			},
+			Err(err) => self.record_divergence(&store_id, keys, &err),
+		}
+		Ok(response)
+	}
+	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
+		let response = self.source.delete(request.clone()).await?;
+
+		let keys: Vec<String> = request.key_value.iter().map(|kv| kv.key.clone()).collect();
+		let store_id = request.store_id.clone();
+		if let Err(err) = self.target.delete(request).await {
+			self.record_divergence(&store_id, keys, &err);
+		}
+		Ok(response)
+	}
+	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+		self.source.list_key_versions(request).await
+	}
+	async fn list_object_versions(&self, request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+		self.source.list_object_versions(request).await
+	}
+	async fn get_object_version(&self, request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+		self.source.get_object_version(request).await
+	}
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+		self.source.global_version(store_id).await
+	}
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+		self.source.backfill(store_id.clone(), key_values.clone(), item_expiries.clone()).await?;
+		self.target.backfill(store_id, key_values, item_expiries).await
+	}
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+		self.source.backfill_global_version(store_id, global_version).await?;
+		self.target.backfill_global_version(store_id, global_version).await
+	}
+	async fn purge_expired(&self) -> std::io::Result<usize> {
+		Ok(self.source.purge_expired().await? + self.target.purge_expired().await?)
+	}
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+		self.source.list_stores(request).await
+	}
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+		self.source.store_metadata(store_id).await
+	}
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+		// Erased from `target` too, so that the data does not resurface after cutover.
+		let deleted = self.source.delete_store(store_id).await?;
+		self.target.delete_store(store_id).await?;
+		self.divergent_keys.lock().unwrap().remove(store_id);
+		Ok(deleted)
+	}
+	async fn check_health(&self) -> std::io::Result<HealthStatus> {
+		let source_status = self.source.check_health().await?;
+		// Requests are still served from `source` while `target` is unavailable, but the affected
+		// writes diverge and need to be reconciled.
+		match self.target.check_health().await {
+			Ok(_) if source_status != HealthStatus::Healthy => Ok(source_status),
+			Ok(HealthStatus::Healthy) => Ok(HealthStatus::Healthy),
+			Ok(HealthStatus::Degraded(reason)) => Ok(HealthStatus::Degraded(format!("Migration target: {}", reason))),
+			Err(err) => Ok(HealthStatus::Degraded(format!("Migration target unavailable: {}", err))),
+		}
+	}
+}
+
+fn non_conditional_delete(store_id: &str, key: &str) -> DeleteObjectRequest {
+	DeleteObjectRequest {
+		store_id: store_id.to_string(),
+		key_value: Some(KeyValue { key: key.to_string(), version: -1, ..Default::default() }),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::{MemoryStore, delete, get, kv, put};
+
+	fn migrating_store(target: Arc<dyn KvStore>) -> (Arc<MemoryStore>, MigratingStore) {
+		let source = Arc::new(MemoryStore::default());
+		let store = MigratingStore::new(Arc::clone(&source) as Arc<dyn KvStore>, target);
+		(source, store)
+	}
+
+	/// Writes `other` to every key written without version checks after the write, like a concurrent
+	/// write mirrored in a different order than it was applied to the source.
+	struct ConcurrentWriter {
+		inner: MemoryStore,
+	}
+
+	#[async_trait]
+	impl KvStore for ConcurrentWriter {
+		async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
+			self.inner.get(request).await
+		}
+		async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+			self.inner.get_objects(request).await
+		}
+		async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+			self.inner.get_versions(store_id, keys).await
+		}
+		async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+			let unconditional: Vec<String> = request.transaction_items.iter().filter(|kv| kv.version == -1).map(|kv| kv.key.clone()).collect();
+			let response = self.inner.put(request.clone()).await?;
+			for key in unconditional {
+				put(&self.inner, &request.store_id, &key, -1, b"other").await?;
+			}
+			Ok(response)
+		}
+		async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
+			self.inner.delete(request).await
+		}
+		async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+			self.inner.list_key_versions(request).await
+		}
+		async fn list_object_versions(&self, request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+			self.inner.list_object_versions(request).await
+		}
+		async fn get_object_version(&self, request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+			self.inner.get_object_version(request).await
+		}
+		async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+			self.inner.global_version(store_id).await
+		}
+		async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+			self.inner.backfill(store_id, key_values, item_expiries).await
+		}
+		async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+			self.inner.backfill_global_version(store_id, global_version).await
+		}
+		async fn purge_expired(&self) -> std::io::Result<usize> {
+			self.inner.purge_expired().await
+		}
+		async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+			self.inner.list_stores(request).await
+		}
+		async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+			self.inner.store_metadata(store_id).await
+		}
+		async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+			self.inner.delete_store(store_id).await
+		}
+		async fn check_health(&self) -> std::io::Result<HealthStatus> {
+			self.inner.check_health().await
+		}
+	}
+
+	#[tokio::test]
+	async fn backfills_and_mirrors_writes() {
+		let target = Arc::new(MemoryStore::default());
+		let (source, store) = migrating_store(Arc::clone(&target) as Arc<dyn KvStore>);
+		put(source.as_ref(), "store", "a", 0, b"a").await.unwrap();
+		put(source.as_ref(), "store", "a", 1, b"b").await.unwrap();
+		let item_expiries = vec![ItemExpiry { key: "b".to_string(), expires_at: i64::MAX }];
+		source.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("b", 0, b"c")],
+			item_expiries: item_expiries.clone(),
+			..Default::default()
+		}).await.unwrap();
+
+		assert_eq!(store.backfill_store("store").await.unwrap(), 2);
+		assert_eq!(target.global_version("store").await.unwrap(), 3);
+		let listed = target.list_key_versions(ListKeyVersionsRequest { store_id: "store".to_string(), ..Default::default() }).await.unwrap();
+		assert_eq!(listed.item_expiries, item_expiries);
+		put(&store, "store", "a", 2, b"d").await.unwrap();
+		delete(&store, "store", "b", 1).await.unwrap();
+		put(&store, "store", "c", -1, b"e").await.unwrap();
+
+		assert_eq!(get(target.as_ref(), "store", "a").await.unwrap(), kv("a", 3, b"d"));
+		assert_eq!(get(target.as_ref(), "store", "b").await.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+		assert_eq!(get(target.as_ref(), "store", "c").await.unwrap(), kv("c", 1, b"e"));
+		assert!(store.divergent_keys().is_empty());
+		let report = store.reconcile_store("store", false).await.unwrap();
+		assert!(report.is_consistent(), "{:?}", report);
+		assert_eq!((report.source_global_version, report.target_global_version), (5, 5));
+	}
+
+	#[tokio::test]
+	async fn backfills_a_page_at_a_time() {
+		let source = Arc::new(MemoryStore::with_page_size(2));
+		let target = Arc::new(MemoryStore::default());
+		let store = MigratingStore::new(Arc::clone(&source) as Arc<dyn KvStore>, Arc::clone(&target) as Arc<dyn KvStore>);
+		for key in ["a", "b", "c", "d", "e"] {
+			put(source.as_ref(), "store", key, 0, key.as_bytes()).await.unwrap();
+		}
+
+		assert_eq!(store.backfill_store("store").await.unwrap(), 5);
+		assert_eq!(source.value_reads(), 0);
+		for key in ["a", "b", "c", "d", "e"] {
+			assert_eq!(get(target.as_ref(), "store", key).await.unwrap(), kv(key, 1, key.as_bytes()));
+		}
+		let report = store.reconcile_store("store", false).await.unwrap();
+		assert!(report.is_consistent(), "{:?}", report);
+	}
+
+	#[tokio::test]
+	async fn records_writes_mirrored_out_of_order() {
+		let target = Arc::new(MemoryStore::default());
+		let (_, store) = migrating_store(Arc::clone(&target) as Arc<dyn KvStore>);
+		put(&store, "store", "k", 0, b"a").await.unwrap();
+		// A later write of the key reaches the target first.
+		put(target.as_ref(), "store", "k", 1, b"stale").await.unwrap();
+
+		put(&store, "store", "k", 1, b"b").await.unwrap();
+		assert_eq!(store.divergent_keys()["store"], HashSet::from(["k".to_string()]));
+		let report = store.reconcile_store("store", true).await.unwrap();
+		assert_eq!(report.value_mismatches, vec!["k".to_string()]);
+		assert_eq!(get(target.as_ref(), "store", "k").await.unwrap(), kv("k", 2, b"b"));
+		assert!(store.reconcile_store("store", false).await.unwrap().is_consistent());
+		assert!(store.divergent_keys().is_empty());
+	}
+
+	#[tokio::test]
+	async fn compares_values_written_without_version_checks() {
+		let target = Arc::new(ConcurrentWriter { inner: MemoryStore::default() });
+		let (_, store) = migrating_store(Arc::clone(&target) as Arc<dyn KvStore>);
+		put(&store, "store", "k", -1, b"a").await.unwrap();
+
+		assert_eq!(store.divergent_keys()["store"], HashSet::from(["k".to_string()]));
+		let report = store.reconcile_store("store", true).await.unwrap();
+		assert_eq!(report.value_mismatches, vec!["k".to_string()]);
+		assert_eq!(get(target.as_ref(), "store", "k").await.unwrap(), kv("k", 1, b"a"));
+	}
+
+	#[tokio::test]
+	async fn reconciles_global_version() {
+		let target = Arc::new(MemoryStore::default());
+		let (source, store) = migrating_store(Arc::clone(&target) as Arc<dyn KvStore>);
+		put(source.as_ref(), "store", "k", 0, b"a").await.unwrap();
+		target.backfill("store".to_string(), vec![kv("k", 1, b"a")], Vec::new()).await.unwrap();
+
+		let report = store.reconcile_store("store", true).await.unwrap();
+		assert!(!report.is_consistent());
+		assert_eq!((report.source_global_version, report.target_global_version), (1, 0));
+		assert_eq!(target.global_version("store").await.unwrap(), 1);
+		// The `global_version` of a backend is never lowered.
+		store.backfill_global_version("store", 0).await.unwrap();
+		assert!(store.reconcile_store("store", false).await.unwrap().is_consistent());
+	}
+}
@@ -0,0 +1,470 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ::prost::Message;
+use axum::body::Bytes;
+use axum::extract::rejection::BytesRejection;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::{Extension, Router, middleware};
+
+use crate::api::handle_request;
+use crate::dynamodb_store::{MAX_TRANSACT_WRITE_ITEMS, MAX_TRANSACTION_VALUE_SIZE};
+use crate::metrics::track_http_metrics;
+use crate::store::{KvStore, conflict_error, list_all_key_versions};
+use crate::telemetry::trace_request;
+use crate::tls::ClientIdentity;
+use crate::types::{CreateSnapshotRequest, CreateSnapshotResponse, DeleteObjectRequest, DeleteSnapshotRequest, DeleteSnapshotResponse, GetObjectRequest, GetSnapshotRequest, GetSnapshotResponse, ItemExpiry, KeyValue, ListKeyVersionsRequest, ListSnapshotsRequest, ListSnapshotsResponse, PutObjectRequest, RestoreSnapshotRequest, RestoreSnapshotResponse, Snapshot};
+
+const SNAPSHOT_SUFFIX: &str = "\0snapshot";
+const SNAPSHOTS_SUFFIX: &str = "\0snapshots";
+// Number of times a snapshot is taken again after writes interleaved with it.
+const MAX_SNAPSHOT_ATTEMPTS: usize = 5;
+
+/// Whether `store_id` holds a snapshot of another store, or lists its snapshots.
+pub fn is_snapshot_store_id(store_id: &str) -> bool {
+	store_id.ends_with(SNAPSHOT_SUFFIX) || store_id.ends_with(SNAPSHOTS_SUFFIX)
+}
+
+// Store holding the items of the snapshot of `store_id` taken at `global_version`.
+fn snapshot_store_id(store_id: &str, global_version: i64) -> String {
+	format!("{}\0{:020}{}", store_id, global_version, SNAPSHOT_SUFFIX)
+}
+
+// Store listing the snapshots of `store_id`, keyed by `snapshot_key` with the encoded `Snapshot` as value.
+fn snapshots_store_id(store_id: &str) -> String {
+	format!("{}{}", store_id, SNAPSHOTS_SUFFIX)
+}
+
+fn snapshot_key(global_version: i64) -> String {
+	format!("{:020}", global_version)
+}
+
+fn now_millis() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as i64
+}
+
+/// Consistent copies of stores, tied to the `global_version` they were taken at.
+///
+/// Snapshots are kept by the `backend` in separate store ids, bypassing the `KvStore` wrappers which
+/// act on writes made by clients. Restores are written through `store`, like those writes.
+pub struct Snapshots {
+	backend: Arc<dyn KvStore>,
+	store: Arc<dyn KvStore>,
+}
+
+impl Snapshots {
+	pub fn new(backend: Arc<dyn KvStore>, store: Arc<dyn KvStore>) -> Self {
+		Self { backend, store }
+	}
+
+	/// Takes a snapshot of the requested store, copying its items to a separate store id.
+	///
+	/// Every put increments the `global_version` of a store and deletes only remove items, so a copy is
+	/// consistent if the `global_version` did not change while it was made and the items of the store
+	/// are still those copied afterwards. Otherwise the copy is discarded and taken again. The store is
+	/// read, copied and verified through the same `KvStore`, so that all see the same writes.
+	pub async fn create(&self, request: CreateSnapshotRequest) -> std::io::Result<CreateSnapshotResponse> {
+		let store = self.backend.as_ref();
+		for _ in 0..MAX_SNAPSHOT_ATTEMPTS {
+			let global_version = store.global_version(&request.store_id).await?;
+			let snapshot_id = snapshot_store_id(&request.store_id, global_version);
+			// Left over by a snapshot taken at the same global version, before some deletes.
+			self.clear(&snapshot_id).await?;
+
+			let copied = self.copy(store, &request.store_id, &snapshot_id).await?;
+			let current: HashMap<String, i64> = list_all_key_versions(store, &request.store_id).await?
+				.into_iter().map(|kv| (kv.key, kv.version)).collect();
+			if current == copied && store.global_version(&request.store_id).await? == global_version {
+				let snapshot = Snapshot { global_version, created_at: now_millis(), item_count: copied.len() as i64 };
+				let entry = KeyValue { key: snapshot_key(global_version), version: 0, value: snapshot.encode_to_vec() };
+				self.backend.backfill(snapshots_store_id(&request.store_id), vec![entry], Vec::new()).await?;
+				return Ok(CreateSnapshotResponse { snapshot: Some(snapshot) });
+			}
+			self.clear(&snapshot_id).await?;
+		}
+		Err(conflict_error("Store kept changing while taking a snapshot".to_string()))
+	}
+
+	/// Copies the items of `store_id` read from `store` to `snapshot_id` with their expiries,
+	/// returning the keys and versions copied.
+	async fn copy(&self, store: &dyn KvStore, store_id: &str, snapshot_id: &str) -> std::io::Result<HashMap<String, i64>> {
+		let mut copied = HashMap::new();
+		let mut page_token = None;
+		loop {
+			let response = store.list_key_versions(ListKeyVersionsRequest {
+				store_id: store_id.to_string(),
+				page_token,
+				include_values: true,
+				..Default::default()
+			}).await?;
+			copied.extend(response.key_versions.iter().map(|kv| (kv.key.clone(), kv.version)));
+			if !response.key_versions.is_empty() {
+				self.backend.backfill(snapshot_id.to_string(), response.key_versions, response.item_expiries).await?;
+			}
+			match response.next_page_token {
+				Some(token) if !token.is_empty() => page_token = Some(token),
+				_ => return Ok(copied),
+			}
+		}
+	}
+
+	async fn clear(&self, store_id: &str) -> std::io::Result<()> {
+		for kv in list_all_key_versions(self.backend.as_ref(), store_id).await? {
+			self.backend.delete(non_conditional_delete(store_id, kv.key)).await?;
+		}
+		Ok(())
+	}
+
+	async fn find(&self, store_id: &str, global_version: i64) -> std::io::Result<Snapshot> {
+		let request = GetObjectRequest { store_id: snapshots_store_id(store_id), key: snapshot_key(global_version), ..Default::default() };
+		let not_found = || std::io::Error::new(std::io::ErrorKind::NotFound, format!("No snapshot at global version {}", global_version));
+		let value = match self.backend.get(request).await {
+			Ok(response) => response.value.ok_or_else(not_found)?,
+			Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Err(not_found()),
+			Err(err) => return Err(err),
+		};
+		Snapshot::decode(value.value.as_slice()).map_err(|err| std::io::Error::other(format!("Failed to decode snapshot: {}", err)))
+	}
+
+	/// Lists the snapshots of the requested store, most recent first.
+	pub async fn list(&self, request: ListSnapshotsRequest) -> std::io::Result<ListSnapshotsResponse> {
+		let mut snapshots = Vec::new();
+		let mut page_token = None;
+		loop {
+			let response = self.backend.list_key_versions(ListKeyVersionsRequest {
+				store_id: snapshots_store_id(&request.store_id),
+				page_token,
+				include_values: true,
+				..Default::default()
+			}).await?;
+			for kv in response.key_versions {
+				snapshots.push(Snapshot::decode(kv.value.as_slice()).map_err(|err| std::io::Error::other(format!("Failed to decode snapshot: {}", err)))?);
+			}
+			match response.next_page_token {
+				Some(token) if !token.is_empty() => page_token = Some(token),
+				_ => break,
+			}
+		}
+		snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.global_version));
+		Ok(ListSnapshotsResponse { snapshots })
+	}
+
+	/// Returns a page of the items of the requested snapshot.
+	pub async fn get(&self, request: GetSnapshotRequest) -> std::io::Result<GetSnapshotResponse> {
+		self.find(&request.store_id, request.global_version).await?;
+		let response = self.backend.list_key_versions(ListKeyVersionsRequest {
+			store_id: snapshot_store_id(&request.store_id, request.global_version),
+			page_size: request.page_size,
+			page_token: request.page_token,
+			include_values: true,
+			..Default::default()
+		}).await?;
+		Ok(GetSnapshotResponse { key_values: response.key_versions, next_page_token: response.next_page_token, item_expiries: response.item_expiries })
+	}
+
+	/// Restores the requested snapshot, writing and deleting items through `store`.
+	///
+	/// Like other writes, those of the restore expect the versions the keys are at, read beforehand,
+	/// and increment them. The restore fails with a conflict if a key is written to concurrently.
+	/// Items are restored with their expiries, those expired since the snapshot was taken being no
+	/// longer part of it.
+	///
+	/// The restore is not atomic. It is written in as few transactions as the backend allows, each
+	/// incrementing the `global_version` once, but a restore which does not fit in a single one is
+	/// left partly applied by a conflict or other failure. Retrying it completes it.
+	pub async fn restore(&self, request: RestoreSnapshotRequest) -> std::io::Result<RestoreSnapshotResponse> {
+		self.find(&request.store_id, request.global_version).await?;
+		let snapshot_id = snapshot_store_id(&request.store_id, request.global_version);
+		let current: HashMap<String, i64> = list_all_key_versions(self.store.as_ref(), &request.store_id).await?
+			.into_iter().map(|kv| (kv.key, kv.version)).collect();
+		let new_batch = || PutObjectRequest { store_id: request.store_id.clone(), ..Default::default() };
+		let mut batch = new_batch();
+		let mut restored = HashSet::new();
+		let mut page_token = None;
+		loop {
+			let response = self.backend.list_key_versions(ListKeyVersionsRequest {
+				store_id: snapshot_id.clone(),
+				page_token,
+				include_values: true,
+				..Default::default()
+			}).await?;
+			let expiries: HashMap<String, i64> = response.item_expiries.into_iter().map(|item_expiry| (item_expiry.key, item_expiry.expires_at)).collect();
+			for kv in response.key_versions {
+				if !fits(&batch, kv.value.len()) {
+					self.write_batch(std::mem::replace(&mut batch, new_batch())).await?;
+				}
+				if let Some(expires_at) = expiries.get(&kv.key) {
+					batch.item_expiries.push(ItemExpiry { key: kv.key.clone(), expires_at: *expires_at });
+				}
+				restored.insert(kv.key.clone());
+				// Keys missing from the store are expected not to exist, at version 0.
+				let version = current.get(&kv.key).copied().unwrap_or(0);
+				batch.transaction_items.push(KeyValue { version, ..kv });
+			}
+			match response.next_page_token {
+				Some(token) if !token.is_empty() => page_token = Some(token),
+				_ => break,
+			}
+		}
+		for (key, version) in current {
+			if !restored.contains(&key) {
+				if !fits(&batch, 0) {
+					self.write_batch(std::mem::replace(&mut batch, new_batch())).await?;
+				}
+				batch.delete_items.push(KeyValue { key, version, value: Vec::new() });
+			}
+		}
+		if !batch.transaction_items.is_empty() || !batch.delete_items.is_empty() {
+			self.write_batch(batch).await?;
+		}
+		Ok(RestoreSnapshotResponse {})
+	}
+
+	/// Writes `batch` through `store`, split in halves for as long as it is rejected as too large,
+	/// e.g. because of the chunks of the large values it replaces.
+	async fn write_batch(&self, batch: PutObjectRequest) -> std::io::Result<()> {
+		let mut pending = vec![batch];
+		while let Some(batch) = pending.pop() {
+			match self.store.put(batch.clone()).await {
+				Ok(_) => {}
+				Err(err) if err.kind() == std::io::ErrorKind::InvalidInput && batch.transaction_items.len() + batch.delete_items.len() > 1 => {
+					let (first, second) = split_batch(batch);
+					pending.push(second);
+					pending.push(first);
+				}
+				Err(err) => return Err(err),
+			}
+		}
+		Ok(())
+	}
+
+	/// Deletes the requested snapshot, if it exists.
+	pub async fn delete(&self, request: DeleteSnapshotRequest) -> std::io::Result<DeleteSnapshotResponse> {
+		// Unlisted first, so that a partially deleted snapshot is never served.
+		self.backend.delete(non_conditional_delete(&snapshots_store_id(&request.store_id), snapshot_key(request.global_version))).await?;
+		self.clear(&snapshot_store_id(&request.store_id, request.global_version)).await?;
+		Ok(DeleteSnapshotResponse {})
+	}
+
+	/// Deletes all snapshots of `store_id`, as part of deleting the store.
+	pub async fn delete_all(&self, store_id: &str) -> std::io::Result<()> {
+		for snapshot in self.list(ListSnapshotsRequest { store_id: store_id.to_string() }).await?.snapshots {
+			self.backend.delete_store(&snapshot_store_id(store_id, snapshot.global_version)).await?;
+		}
+		self.backend.delete_store(&snapshots_store_id(store_id)).await?;
+		Ok(())
+	}
+}
+
+/// Whether an item with a value of `value_size` bytes can be added to `batch` without exceeding the
+/// limits of a single transaction, in which the update of the `global_version` is one more write.
+fn fits(batch: &PutObjectRequest, value_size: usize) -> bool {
+	let batch_value_size: usize = batch.transaction_items.iter().map(|kv| kv.value.len()).sum();
+	batch.transaction_items.len() + batch.delete_items.len() + 2 <= MAX_TRANSACT_WRITE_ITEMS
+		&& batch_value_size + value_size <= MAX_TRANSACTION_VALUE_SIZE
+}
+
+/// Splits the items of `batch` into two batches of about the same size.
+fn split_batch(batch: PutObjectRequest) -> (PutObjectRequest, PutObjectRequest) {
+	let PutObjectRequest { store_id, mut transaction_items, mut delete_items, item_expiries, .. } = batch;
+	let half = (transaction_items.len() + delete_items.len()) / 2;
+	let (second_items, second_deletes) = match half.checked_sub(transaction_items.len()) {
+		None => (transaction_items.split_off(half), std::mem::take(&mut delete_items)),
+		Some(deletes) => (Vec::new(), delete_items.split_off(deletes)),
+	};
+	let (first_expiries, second_expiries) = item_expiries.into_iter()
+		.partition(|item_expiry| transaction_items.iter().any(|kv| kv.key == item_expiry.key));
+	let first = PutObjectRequest { store_id: store_id.clone(), transaction_items, delete_items, item_expiries: first_expiries, ..Default::default() };
+	let second = PutObjectRequest { store_id, transaction_items: second_items, delete_items: second_deletes, item_expiries: second_expiries, ..Default::default() };
+	(first, second)
+}
+
+fn non_conditional_delete(store_id: &str, key: String) -> DeleteObjectRequest {
+	DeleteObjectRequest {
+		store_id: store_id.to_string(),
+		key_value: Some(KeyValue { key, version: -1, value: Vec::new() }),
+	}
+}
+
+/// Routes of the snapshot APIs.
+pub fn router(snapshots: Arc<Snapshots>, max_body_size: usize) -> Router {
+	Router::new()
+		.route("/createSnapshot", post(create_snapshot))
+		.route("/listSnapshots", post(list_snapshots))
+		.route("/getSnapshot", post(get_snapshot))
+		.route("/restoreSnapshot", post(restore_snapshot))
+		.route("/deleteSnapshot", post(delete_snapshot))
+		.layer(DefaultBodyLimit::max(max_body_size))
+		.route_layer(middleware::from_fn(track_http_metrics))
+		.route_layer(middleware::from_fn(trace_request))
+		.with_state(snapshots)
+}
+
+async fn create_snapshot(
+	State(snapshots): State<Arc<Snapshots>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: CreateSnapshotRequest| async move {
+		snapshots.create(request).await
+	}, "Failed to create snapshot").await
+}
+
+async fn list_snapshots(
+	State(snapshots): State<Arc<Snapshots>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: ListSnapshotsRequest| async move {
+		snapshots.list(request).await
+	}, "Failed to list snapshots").await
+}
+
+async fn get_snapshot(
+	State(snapshots): State<Arc<Snapshots>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: GetSnapshotRequest| async move {
+		snapshots.get(request).await
+	}, "Failed to get snapshot").await
+}
+
+async fn restore_snapshot(
+	State(snapshots): State<Arc<Snapshots>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: RestoreSnapshotRequest| async move {
+		snapshots.restore(request).await
+	}, "Failed to restore snapshot").await
+}
+
+async fn delete_snapshot(
+	State(snapshots): State<Arc<Snapshots>>,
+	identity: Option<Extension<ClientIdentity>>,
+	headers: HeaderMap,
+	body: Result<Bytes, BytesRejection>,
+) -> impl IntoResponse {
+	handle_request(&headers, identity, body, |request: DeleteSnapshotRequest| async move {
+		snapshots.delete(request).await
+	}, "Failed to delete snapshot").await
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::store::is_conflict;
+	use crate::testing::{MemoryStore, delete, get, kv, put};
+
+	fn restore_request(global_version: i64) -> RestoreSnapshotRequest {
+		RestoreSnapshotRequest { store_id: "store".to_string(), global_version }
+	}
+
+	#[tokio::test]
+	async fn restores_items_as_new_versions() {
+		let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		let snapshots = Snapshots::new(Arc::clone(&store), Arc::clone(&store));
+		put(store.as_ref(), "store", "a", 0, b"a").await.unwrap();
+		put(store.as_ref(), "store", "b", 0, b"b").await.unwrap();
+		let snapshot = snapshots.create(CreateSnapshotRequest { store_id: "store".to_string() }).await.unwrap().snapshot.unwrap();
+		assert_eq!((snapshot.global_version, snapshot.item_count), (2, 2));
+
+		put(store.as_ref(), "store", "a", 1, b"a2").await.unwrap();
+		delete(store.as_ref(), "store", "b", 1).await.unwrap();
+		put(store.as_ref(), "store", "c", 0, b"c").await.unwrap();
+		snapshots.restore(restore_request(2)).await.unwrap();
+		// Restored in a single transaction.
+		assert_eq!(store.global_version("store").await.unwrap(), 5);
+
+		// Clients holding version 2 of `a` conflict instead of overwriting the restored value.
+		assert_eq!(get(store.as_ref(), "store", "a").await.unwrap(), kv("a", 3, b"a"));
+		assert_eq!(get(store.as_ref(), "store", "b").await.unwrap(), kv("b", 1, b"b"));
+		assert_eq!(get(store.as_ref(), "store", "c").await.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+		assert!(is_conflict(&put(store.as_ref(), "store", "a", 2, b"stale").await.unwrap_err()));
+	}
+
+	#[tokio::test]
+	async fn restores_in_as_few_transactions_as_fit() {
+		let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		let snapshots = Snapshots::new(Arc::clone(&store), Arc::clone(&store));
+		let keys: Vec<String> = (0..150).map(|index| format!("k{:03}", index)).collect();
+		store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: keys.iter().map(|key| kv(key, 0, b"v")).collect(),
+			..Default::default()
+		}).await.unwrap();
+		snapshots.create(CreateSnapshotRequest { store_id: "store".to_string() }).await.unwrap();
+		put(store.as_ref(), "store", "extra", 0, b"v").await.unwrap();
+
+		snapshots.restore(restore_request(1)).await.unwrap();
+		assert_eq!(store.global_version("store").await.unwrap(), 4);
+		let listed = list_all_key_versions(store.as_ref(), "store").await.unwrap();
+		assert_eq!(listed.iter().map(|kv| kv.key.clone()).collect::<Vec<_>>(), keys);
+		assert!(listed.iter().all(|kv| kv.version == 2));
+	}
+
+	#[test]
+	fn splits_batches_in_halves() {
+		let batch = PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("a", 0, b"a"), kv("b", 0, b"b"), kv("c", 0, b"c")],
+			delete_items: vec![kv("d", 1, b"")],
+			item_expiries: vec![ItemExpiry { key: "c".to_string(), expires_at: 1 }],
+			..Default::default()
+		};
+		let (first, second) = split_batch(batch);
+		assert_eq!((first.transaction_items.len(), first.delete_items.len(), first.item_expiries.len()), (2, 0, 0));
+		assert_eq!((second.transaction_items.len(), second.delete_items.len(), second.item_expiries.len()), (1, 1, 1));
+		let (first, second) = split_batch(second);
+		assert_eq!((first.transaction_items, first.item_expiries.len()), (vec![kv("c", 0, b"c")], 1));
+		assert_eq!((second.transaction_items.len(), second.delete_items.len()), (0, 1));
+	}
+
+	#[tokio::test]
+	async fn snapshots_are_consistent_copies() {
+		let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		let snapshots = Snapshots::new(Arc::clone(&store), Arc::clone(&store));
+		put(store.as_ref(), "store", "a", 0, b"a").await.unwrap();
+		snapshots.create(CreateSnapshotRequest { store_id: "store".to_string() }).await.unwrap();
+		put(store.as_ref(), "store", "a", 1, b"a2").await.unwrap();
+
+		let response = snapshots.get(GetSnapshotRequest { store_id: "store".to_string(), global_version: 1, ..Default::default() }).await.unwrap();
+		assert_eq!(response.key_values, vec![kv("a", 1, b"a")]);
+		assert_eq!(snapshots.list(ListSnapshotsRequest { store_id: "store".to_string() }).await.unwrap().snapshots.len(), 1);
+		assert_eq!(snapshots.restore(restore_request(2)).await.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+		snapshots.delete(DeleteSnapshotRequest { store_id: "store".to_string(), global_version: 1 }).await.unwrap();
+		assert!(snapshots.list(ListSnapshotsRequest { store_id: "store".to_string() }).await.unwrap().snapshots.is_empty());
+	}
+
+	#[tokio::test]
+	async fn snapshots_keep_item_expiries() {
+		let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		let snapshots = Snapshots::new(Arc::clone(&store), Arc::clone(&store));
+		let expires_at = now_millis() + 60_000;
+		store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("a", 0, b"a"), kv("b", 0, b"b")],
+			item_expiries: vec![ItemExpiry { key: "a".to_string(), expires_at }],
+			..Default::default()
+		}).await.unwrap();
+		snapshots.create(CreateSnapshotRequest { store_id: "store".to_string() }).await.unwrap();
+
+		let response = snapshots.get(GetSnapshotRequest { store_id: "store".to_string(), global_version: 1, ..Default::default() }).await.unwrap();
+		assert_eq!(response.item_expiries, vec![ItemExpiry { key: "a".to_string(), expires_at }]);
+
+		put(store.as_ref(), "store", "a", 1, b"a2").await.unwrap();
+		snapshots.restore(restore_request(1)).await.unwrap();
+		let listed = store.list_key_versions(ListKeyVersionsRequest { store_id: "store".to_string(), ..Default::default() }).await.unwrap();
+		assert_eq!(listed.item_expiries, vec![ItemExpiry { key: "a".to_string(), expires_at }]);
+	}
+}
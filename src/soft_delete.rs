@@ -0,0 +1,439 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::async_trait;
+use axum::body::Bytes;
+use axum::extract::rejection::BytesRejection;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::post;
+use axum::Router;
+
+use crate::api::handle_admin_request;
+use crate::config::SoftDeleteConfig;
+use crate::store::{HealthStatus, KvStore, conflict_error, get_all_objects, is_conflict, list_all_key_versions, next_version, validate_store_id};
+use crate::telemetry::loggable_store_id;
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, DeletedObject, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, ItemExpiry, KeyValue, GetObjectVersionRequest, GetObjectVersionResponse, ListDeletedObjectsRequest, ListDeletedObjectsResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, PutObjectRequest, PutObjectResponse, StoreMetadata, RestoreObjectRequest, RestoreObjectResponse};
+
+const DELETED_SUFFIX: &str = "\0deleted";
+// Store listing the stores holding deleted items, so that the reaper does not have to scan the
+// backend. Keys are store ids and versions the time of their latest deletion.
+const DELETED_STORES_ID: &str = "\0deleted";
+
+/// Error returned by the soft delete APIs when soft delete is not enabled.
+pub fn soft_delete_not_enabled() -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::InvalidInput, "Soft delete is not enabled")
+}
+
+/// Whether `store_id` holds the deleted items of another store, or lists such stores.
+pub fn is_deleted_store_id(store_id: &str) -> bool {
+	store_id.ends_with(DELETED_SUFFIX)
+}
+
+fn deleted_store_id(store_id: &str) -> String {
+	format!("{}{}", store_id, DELETED_SUFFIX)
+}
+
+/// Key of a deleted item. Items deleted from the same key within the same millisecond are told
+/// apart by their version.
+fn deleted_key(key: &str, deleted_at: i64, version: i64) -> String {
+	format!("{}\0{:020}\0{:020}", key, deleted_at, version)
+}
+
+/// Splits the key of a deleted item into the key it was deleted from and the time of deletion.
+fn parse_deleted_key(deleted_key: &str) -> Option<(&str, i64)> {
+	let mut parts = deleted_key.rsplitn(3, '\0');
+	// The version deleted, also that of the item.
+	parts.next()?.parse::<i64>().ok()?;
+	let deleted_at = parts.next()?.parse().ok()?;
+	Some((parts.next()?, deleted_at))
+}
+
+fn now_millis() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as i64
+}
+
+/// A `KvStore` keeping the items deleted through it for [`SoftDeleteConfig::retention`], during
+/// which they can be restored with [`SoftDeleteStore::restore`].
+///
+/// Deleted items are moved by the `backend` to a separate store id, which hides them from reads and
+/// listings of the store, bypassing the wrappers in `inner` which act on writes made by clients.
+/// They are copied there before being deleted, so a deletion either fails or leaves a restorable
+/// copy. [`SoftDeleteStore::reap`] purges them once expired. Items written with an expiry keep it:
+/// they cannot be restored past it, and are restored with it.
+pub struct SoftDeleteStore {
+	inner: Arc<dyn KvStore>,
+	backend: Arc<dyn KvStore>,
+	config: SoftDeleteConfig,
+}
+
+impl SoftDeleteStore {
+	pub fn new(inner: Arc<dyn KvStore>, backend: Arc<dyn KvStore>, config: SoftDeleteConfig) -> Self {
+		Self { inner, backend, config }
+	}
+
+	fn is_expired(&self, deleted_at: i64, now: i64) -> bool {
+		now - deleted_at > self.config.retention.as_millis() as i64
+	}
+
+	/// Copies the items under `keys` about to be deleted from `store_id`, along with their expiries,
+	/// returning the keys of the copies.
+	async fn retain(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<Vec<String>> {
+		let values = get_all_objects(self.inner.as_ref(), store_id, keys).await?;
+		if values.is_empty() {
+			return Ok(Vec::new());
+		}
+		let deleted_at = now_millis();
+		let mut item_expiries = Vec::new();
+		let mut deleted_values = Vec::with_capacity(values.len());
+		for kv in values {
+			let key = deleted_key(&kv.key, deleted_at, kv.version);
+			if let Some(expires_at) = item_expiry(self.inner.as_ref(), store_id, &kv.key).await? {
+				item_expiries.push(ItemExpiry { key: key.clone(), expires_at });
+			}
+			deleted_values.push(KeyValue { key, ..kv });
+		}
+		let deleted_keys = deleted_values.iter().map(|kv| kv.key.clone()).collect();
+		// Registered first, so that the reaper finds every deleted item.
+		self.backend.backfill(DELETED_STORES_ID.to_string(), vec![KeyValue { key: store_id.to_string(), version: deleted_at, value: Vec::new() }], Vec::new()).await?;
+		self.backend.backfill(deleted_store_id(store_id), deleted_values, item_expiries).await?;
+		Ok(deleted_keys)
+	}
+
+	/// Removes the copies of items whose deletion failed.
+	async fn discard(&self, store_id: &str, deleted_keys: Vec<String>) {
+		for key in deleted_keys {
+			if let Err(err) = self.remove_deleted(store_id, key).await {
+				tracing::warn!(store_id = loggable_store_id(store_id), error = %err, "Failed to discard copy of item not deleted");
+			}
+		}
+	}
+
+	async fn remove_deleted(&self, store_id: &str, deleted_key: String) -> std::io::Result<()> {
+		self.backend.delete(DeleteObjectRequest {
+			store_id: deleted_store_id(store_id),
+			key_value: Some(KeyValue { key: deleted_key, version: -1, value: Vec::new() }),
+		}).await?;
+		Ok(())
+	}
+
+	/// Lists the deleted items of `store_id` which can still be restored, most recently deleted first.
+	pub async fn list_deleted(&self, request: ListDeletedObjectsRequest) -> std::io::Result<ListDeletedObjectsResponse> {
+		let now = now_millis();
+		let mut objects: Vec<DeletedObject> = self.list_all_deleted(&request.store_id, request.key_prefix).await?
+			.into_iter()
+			.filter(|object| !self.is_expired(object.deleted_at, now))
+			.collect();
+		objects.sort_by_key(|object| std::cmp::Reverse(object.deleted_at));
+		Ok(ListDeletedObjectsResponse { objects })
+	}
+
+	async fn list_all_deleted(&self, store_id: &str, key_prefix: Option<String>) -> std::io::Result<Vec<DeletedObject>> {
+		let mut objects = Vec::new();
+		let mut page_token = None;
+		loop {
+			let response = self.backend.list_key_versions(ListKeyVersionsRequest {
+				store_id: deleted_store_id(store_id),
+				key_prefix: key_prefix.clone(),
+				page_token,
+				..Default::default()
+			}).await?;
+			for kv in response.key_versions {
+				if let Some((key, deleted_at)) = parse_deleted_key(&kv.key) {
+					objects.push(DeletedObject { key: key.to_string(), version: kv.version, deleted_at });
+				}
+			}
+			match response.next_page_token {
+				Some(token) if !token.is_empty() => page_token = Some(token),
+				_ => return Ok(objects),
+			}
+		}
+	}
+
+	/// Restores the most recently deleted item under the requested key, writing it through `store`
+	/// so that it is handled like any other write. The key must not have been written to since, and
+	/// starts over from version 1 like other keys written for the first time.
+	pub async fn restore(&self, store: &dyn KvStore, request: RestoreObjectRequest) -> std::io::Result<RestoreObjectResponse> {
+		let not_found = || std::io::Error::new(std::io::ErrorKind::NotFound, "No deleted item to restore under the requested key");
+		let deleted = self.list_deleted(ListDeletedObjectsRequest {
+			store_id: request.store_id.clone(),
+			key_prefix: Some(format!("{}\0", request.key)),
+		}).await?
+			.objects
+			.into_iter()
+			.find(|object| object.key == request.key)
+			.ok_or_else(not_found)?;
+		let deleted_key = deleted_key(&deleted.key, deleted.deleted_at, deleted.version);
+		let value = self.backend.get(GetObjectRequest {
+			store_id: deleted_store_id(&request.store_id),
+			key: deleted_key.clone(),
+			..Default::default()
+		}).await?.value.ok_or_else(not_found)?;
+		let item_expiries = item_expiry(self.backend.as_ref(), &deleted_store_id(&request.store_id), &deleted_key).await?
+			.map(|expires_at| ItemExpiry { key: request.key.clone(), expires_at })
+			.into_iter()
+			.collect();
+
+		// Version 0 requires the key not to exist.
+		let result = store.put(PutObjectRequest {
+			store_id: request.store_id.clone(),
+			transaction_items: vec![KeyValue { key: request.key.clone(), version: 0, value: value.value.clone() }],
+			item_expiries,
+			..Default::default()
+		}).await;
+		match result {
+			Err(err) if is_conflict(&err) => return Err(conflict_error("Key was written to since it was deleted".to_string())),
+			result => result?,
+		};
+		self.remove_deleted(&request.store_id, deleted_key).await?;
+		Ok(RestoreObjectResponse { value: Some(KeyValue { key: request.key, version: next_version(0), value: value.value }) })
+	}
+
+	/// Purges the deleted items past their retention, returning the number of items purged.
+	pub async fn reap(&self) -> std::io::Result<usize> {
+		let now = now_millis();
+		let mut purged = 0;
+		for store in list_all_key_versions(self.backend.as_ref(), DELETED_STORES_ID).await? {
+			let mut remaining = 0;
+			for object in self.list_all_deleted(&store.key, None).await? {
+				if self.is_expired(object.deleted_at, now) {
+					self.remove_deleted(&store.key, deleted_key(&object.key, object.deleted_at, object.version)).await?;
+					purged += 1;
+				} else {
+					remaining += 1;
+				}
+			}
+			if remaining == 0 {
+				// Only succeeds if no item was deleted from the store since it was listed.
+				let result = self.backend.delete(DeleteObjectRequest {
+					store_id: DELETED_STORES_ID.to_string(),
+					key_value: Some(KeyValue { key: store.key.clone(), version: store.version, value: Vec::new() }),
+				}).await;
+				match result {
+					Err(err) if !is_conflict(&err) => return Err(err),
+					_ => {}
+				}
+			}
+		}
+		Ok(purged)
+	}
+}
+
+/// Returns the expiry of the item under `key` in `store_id`, if it exists and was written with one.
+async fn item_expiry(store: &dyn KvStore, store_id: &str, key: &str) -> std::io::Result<Option<i64>> {
+	// Keys are listed in order, so the item under `key` comes first among those prefixed by it.
+	let response = store.list_key_versions(ListKeyVersionsRequest {
+		store_id: store_id.to_string(),
+		key_prefix: Some(key.to_string()),
+		page_size: Some(1),
+		..Default::default()
+	}).await?;
+	Ok(response.item_expiries.into_iter().find(|item_expiry| item_expiry.key == key).map(|item_expiry| item_expiry.expires_at))
+}
+
+/// Runs [`SoftDeleteStore::reap`] every [`SoftDeleteConfig::reap_interval`].
+pub async fn run_reaper(store: Arc<SoftDeleteStore>) {
+	let mut interval = tokio::time::interval(store.config.reap_interval);
+	interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+	loop {
+		interval.tick().await;
+		match store.reap().await {
+			Ok(0) => {}
+			Ok(purged) => tracing::info!(purged, "Purged deleted items past their retention"),
+			Err(err) => tracing::warn!(error = %err, "Failed to purge deleted items"),
+		}
+	}
+}
+
+#[async_trait]
+impl KvStore for SoftDeleteStore {
+	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
+		self.inner.get(request).await
+	}
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+		self.inner.get_objects(request).await
+	}
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+		self.inner.get_versions(store_id, keys).await
+	}
+	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+		if request.delete_items.is_empty() {
+			return self.inner.put(request).await;
+		}
+		let store_id = request.store_id.clone();
+		let keys = request.delete_items.iter().map(|kv| kv.key.clone()).collect();
+		let deleted_keys = self.retain(&store_id, keys).await?;
+
+		let result = self.inner.put(request).await;
+		if result.is_err() {
+			self.discard(&store_id, deleted_keys).await;
+		}
+		result
+	}
+	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
+		let store_id = request.store_id.clone();
+		let keys = request.key_value.iter().map(|kv| kv.key.clone()).collect();
+		let deleted_keys = self.retain(&store_id, keys).await?;
+
+		let result = self.inner.delete(request).await;
+		if result.is_err() {
+			self.discard(&store_id, deleted_keys).await;
+		}
+		result
+	}
+	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+		self.inner.list_key_versions(request).await
+	}
+	async fn list_object_versions(&self, request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+		self.inner.list_object_versions(request).await
+	}
+	async fn get_object_version(&self, request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+		self.inner.get_object_version(request).await
+	}
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+		self.inner.global_version(store_id).await
+	}
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+		self.inner.backfill(store_id, key_values, item_expiries).await
+	}
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+		self.inner.backfill_global_version(store_id, global_version).await
+	}
+	async fn purge_expired(&self) -> std::io::Result<usize> {
+		self.inner.purge_expired().await
+	}
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+		self.inner.list_stores(request).await
+	}
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+		self.inner.store_metadata(store_id).await
+	}
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+		// Erasure includes the items deleted before, which are not restorable afterwards.
+		let deleted = self.inner.delete_store(store_id).await?;
+		self.backend.delete_store(&deleted_store_id(store_id)).await?;
+		self.backend.delete(DeleteObjectRequest {
+			store_id: DELETED_STORES_ID.to_string(),
+			key_value: Some(KeyValue { key: store_id.to_string(), version: -1, value: Vec::new() }),
+		}).await?;
+		Ok(deleted)
+	}
+	async fn check_health(&self) -> std::io::Result<HealthStatus> {
+		self.inner.check_health().await
+	}
+}
+
+#[derive(Clone)]
+struct AdminState {
+	store: Arc<dyn KvStore>,
+	soft_delete: Option<Arc<SoftDeleteStore>>,
+}
+
+/// Routes of the admin APIs listing and restoring deleted items, which write restored items through
+/// `store`. They are served by [`crate::admin::router`], behind the admin token.
+pub fn routes(store: Arc<dyn KvStore>, soft_delete: Option<Arc<SoftDeleteStore>>, max_body_size: usize) -> Router {
+	Router::new()
+		.route("/admin/listDeletedObjects", post(list_deleted_objects))
+		.route("/admin/restoreObject", post(restore_object))
+		.layer(DefaultBodyLimit::max(max_body_size))
+		.with_state(AdminState { store, soft_delete })
+}
+
+async fn list_deleted_objects(State(state): State<AdminState>, headers: HeaderMap, body: Result<Bytes, BytesRejection>) -> impl IntoResponse {
+	handle_admin_request(&headers, body, |request: ListDeletedObjectsRequest| async move {
+		validate_store_id(&request.store_id)?;
+		state.soft_delete.ok_or_else(soft_delete_not_enabled)?.list_deleted(request).await
+	}, "Failed to list deleted objects").await
+}
+
+async fn restore_object(State(state): State<AdminState>, headers: HeaderMap, body: Result<Bytes, BytesRejection>) -> impl IntoResponse {
+	handle_admin_request(&headers, body, |request: RestoreObjectRequest| async move {
+		validate_store_id(&request.store_id)?;
+		state.soft_delete.ok_or_else(soft_delete_not_enabled)?.restore(state.store.as_ref(), request).await
+	}, "Failed to restore object").await
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::*;
+	use crate::testing::{MemoryStore, delete, get, kv, put};
+
+	fn soft_delete_store() -> SoftDeleteStore {
+		let config = SoftDeleteConfig { retention: Duration::from_secs(60), reap_interval: Duration::from_secs(60) };
+		let backend: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+		SoftDeleteStore::new(Arc::clone(&backend), backend, config)
+	}
+
+	fn restore_request(key: &str) -> RestoreObjectRequest {
+		RestoreObjectRequest { store_id: "store".to_string(), key: key.to_string() }
+	}
+
+	#[tokio::test]
+	async fn restores_deleted_items_as_new_keys() {
+		let store = soft_delete_store();
+		put(&store, "store", "k", 0, b"a").await.unwrap();
+		put(&store, "store", "k", 1, b"b").await.unwrap();
+		delete(&store, "store", "k", 2).await.unwrap();
+		assert_eq!(get(&store, "store", "k").await.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+		let deleted = store.list_deleted(ListDeletedObjectsRequest { store_id: "store".to_string(), key_prefix: None }).await.unwrap();
+		assert_eq!(deleted.objects.len(), 1);
+		assert_eq!((deleted.objects[0].key.as_str(), deleted.objects[0].version), ("k", 2));
+
+		let restored = store.restore(&store, restore_request("k")).await.unwrap();
+		assert_eq!(restored.value, Some(kv("k", 1, b"b")));
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 1, b"b"));
+		assert!(store.list_deleted(ListDeletedObjectsRequest { store_id: "store".to_string(), key_prefix: None }).await.unwrap().objects.is_empty());
+	}
+
+	#[tokio::test]
+	async fn retains_each_deleted_version_with_its_expiry() {
+		let store = soft_delete_store();
+		store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("k", 0, b"a")],
+			item_expiries: vec![ItemExpiry { key: "k".to_string(), expires_at: i64::MAX }],
+			..Default::default()
+		}).await.unwrap();
+		delete(&store, "store", "k", 1).await.unwrap();
+		// Deleted again, possibly within the same millisecond.
+		put(&store, "store", "k", 0, b"b").await.unwrap();
+		put(&store, "store", "k", 1, b"c").await.unwrap();
+		delete(&store, "store", "k", 2).await.unwrap();
+
+		let deleted = store.list_deleted(ListDeletedObjectsRequest { store_id: "store".to_string(), key_prefix: None }).await.unwrap();
+		let mut versions: Vec<i64> = deleted.objects.iter().map(|object| object.version).collect();
+		versions.sort();
+		assert_eq!(versions, vec![1, 2]);
+		let retained = store.backend.list_key_versions(ListKeyVersionsRequest { store_id: deleted_store_id("store"), ..Default::default() }).await.unwrap();
+		let first = deleted.objects.iter().find(|object| object.version == 1).unwrap();
+		assert_eq!(retained.item_expiries, vec![ItemExpiry { key: deleted_key("k", first.deleted_at, 1), expires_at: i64::MAX }]);
+
+		store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("e", 0, b"e")],
+			item_expiries: vec![ItemExpiry { key: "e".to_string(), expires_at: i64::MAX }],
+			..Default::default()
+		}).await.unwrap();
+		delete(&store, "store", "e", 1).await.unwrap();
+		store.restore(&store, restore_request("e")).await.unwrap();
+		let listed = store.list_key_versions(ListKeyVersionsRequest { store_id: "store".to_string(), key_prefix: Some("e".to_string()), ..Default::default() }).await.unwrap();
+		assert_eq!(listed.item_expiries, vec![ItemExpiry { key: "e".to_string(), expires_at: i64::MAX }]);
+	}
+
+	#[tokio::test]
+	async fn does_not_restore_over_keys_written_since() {
+		let store = soft_delete_store();
+		put(&store, "store", "k", 0, b"a").await.unwrap();
+		delete(&store, "store", "k", 1).await.unwrap();
+		put(&store, "store", "k", 0, b"b").await.unwrap();
+
+		assert!(is_conflict(&store.restore(&store, restore_request("k")).await.unwrap_err()));
+		assert_eq!(get(&store, "store", "k").await.unwrap(), kv("k", 1, b"b"));
+		assert_eq!(store.restore(&store, restore_request("other")).await.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+	}
+}
@@ -1,11 +1,125 @@
+use std::collections::HashMap;
+
 use axum::async_trait;
 
-use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, PutObjectRequest, PutObjectResponse};
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, ItemExpiry, KeyValue, GetObjectVersionRequest, GetObjectVersionResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, PutObjectRequest, PutObjectResponse, StoreMetadata};
 
 #[async_trait]
-pub trait KvStore {
+pub trait KvStore: Send + Sync {
 	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse>;
+	/// Fetches the values of up to `MAX_GET_OBJECTS_KEYS` keys, reporting those not found in
+	/// `missing_keys` rather than failing.
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse>;
+	/// Returns the current versions of `keys`, omitting those not found, without reading their values.
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>>;
+	/// Writes and deletes the items of `request` atomically, incrementing the `global_version` of the
+	/// store in the same write. Fails with a conflict if `request.global_version` is set and is not
+	/// the current `global_version`. Returns the `global_version` written.
 	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse>;
+	/// Deletes a single item, leaving the `global_version` of the store unchanged.
 	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse>;
+	/// Lists keys and their versions, returning the `global_version` of the store on the first page
+	/// only, read before the keys so that all of them were written at that version or later.
 	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse>;
+	/// Lists the previous versions of a key, if version history is enabled.
+	async fn list_object_versions(&self, request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse>;
+	/// Fetches a previous version of a key, if version history is enabled.
+	async fn get_object_version(&self, request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse>;
+	/// Returns the current `global_version` of `store_id`, 0 if it was never written to.
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64>;
+	/// Writes `key_values` as-is, skipping version checks and preserving the supplied versions, with
+	/// the expiries in `item_expiries`. Used to copy data between backends.
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()>;
+	/// Raises the `global_version` of `store_id` to `global_version`, leaving it unchanged if it is
+	/// already higher. Used to copy data between backends.
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()>;
+	/// Removes the expired items not removed by the backend on its own, returning their number.
+	async fn purge_expired(&self) -> std::io::Result<usize>;
+	/// Lists a page of the stores holding items or written to before, along with their number of keys
+	/// and the sum of the sizes recorded on their items. Pages hold at most `page_size` stores and are
+	/// also bounded by the items read, so one may list none and still be followed by others.
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse>;
+	/// Returns the metadata of `store_id`, failing with `NotFound` if it was never written to.
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata>;
+	/// Permanently deletes all items of `store_id` and the data kept about it, returning the number of
+	/// items deleted.
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64>;
+	/// Checks that the backend is reachable and able to serve requests. Returns an error if it is not.
+	async fn check_health(&self) -> std::io::Result<HealthStatus>;
+}
+
+/// Checks that clients may use `store_id`. `KvStore`s keep chunks, metadata, previous versions,
+/// deleted items and snapshots in partitions named `<store_id>\0<suffix>`, so store ids containing
+/// `\0` are rejected with `InvalidInput` for clients not to read or overwrite them.
+pub fn validate_store_id(store_id: &str) -> std::io::Result<()> {
+	if store_id.contains('\0') {
+		return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "store_id must not contain NUL characters"));
+	}
+	Ok(())
+}
+
+/// Version stored by a write expecting `version`: writes skipping version checks (-1) reset it to 1,
+/// others increment it.
+pub fn next_version(version: i64) -> i64 {
+	if version == -1 { 1 } else { version + 1 }
+}
+
+/// Maximum number of keys fetched by a single `GetObjectsRequest`.
+pub const MAX_GET_OBJECTS_KEYS: usize = 100;
+
+/// Health of a `KvStore` backend able to serve requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HealthStatus {
+	Healthy,
+	/// Requests are served, but with reduced capacity or guarantees, as described.
+	Degraded(String),
+}
+
+/// Marks an error returned by a `KvStore` as caused by a version mismatch on write, reported to
+/// clients as `CONFLICT_EXCEPTION`.
+#[derive(Debug)]
+pub struct ConflictError(pub String);
+
+impl std::fmt::Display for ConflictError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+
+impl std::error::Error for ConflictError {}
+
+pub fn conflict_error(message: String) -> std::io::Error {
+	std::io::Error::other(ConflictError(message))
+}
+
+pub fn is_conflict(err: &std::io::Error) -> bool {
+	err.get_ref().is_some_and(|inner| inner.is::<ConflictError>())
+}
+
+/// Lists the keys and versions of all items in `store_id`, following pagination to the end.
+pub async fn list_all_key_versions(store: &dyn KvStore, store_id: &str) -> std::io::Result<Vec<KeyValue>> {
+	let mut key_versions = Vec::new();
+	let mut page_token = None;
+	loop {
+		let response = store.list_key_versions(ListKeyVersionsRequest {
+			store_id: store_id.to_string(),
+			page_token,
+			..Default::default()
+		}).await?;
+		key_versions.extend(response.key_versions);
+		match response.next_page_token {
+			Some(token) if !token.is_empty() => page_token = Some(token),
+			_ => return Ok(key_versions),
+		}
+	}
+}
+
+/// Fetches the values of those `keys` which exist in `store_id`, in as many `GetObjectsRequest`s as needed.
+pub async fn get_all_objects(store: &dyn KvStore, store_id: &str, keys: Vec<String>) -> std::io::Result<Vec<KeyValue>> {
+	let mut values = Vec::new();
+	for keys in keys.chunks(MAX_GET_OBJECTS_KEYS) {
+		let response = store.get_objects(GetObjectsRequest { store_id: store_id.to_string(), keys: keys.to_vec() }).await?;
+		values.extend(response.values);
+	}
+	Ok(values)
 }
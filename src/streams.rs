@@ -0,0 +1,365 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use aws_sdk_dynamodbstreams::Client;
+use aws_sdk_dynamodbstreams::types::{OperationType, Record, Shard, ShardIteratorType};
+use tokio::time::Instant;
+
+use crate::changes::{ChangeBus, ChangeEvent, ChangeOp};
+use crate::dynamodb_store::{GLOBAL_VERSION, is_item_partition};
+use crate::metrics::observe_backend_call;
+use crate::store::KvStore;
+use crate::telemetry::loggable_store_id;
+
+// Value of the `backend` label of metrics recorded by the stream consumer.
+const BACKEND: &str = "dynamodb-streams";
+// How long to wait before reading shards again when none of them returned records.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// How often the stream is described to find new shards. DynamoDB rolls shards over every few hours.
+const SHARD_DISCOVERY_INTERVAL: Duration = Duration::from_secs(30);
+// GetRecords returns at most 1000 records per call.
+const MAX_RECORDS_PER_CALL: i32 = 1000;
+
+/// Records of a shard were lost, because they were trimmed from the stream or the iterator reading
+/// them expired before they were read.
+#[derive(Debug)]
+struct RecordsLost(String);
+
+impl std::fmt::Display for RecordsLost {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.0)
+	}
+}
+
+impl std::error::Error for RecordsLost {}
+
+fn is_records_lost(err: &std::io::Error) -> bool {
+	err.get_ref().is_some_and(|inner| inner.is::<RecordsLost>())
+}
+
+async fn describe_shards(client: &Client, stream_arn: &str) -> std::io::Result<Vec<Shard>> {
+	let mut shards = Vec::new();
+	let mut exclusive_start_shard_id = None;
+	loop {
+		let query = client.describe_stream()
+			.stream_arn(stream_arn)
+			.set_exclusive_start_shard_id(exclusive_start_shard_id);
+		let output = observe_backend_call(BACKEND, "DescribeStream", query.send())
+			.await
+			.map_err(|err| std::io::Error::other(format!("Failed to describe stream: {:?}", err.into_service_error())))?;
+		let Some(description) = output.stream_description else {
+			return Ok(shards);
+		};
+		shards.extend(description.shards.unwrap_or_default());
+		exclusive_start_shard_id = description.last_evaluated_shard_id;
+		if exclusive_start_shard_id.is_none() {
+			return Ok(shards);
+		}
+	}
+}
+
+async fn get_shard_iterator(client: &Client, stream_arn: &str, shard_id: &str, iterator_type: ShardIteratorType) -> std::io::Result<Option<String>> {
+	let query = client.get_shard_iterator()
+		.stream_arn(stream_arn)
+		.shard_id(shard_id)
+		.shard_iterator_type(iterator_type);
+	match observe_backend_call(BACKEND, "GetShardIterator", query.send()).await.map_err(|err| err.into_service_error()) {
+		Ok(output) => Ok(output.shard_iterator),
+		Err(err) if err.is_trimmed_data_access_exception() => Err(std::io::Error::other(RecordsLost(format!("{:?}", err)))),
+		Err(err) => Err(std::io::Error::other(format!("Failed to get shard iterator: {:?}", err))),
+	}
+}
+
+/// Returns the records read with `shard_iterator` and the iterator of the next ones, if any.
+async fn get_records(client: &Client, shard_iterator: &str) -> std::io::Result<(Vec<Record>, Option<String>)> {
+	let query = client.get_records()
+		.shard_iterator(shard_iterator)
+		.limit(MAX_RECORDS_PER_CALL);
+	match observe_backend_call(BACKEND, "GetRecords", query.send()).await.map_err(|err| err.into_service_error()) {
+		Ok(output) => Ok((output.records.unwrap_or_default(), output.next_shard_iterator)),
+		Err(err) if err.is_expired_iterator_exception() || err.is_trimmed_data_access_exception() => {
+			Err(std::io::Error::other(RecordsLost(format!("{:?}", err))))
+		}
+		Err(err) => Err(std::io::Error::other(format!("Failed to get records: {:?}", err))),
+	}
+}
+
+/// Returns the write recorded by `record`, along with the global version it created if it is a put
+/// recording it, or `None` if it is not a write to a key, e.g. one to a value chunk or to a global
+/// version. The event is yet to be tagged with a global version.
+fn change_of(record: &Record) -> Option<(ChangeEvent, Option<i64>)> {
+	let stream_record = record.dynamodb.as_ref()?;
+	let keys = stream_record.keys.as_ref()?;
+	let store_id = keys.get("store_id")?.as_s().ok()?.clone();
+	let key = keys.get("key")?.as_s().ok()?.clone();
+	if !is_item_partition(&store_id) {
+		return None;
+	}
+	let (image, op) = match record.event_name.as_ref()? {
+		OperationType::Insert | OperationType::Modify => (stream_record.new_image.as_ref(), ChangeOp::Put),
+		OperationType::Remove => (stream_record.old_image.as_ref(), ChangeOp::Delete),
+		_ => return None,
+	};
+	let number = |name: &str| image
+		.and_then(|image| image.get(name))
+		.and_then(|value| value.as_n().ok())
+		.and_then(|value| value.parse::<i64>().ok());
+	let version = number("version").unwrap_or(-1);
+	let global_version = match op {
+		ChangeOp::Put => number(GLOBAL_VERSION),
+		// The old image holds the global version of the put that wrote the value deleted.
+		ChangeOp::Delete => None,
+	};
+	Some((ChangeEvent { store_id, key, version, op, global_version: 0 }, global_version))
+}
+
+/// Where reading a shard starts from.
+#[derive(Clone, Copy)]
+enum ShardStart {
+	/// After the last record, for shards open when the consumer starts.
+	Latest,
+	/// At the first record, for shards created later.
+	TrimHorizon,
+}
+
+struct ShardReader {
+	parent_shard_id: Option<String>,
+	start: ShardStart,
+	iterator: Option<String>,
+}
+
+/// Reads the stream `stream_arn` of the VSS table and publishes the writes it records on `bus`,
+/// until the process exits.
+///
+/// Writes made before the consumer starts are not published. Puts are tagged with the global
+/// version they created, recorded in the items they wrote, so that all the writes of a transaction
+/// share it. Deletes, and items written without one such as backfilled ones, are tagged like
+/// deletes made through a `NotifyingStore`: with the latest global version published for their
+/// store, read from `store` if none was. If records are lost, e.g. because the consumer fell more
+/// than 24 hours behind, all subscribers are reset.
+///
+/// DynamoDB throttles shards read by more than two consumers at once, which limits deployments
+/// using this feed to two servers per table.
+pub async fn consume(client: Client, stream_arn: String, store: Arc<dyn KvStore>, bus: Arc<ChangeBus>) {
+	let mut readers: HashMap<String, ShardReader> = HashMap::new();
+	let mut known_shards: HashSet<String> = HashSet::new();
+	let mut last_discovery: Option<Instant> = None;
+	loop {
+		if last_discovery.is_none_or(|last_discovery| last_discovery.elapsed() >= SHARD_DISCOVERY_INTERVAL) {
+			match describe_shards(&client, &stream_arn).await {
+				Ok(shards) => {
+					let starting = last_discovery.is_none();
+					for shard in shards {
+						let Some(shard_id) = shard.shard_id else { continue };
+						if !known_shards.insert(shard_id.clone()) {
+							continue;
+						}
+						let closed = shard.sequence_number_range.is_some_and(|range| range.ending_sequence_number.is_some());
+						let start = match (starting, closed) {
+							// Closed before the consumer started.
+							(true, true) => continue,
+							(true, false) => ShardStart::Latest,
+							(false, _) => ShardStart::TrimHorizon,
+						};
+						readers.insert(shard_id, ShardReader { parent_shard_id: shard.parent_shard_id, start, iterator: None });
+					}
+					last_discovery = Some(Instant::now());
+				}
+				Err(err) => tracing::error!(error = %err, "Failed to describe DynamoDB stream"),
+			}
+		}
+
+		let mut received_records = false;
+		let shard_ids: Vec<String> = readers.keys().cloned().collect();
+		for shard_id in shard_ids {
+			// Records of a key are ordered across shards by reading parents first.
+			if readers[&shard_id].parent_shard_id.as_ref().is_some_and(|parent_shard_id| readers.contains_key(parent_shard_id)) {
+				continue;
+			}
+			match read_shard(&client, &stream_arn, &shard_id, readers.get_mut(&shard_id).unwrap()).await {
+				Ok((records, closed)) => {
+					received_records |= !records.is_empty();
+					publish_records(records, store.as_ref(), &bus).await;
+					if closed {
+						readers.remove(&shard_id);
+					}
+				}
+				Err(err) if is_records_lost(&err) => {
+					tracing::error!(shard_id, error = %err, "Lost records of DynamoDB stream shard, resetting subscribers");
+					let reader = readers.get_mut(&shard_id).unwrap();
+					reader.start = ShardStart::Latest;
+					reader.iterator = None;
+					bus.reset_all();
+				}
+				Err(err) => tracing::error!(shard_id, error = %err, "Failed to read DynamoDB stream shard"),
+			}
+		}
+		if !received_records {
+			tokio::time::sleep(POLL_INTERVAL).await;
+		}
+	}
+}
+
+/// Reads the next records of a shard, along with whether all records of the shard have been read
+/// since it is closed.
+async fn read_shard(client: &Client, stream_arn: &str, shard_id: &str, reader: &mut ShardReader) -> std::io::Result<(Vec<Record>, bool)> {
+	let iterator = match reader.iterator.take() {
+		Some(iterator) => iterator,
+		None => {
+			let iterator_type = match reader.start {
+				ShardStart::Latest => ShardIteratorType::Latest,
+				ShardStart::TrimHorizon => ShardIteratorType::TrimHorizon,
+			};
+			match get_shard_iterator(client, stream_arn, shard_id, iterator_type).await? {
+				Some(iterator) => iterator,
+				None => return Ok((Vec::new(), true)),
+			}
+		}
+	};
+	let (records, next_iterator) = match get_records(client, &iterator).await {
+		Ok(output) => output,
+		Err(err) => {
+			// Iterators can be retried until they expire.
+			reader.iterator = Some(iterator);
+			return Err(err);
+		}
+	};
+	let closed = next_iterator.is_none();
+	reader.iterator = next_iterator;
+	Ok((records, closed))
+}
+
+/// Publishes the writes recorded in `records` on `bus`, in order.
+async fn publish_records(records: Vec<Record>, store: &dyn KvStore, bus: &ChangeBus) {
+	for (change, global_version) in records.iter().filter_map(change_of) {
+		let global_version = match global_version.or_else(|| bus.latest_global_version(&change.store_id)) {
+			Some(global_version) => global_version,
+			None => match store.global_version(&change.store_id).await {
+				Ok(global_version) => global_version,
+				Err(err) => {
+					tracing::warn!(store_id = loggable_store_id(&change.store_id), error = %err, "Failed to read global version, resetting subscribers");
+					bus.reset(&change.store_id);
+					continue;
+				}
+			},
+		};
+		bus.publish(ChangeEvent { global_version, ..change });
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use aws_sdk_dynamodbstreams::types::{AttributeValue, StreamRecord};
+
+	use super::*;
+	use crate::testing::{MemoryStore, delete, dynamodb_store_with_stream, dynamodb_streams_client, kv, put};
+	use crate::types::PutObjectRequest;
+
+	fn record(op: OperationType, key: &str, version: i64, global_version: Option<i64>) -> Record {
+		let number = |n: i64| AttributeValue::N(n.to_string());
+		let mut image = StreamRecord::builder()
+			.keys("store_id", AttributeValue::S("store".to_string()))
+			.keys("key", AttributeValue::S(key.to_string()));
+		image = match op {
+			OperationType::Remove => image.old_image("version", number(version)),
+			_ => image.new_image("version", number(version)),
+		};
+		if let Some(global_version) = global_version {
+			image = image.new_image(GLOBAL_VERSION, number(global_version));
+		}
+		Record::builder().event_name(op).dynamodb(image.build()).build()
+	}
+
+	#[tokio::test]
+	async fn tags_puts_with_the_global_version_they_created() {
+		let store = MemoryStore::default();
+		let bus = ChangeBus::new(16);
+		let records = vec![
+			record(OperationType::Insert, "a", 1, Some(4)),
+			record(OperationType::Insert, "b", 1, Some(4)),
+			record(OperationType::Remove, "c", 2, None),
+			record(OperationType::Modify, "a", 2, Some(5)),
+		];
+		publish_records(records, &store, &bus).await;
+
+		let published: Vec<(String, ChangeOp, i64)> = bus.buffered_events("store").iter()
+			.map(|event| (event.key.clone(), event.op, event.global_version))
+			.collect();
+		assert_eq!(published, vec![
+			("a".to_string(), ChangeOp::Put, 4),
+			("b".to_string(), ChangeOp::Put, 4),
+			("c".to_string(), ChangeOp::Delete, 4),
+			("a".to_string(), ChangeOp::Put, 5),
+		]);
+		assert_eq!(store.global_version_reads(), 0);
+
+		// Without a put published, deletes are tagged with the global version read from the store.
+		put(&store, "other", "k", 0, b"v").await.unwrap();
+		let mut delete = record(OperationType::Remove, "k", 1, None);
+		delete.dynamodb.as_mut().unwrap().keys.as_mut().unwrap().insert("store_id".to_string(), AttributeValue::S("other".to_string()));
+		publish_records(vec![delete], &store, &bus).await;
+		assert_eq!(bus.latest_global_version("other"), Some(1));
+		assert_eq!(store.global_version_reads(), 1);
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn reads_writes_to_keys_from_shards() {
+		let store = dynamodb_store_with_stream().await;
+		let client = dynamodb_streams_client();
+		let stream_arn = store.latest_stream_arn().await.unwrap();
+		put(&store, "store", "a", 0, b"a").await.unwrap();
+		// Writes to the value chunks and to the global version are not changes to keys.
+		put(&store, "store", "a", 1, &vec![0; 1024 * 1024]).await.unwrap();
+		store.put(PutObjectRequest {
+			store_id: "store".to_string(),
+			transaction_items: vec![kv("b", 0, b"b"), kv("c", 0, b"c")],
+			..Default::default()
+		}).await.unwrap();
+		delete(&store, "store", "a", 2).await.unwrap();
+
+		let mut records = Vec::new();
+		for shard in describe_shards(&client, &stream_arn).await.unwrap() {
+			let mut reader = ShardReader { parent_shard_id: None, start: ShardStart::TrimHorizon, iterator: None };
+			let (shard_records, _) = read_shard(&client, &stream_arn, shard.shard_id().unwrap(), &mut reader).await.unwrap();
+			records.extend(shard_records);
+		}
+		let changes: Vec<(String, i64, ChangeOp, Option<i64>)> = records.iter().filter_map(change_of)
+			.map(|(change, global_version)| (change.key, change.version, change.op, global_version))
+			.collect();
+		assert_eq!(changes, vec![
+			("a".to_string(), 1, ChangeOp::Put, Some(1)),
+			("a".to_string(), 2, ChangeOp::Put, Some(2)),
+			("b".to_string(), 1, ChangeOp::Put, Some(3)),
+			("c".to_string(), 1, ChangeOp::Put, Some(3)),
+			("a".to_string(), 2, ChangeOp::Delete, None),
+		]);
+
+		let bus = ChangeBus::new(16);
+		publish_records(records, &store, &bus).await;
+		assert_eq!(bus.latest_global_version("store"), Some(3));
+	}
+
+	#[tokio::test]
+	#[ignore = "requires DynamoDB Local at localhost:8000"]
+	async fn publishes_writes_made_once_started() {
+		let store = Arc::new(dynamodb_store_with_stream().await);
+		let stream_arn = store.latest_stream_arn().await.unwrap();
+		let bus = Arc::new(ChangeBus::new(16));
+		let consumer = tokio::spawn(consume(dynamodb_streams_client(), stream_arn, Arc::clone(&store) as Arc<dyn KvStore>, Arc::clone(&bus)));
+
+		// Writes made before the consumer read the stream for the first time are not published.
+		let mut published = None;
+		for attempt in 0..20 {
+			put(store.as_ref(), "store", &format!("k{}", attempt), 0, b"v").await.unwrap();
+			tokio::time::sleep(Duration::from_millis(500)).await;
+			published = bus.latest_global_version("store");
+			if published.is_some() {
+				break;
+			}
+		}
+		consumer.abort();
+		assert!(published.is_some());
+	}
+}
@@ -0,0 +1,182 @@
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use axum::http::{HeaderValue, Request};
+use axum::extract::MatchedPath;
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::Tracer;
+use opentelemetry_sdk::{Resource, runtime};
+use sha2::{Digest, Sha256};
+use tracing::Instrument;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::config::{LogFormat, StoreIdLogging, TelemetryConfig};
+use crate::types::ErrorCode;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+// Longer request ids supplied by clients are replaced with a generated one.
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+static STORE_ID_LOGGING: OnceLock<StoreIdLogging> = OnceLock::new();
+
+/// Installs the global `tracing` subscriber, writing logs to stdout and, if configured, exporting
+/// spans to an OpenTelemetry collector over OTLP/gRPC.
+///
+/// Log levels are controlled with the `RUST_LOG` environment variable and default to `info`.
+pub fn init(config: &TelemetryConfig) -> std::io::Result<()> {
+	STORE_ID_LOGGING.set(config.store_id_logging).ok();
+
+	let otel_layer = match &config.otlp_endpoint {
+		Some(endpoint) => Some(tracing_opentelemetry::layer().with_tracer(otlp_tracer(endpoint, &config.service_name)?)),
+		None => None,
+	};
+	let (json_layer, text_layer) = match config.log_format {
+		LogFormat::Json => (Some(tracing_subscriber::fmt::layer().json().flatten_event(true)), None),
+		LogFormat::Text => (None, Some(tracing_subscriber::fmt::layer())),
+	};
+
+	tracing_subscriber::registry()
+		.with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+		.with(json_layer)
+		.with(text_layer)
+		.with(otel_layer)
+		.try_init()
+		.map_err(|err| std::io::Error::other(format!("Failed to install tracing subscriber: {}", err)))
+}
+
+/// Installs the global tracer provider, exporting spans in batches to the OpenTelemetry collector at
+/// `endpoint` over OTLP/gRPC, and returns its tracer.
+fn otlp_tracer(endpoint: &str, service_name: &str) -> std::io::Result<Tracer> {
+	opentelemetry_otlp::new_pipeline()
+		.tracing()
+		.with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+		.with_trace_config(opentelemetry_sdk::trace::config()
+			.with_resource(Resource::new(vec![KeyValue::new("service.name", service_name.to_string())])))
+		.install_batch(runtime::Tokio)
+		.map_err(|err| std::io::Error::other(format!("Failed to set up OTLP exporter: {}", err)))
+}
+
+/// Flushes spans not yet exported to the OpenTelemetry collector.
+pub fn shutdown() {
+	opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Middleware running each request in a span carrying a request id, and logging its outcome and
+/// latency once handled.
+///
+/// The request id is taken from the `X-Request-Id` header if present and echoed in the response.
+pub async fn trace_request<B>(request: Request<B>, next: Next<B>) -> impl IntoResponse {
+	let request_id = request.headers().get(REQUEST_ID_HEADER)
+		.and_then(|value| value.to_str().ok())
+		.filter(|value| !value.is_empty() && value.len() <= MAX_REQUEST_ID_LENGTH)
+		.map(String::from)
+		.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+	let endpoint = request.extensions().get::<MatchedPath>().map(|matched_path| matched_path.as_str().to_string());
+	let span = tracing::info_span!(
+		"request",
+		request_id = %request_id,
+		endpoint = endpoint.as_deref().unwrap_or("unmatched"),
+		store_id = tracing::field::Empty,
+	);
+
+	let start = Instant::now();
+	let mut response = next.run(request).instrument(span.clone()).await;
+	let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+	let outcome = response.extensions().get::<ErrorCode>().map_or("OK", ErrorCode::as_str_name);
+	span.in_scope(|| {
+		tracing::info!(status = response.status().as_u16(), outcome, latency_ms, "Request completed");
+	});
+	if let Ok(value) = HeaderValue::from_str(&request_id) {
+		response.headers_mut().insert(REQUEST_ID_HEADER, value);
+	}
+	response
+}
+
+/// Records `store_id` on the current request span, hashed or redacted as configured.
+pub fn record_store_id(store_id: &str) {
+	tracing::Span::current().record("store_id", loggable_store_id(store_id));
+}
+
+/// Returns `store_id` as it may appear in logs, hashed or redacted as configured.
+pub fn loggable_store_id(store_id: &str) -> String {
+	match STORE_ID_LOGGING.get().copied().unwrap_or(StoreIdLogging::Hashed) {
+		StoreIdLogging::Plain => store_id.to_string(),
+		// Truncated, the hash only needs to tell stores apart in logs.
+		StoreIdLogging::Hashed => hex::encode(&Sha256::digest(store_id.as_bytes())[..8]),
+		StoreIdLogging::Redacted => "[redacted]".to_string(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use axum::body::Body;
+	use axum::routing::post;
+	use axum::{Router, middleware};
+	use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
+	use opentelemetry_proto::tonic::collector::trace::v1::{ExportTraceServiceRequest, ExportTraceServiceResponse};
+	use opentelemetry_proto::tonic::common::v1::{KeyValue as Attribute, any_value};
+	use tokio::sync::mpsc;
+	use tonic::transport::Server;
+	use tonic::transport::server::TcpIncoming;
+	use tower::ServiceExt;
+
+	use super::*;
+
+	/// In-process OpenTelemetry collector, forwarding the requests it receives.
+	struct Collector(mpsc::UnboundedSender<ExportTraceServiceRequest>);
+
+	#[axum::async_trait]
+	impl TraceService for Collector {
+		async fn export(&self, request: tonic::Request<ExportTraceServiceRequest>) -> Result<tonic::Response<ExportTraceServiceResponse>, tonic::Status> {
+			self.0.send(request.into_inner()).ok();
+			Ok(tonic::Response::new(ExportTraceServiceResponse::default()))
+		}
+	}
+
+	fn string_attribute(attributes: &[Attribute], key: &str) -> Option<String> {
+		let value = attributes.iter().find(|attribute| attribute.key == key)?.value.as_ref()?.value.as_ref()?;
+		match value {
+			any_value::Value::StringValue(value) => Some(value.clone()),
+			_ => None,
+		}
+	}
+
+	// The batch exporter blocks on flushing, which needs another worker thread.
+	#[tokio::test(flavor = "multi_thread")]
+	async fn exports_request_spans_over_otlp() {
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let endpoint = format!("http://{}", listener.local_addr().unwrap());
+		let (sender, mut receiver) = mpsc::unbounded_channel();
+		let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+		tokio::spawn(Server::builder().add_service(TraceServiceServer::new(Collector(sender))).serve_with_incoming(incoming));
+
+		let tracer = otlp_tracer(&endpoint, "vss-test").unwrap();
+		let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+		let router = Router::new()
+			.route("/getObject", post(|| async { record_store_id("store") }))
+			.route_layer(middleware::from_fn(trace_request));
+		{
+			let _guard = tracing::subscriber::set_default(subscriber);
+			let request = Request::post("/getObject").header(REQUEST_ID_HEADER, "request-1").body(Body::empty()).unwrap();
+			router.oneshot(request).await.unwrap();
+		}
+		tokio::task::spawn_blocking(shutdown).await.unwrap();
+
+		let request = tokio::time::timeout(Duration::from_secs(10), receiver.recv()).await.unwrap().unwrap();
+		let resource_spans = &request.resource_spans[0];
+		let resource_attributes = &resource_spans.resource.as_ref().unwrap().attributes;
+		assert_eq!(string_attribute(resource_attributes, "service.name").as_deref(), Some("vss-test"));
+		let span = resource_spans.scope_spans.iter().flat_map(|scope_spans| &scope_spans.spans).find(|span| span.name == "request").unwrap();
+		assert_eq!(string_attribute(&span.attributes, "request_id").as_deref(), Some("request-1"));
+		assert_eq!(string_attribute(&span.attributes, "endpoint").as_deref(), Some("/getObject"));
+		assert_eq!(string_attribute(&span.attributes, "store_id"), Some(loggable_store_id("store")));
+	}
+}
@@ -0,0 +1,338 @@
+//! Test doubles and helpers shared by the unit tests of `KvStore`s.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aws_sdk_dynamodb::Client;
+use aws_sdk_dynamodb::config::{Credentials, Region};
+use axum::async_trait;
+
+use crate::dynamodb_store::DynamoDbStore;
+use crate::history::history_not_enabled;
+use crate::store::{HealthStatus, KvStore, conflict_error};
+use crate::types::{DeleteObjectRequest, DeleteObjectResponse, GetObjectRequest, GetObjectResponse, GetObjectsRequest, GetObjectsResponse, ItemExpiry, KeyValue, GetObjectVersionRequest, GetObjectVersionResponse, ListKeyVersionsRequest, ListKeyVersionsResponse, ListObjectVersionsRequest, ListObjectVersionsResponse, ListStoresRequest, ListStoresResponse, PutObjectRequest, PutObjectResponse, StoreMetadata, StoreStats};
+
+struct Item {
+	value: KeyValue,
+	// In milliseconds since the Unix epoch.
+	expires_at: Option<i64>,
+}
+
+impl Item {
+	fn is_expired(&self) -> bool {
+		self.expires_at.is_some_and(|expires_at| expires_at <= now_millis())
+	}
+}
+
+#[derive(Default)]
+struct Stores {
+	items: HashMap<String, BTreeMap<String, Item>>,
+	global_versions: HashMap<String, i64>,
+}
+
+impl Stores {
+	fn current(&self, store_id: &str, key: &str) -> Option<&KeyValue> {
+		self.items.get(store_id)?.get(key).filter(|item| !item.is_expired()).map(|item| &item.value)
+	}
+}
+
+/// A `KvStore` keeping items in memory, with the version semantics of `DynamoDbStore`.
+#[derive(Default)]
+pub struct MemoryStore {
+	stores: Mutex<Stores>,
+	value_reads: AtomicUsize,
+	global_version_reads: AtomicUsize,
+	get_latency: Duration,
+	page_size: Option<usize>,
+	health: Option<(Result<HealthStatus, String>, Duration)>,
+}
+
+impl MemoryStore {
+	/// Returns a `MemoryStore` taking `latency` to respond to `get`.
+	pub fn with_get_latency(latency: Duration) -> Self {
+		Self { get_latency: latency, ..Default::default() }
+	}
+
+	/// Returns a `MemoryStore` whose `check_health` takes `latency` to report `health`, an error
+	/// with the given message if it is `Err`.
+	pub fn with_health(health: Result<HealthStatus, String>, latency: Duration) -> Self {
+		Self { health: Some((health, latency)), ..Default::default() }
+	}
+
+	/// Returns a `MemoryStore` listing at most `page_size` keys per page when the request does not
+	/// set a page size.
+	pub fn with_page_size(page_size: usize) -> Self {
+		Self { page_size: Some(page_size), ..Default::default() }
+	}
+
+	/// Returns the number of calls to `get` and `get_objects` so far.
+	pub fn value_reads(&self) -> usize {
+		self.value_reads.load(Ordering::Relaxed)
+	}
+
+	/// Returns the number of calls to `global_version` so far.
+	pub fn global_version_reads(&self) -> usize {
+		self.global_version_reads.load(Ordering::Relaxed)
+	}
+}
+
+fn now_millis() -> i64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as i64
+}
+
+/// Returns a `DynamoDbStore` backed by a new table of the DynamoDB Local instance listening at
+/// `VSS_TEST_DYNAMODB_ENDPOINT`, `http://localhost:8000` by default.
+pub async fn dynamodb_store() -> DynamoDbStore {
+	let store = DynamoDbStore::new(dynamodb_client().await, format!("vss-test-{}", uuid::Uuid::new_v4()));
+	store.ensure_table(true, None, false).await.expect("Failed to create table in DynamoDB Local");
+	store
+}
+
+/// Returns a `DynamoDbStore` like [`dynamodb_store`], whose table has a stream.
+pub async fn dynamodb_store_with_stream() -> DynamoDbStore {
+	let store = DynamoDbStore::new(dynamodb_client().await, format!("vss-test-{}", uuid::Uuid::new_v4()));
+	store.ensure_table(true, None, true).await.expect("Failed to create table in DynamoDB Local");
+	store
+}
+
+fn dynamodb_endpoint() -> String {
+	std::env::var("VSS_TEST_DYNAMODB_ENDPOINT").unwrap_or_else(|_| "http://localhost:8000".to_string())
+}
+
+pub async fn dynamodb_client() -> Client {
+	let config = aws_sdk_dynamodb::Config::builder()
+		.endpoint_url(dynamodb_endpoint())
+		.region(Region::new("us-east-1"))
+		.credentials_provider(Credentials::new("test", "test", None, None, "test"))
+		.build();
+	Client::from_conf(config)
+}
+
+/// Returns a client of the DynamoDB Streams API of the DynamoDB Local instance of [`dynamodb_client`].
+pub fn dynamodb_streams_client() -> aws_sdk_dynamodbstreams::Client {
+	let config = aws_sdk_dynamodbstreams::Config::builder()
+		.endpoint_url(dynamodb_endpoint())
+		.region(Region::new("us-east-1"))
+		.credentials_provider(Credentials::new("test", "test", None, None, "test"))
+		.build();
+	aws_sdk_dynamodbstreams::Client::from_conf(config)
+}
+
+pub fn kv(key: &str, version: i64, value: &[u8]) -> KeyValue {
+	KeyValue { key: key.to_string(), version, value: value.to_vec() }
+}
+
+/// Writes `value` under `key`, expecting the key to be at `version`.
+pub async fn put(store: &dyn KvStore, store_id: &str, key: &str, version: i64, value: &[u8]) -> std::io::Result<PutObjectResponse> {
+	store.put(PutObjectRequest {
+		store_id: store_id.to_string(),
+		transaction_items: vec![kv(key, version, value)],
+		..Default::default()
+	}).await
+}
+
+pub async fn get(store: &dyn KvStore, store_id: &str, key: &str) -> std::io::Result<KeyValue> {
+	let response = store.get(GetObjectRequest { store_id: store_id.to_string(), key: key.to_string(), ..Default::default() }).await?;
+	Ok(response.value.unwrap())
+}
+
+pub async fn delete(store: &dyn KvStore, store_id: &str, key: &str, version: i64) -> std::io::Result<DeleteObjectResponse> {
+	store.delete(DeleteObjectRequest { store_id: store_id.to_string(), key_value: Some(kv(key, version, b"")) }).await
+}
+
+fn not_found() -> std::io::Error {
+	std::io::Error::new(std::io::ErrorKind::NotFound, "Key not found")
+}
+
+#[async_trait]
+impl KvStore for MemoryStore {
+	async fn get(&self, request: GetObjectRequest) -> std::io::Result<GetObjectResponse> {
+		self.value_reads.fetch_add(1, Ordering::Relaxed);
+		if !self.get_latency.is_zero() {
+			tokio::time::sleep(self.get_latency).await;
+		}
+		let stores = self.stores.lock().unwrap();
+		let value = stores.current(&request.store_id, &request.key).ok_or_else(not_found)?;
+		if request.known_version == Some(value.version) {
+			return Ok(GetObjectResponse { value: Some(KeyValue { key: request.key, version: value.version, ..Default::default() }), not_modified: true });
+		}
+		Ok(GetObjectResponse { value: Some(value.clone()), not_modified: false })
+	}
+	async fn get_objects(&self, request: GetObjectsRequest) -> std::io::Result<GetObjectsResponse> {
+		self.value_reads.fetch_add(1, Ordering::Relaxed);
+		let stores = self.stores.lock().unwrap();
+		let mut response = GetObjectsResponse::default();
+		for key in request.keys {
+			match stores.current(&request.store_id, &key) {
+				Some(value) => response.values.push(value.clone()),
+				None => response.missing_keys.push(key),
+			}
+		}
+		Ok(response)
+	}
+	async fn get_versions(&self, store_id: &str, keys: Vec<String>) -> std::io::Result<HashMap<String, i64>> {
+		let stores = self.stores.lock().unwrap();
+		Ok(keys.into_iter()
+			.filter_map(|key| stores.current(store_id, &key).map(|value| (key, value.version)))
+			.collect())
+	}
+	async fn put(&self, request: PutObjectRequest) -> std::io::Result<PutObjectResponse> {
+		let mut stores = self.stores.lock().unwrap();
+		let global_version = stores.global_versions.get(&request.store_id).copied().unwrap_or(0);
+		if request.global_version.is_some_and(|expected| expected != global_version) {
+			return Err(conflict_error("Global version mismatch".to_string()));
+		}
+		for kv in &request.transaction_items {
+			let current = stores.current(&request.store_id, &kv.key).map(|value| value.version);
+			let matches = match kv.version {
+				-1 => true,
+				0 => current.is_none(),
+				version => current == Some(version),
+			};
+			if !matches {
+				return Err(conflict_error(format!("Version mismatch for key {}", kv.key)));
+			}
+		}
+		for kv in &request.delete_items {
+			let current = stores.current(&request.store_id, &kv.key).map(|value| value.version);
+			if kv.version != -1 && current != Some(kv.version) {
+				return Err(conflict_error(format!("Version mismatch for key {}", kv.key)));
+			}
+		}
+
+		let items = stores.items.entry(request.store_id.clone()).or_default();
+		for kv in request.transaction_items {
+			let expires_at = request.item_expiries.iter().find(|item_expiry| item_expiry.key == kv.key).map(|item_expiry| item_expiry.expires_at);
+			let version = if kv.version == -1 { 1 } else { kv.version + 1 };
+			items.insert(kv.key.clone(), Item { value: KeyValue { version, ..kv }, expires_at });
+		}
+		for kv in request.delete_items {
+			items.remove(&kv.key);
+		}
+		stores.global_versions.insert(request.store_id, global_version + 1);
+		Ok(PutObjectResponse { global_version: Some(global_version + 1) })
+	}
+	async fn delete(&self, request: DeleteObjectRequest) -> std::io::Result<DeleteObjectResponse> {
+		let mut stores = self.stores.lock().unwrap();
+		let kv = request.key_value.unwrap_or_default();
+		let current = stores.current(&request.store_id, &kv.key).map(|value| value.version);
+		if kv.version != -1 && current != Some(kv.version) {
+			return Err(conflict_error(format!("Version mismatch for key {}", kv.key)));
+		}
+		if let Some(items) = stores.items.get_mut(&request.store_id) {
+			items.remove(&kv.key);
+		}
+		Ok(DeleteObjectResponse {})
+	}
+	async fn list_key_versions(&self, request: ListKeyVersionsRequest) -> std::io::Result<ListKeyVersionsResponse> {
+		let stores = self.stores.lock().unwrap();
+		let global_version = match request.page_token {
+			None => Some(stores.global_versions.get(&request.store_id).copied().unwrap_or(0)),
+			Some(_) => None,
+		};
+		let page_size = request.page_size.filter(|page_size| *page_size > 0).map(|page_size| page_size as usize)
+			.or(self.page_size).unwrap_or(usize::MAX);
+		let mut response = ListKeyVersionsResponse { global_version, ..Default::default() };
+		let Some(items) = stores.items.get(&request.store_id) else { return Ok(response) };
+		let matching = items.iter()
+			.filter(|(key, _)| request.page_token.as_ref().is_none_or(|start_key| *key > start_key))
+			.filter(|(key, _)| request.key_prefix.as_ref().is_none_or(|prefix| key.starts_with(prefix.as_str())));
+		for (count, (key, item)) in matching.enumerate() {
+			if count == page_size {
+				response.next_page_token = response.key_versions.last().map(|kv: &KeyValue| kv.key.clone());
+				break;
+			}
+			if item.is_expired() {
+				continue;
+			}
+			let value = if request.include_values { item.value.value.clone() } else { Vec::new() };
+			response.key_versions.push(KeyValue { key: key.clone(), version: item.value.version, value });
+			if let Some(expires_at) = item.expires_at {
+				response.item_expiries.push(ItemExpiry { key: key.clone(), expires_at });
+			}
+		}
+		Ok(response)
+	}
+	async fn list_object_versions(&self, _request: ListObjectVersionsRequest) -> std::io::Result<ListObjectVersionsResponse> {
+		Err(history_not_enabled())
+	}
+	async fn get_object_version(&self, _request: GetObjectVersionRequest) -> std::io::Result<GetObjectVersionResponse> {
+		Err(history_not_enabled())
+	}
+	async fn global_version(&self, store_id: &str) -> std::io::Result<i64> {
+		self.global_version_reads.fetch_add(1, Ordering::Relaxed);
+		Ok(self.stores.lock().unwrap().global_versions.get(store_id).copied().unwrap_or(0))
+	}
+	async fn backfill(&self, store_id: String, key_values: Vec<KeyValue>, item_expiries: Vec<ItemExpiry>) -> std::io::Result<()> {
+		let mut stores = self.stores.lock().unwrap();
+		let items = stores.items.entry(store_id).or_default();
+		for kv in key_values {
+			let expires_at = item_expiries.iter().find(|item_expiry| item_expiry.key == kv.key).map(|item_expiry| item_expiry.expires_at);
+			items.insert(kv.key.clone(), Item { value: kv, expires_at });
+		}
+		Ok(())
+	}
+	async fn backfill_global_version(&self, store_id: &str, global_version: i64) -> std::io::Result<()> {
+		let mut stores = self.stores.lock().unwrap();
+		let current = stores.global_versions.entry(store_id.to_string()).or_default();
+		*current = (*current).max(global_version);
+		Ok(())
+	}
+	async fn purge_expired(&self) -> std::io::Result<usize> {
+		let mut stores = self.stores.lock().unwrap();
+		let mut purged = 0;
+		for items in stores.items.values_mut() {
+			let before = items.len();
+			items.retain(|_, item| !item.is_expired());
+			purged += before - items.len();
+		}
+		Ok(purged)
+	}
+	async fn list_stores(&self, request: ListStoresRequest) -> std::io::Result<ListStoresResponse> {
+		let stores = self.stores.lock().unwrap();
+		let page_size = request.page_size.filter(|page_size| *page_size > 0).map(|page_size| page_size as usize)
+			.or(self.page_size).unwrap_or(usize::MAX);
+		let after = request.page_token.unwrap_or_default();
+		let mut stats: Vec<StoreStats> = stores.items.iter()
+			.filter(|(store_id, items)| !store_id.contains('\0') && !items.is_empty() && **store_id > after)
+			.map(|(store_id, items)| StoreStats {
+				store_id: store_id.clone(),
+				key_count: items.len() as i64,
+				total_size: items.values().map(|item| item.value.value.len() as i64).sum(),
+			})
+			.collect();
+		stats.sort_by(|a, b| a.store_id.cmp(&b.store_id));
+		let next_page_token = (stats.len() > page_size).then(|| stats[page_size - 1].store_id.clone());
+		stats.truncate(page_size);
+		Ok(ListStoresResponse { stores: stats, next_page_token })
+	}
+	async fn store_metadata(&self, store_id: &str) -> std::io::Result<StoreMetadata> {
+		let stores = self.stores.lock().unwrap();
+		let items = stores.items.get(store_id);
+		let global_version = stores.global_versions.get(store_id).copied();
+		if items.is_none_or(|items| items.is_empty()) && global_version.is_none() {
+			return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Store not found"));
+		}
+		Ok(StoreMetadata {
+			stats: Some(StoreStats {
+				store_id: store_id.to_string(),
+				key_count: items.map_or(0, |items| items.len() as i64),
+				total_size: items.map_or(0, |items| items.values().map(|item| item.value.value.len() as i64).sum()),
+			}),
+			global_version: global_version.unwrap_or(0),
+			..Default::default()
+		})
+	}
+	async fn delete_store(&self, store_id: &str) -> std::io::Result<u64> {
+		let mut stores = self.stores.lock().unwrap();
+		stores.global_versions.remove(store_id);
+		Ok(stores.items.remove(store_id).map_or(0, |items| items.len() as u64))
+	}
+	async fn check_health(&self) -> std::io::Result<HealthStatus> {
+		let Some((health, latency)) = &self.health else { return Ok(HealthStatus::Healthy) };
+		tokio::time::sleep(*latency).await;
+		health.clone().map_err(std::io::Error::other)
+	}
+}
@@ -0,0 +1,321 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use axum::{Extension, Router};
+use axum::middleware::AddExtension;
+use axum_server::accept::Accept;
+use axum_server::tls_rustls::{RustlsAcceptor, RustlsConfig};
+use hyper::server::conn::AddrStream;
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tower::Layer;
+
+use crate::config::TlsConfig;
+
+/// Identity of a client authenticated with a certificate, added to the extensions of its requests
+/// when mutual TLS is enabled.
+#[derive(Clone)]
+pub struct ClientIdentity {
+	/// Subject of the client certificate, e.g. `CN=wallet-1, O=Example`.
+	pub subject: String,
+	access: Arc<RwLock<StoreAccess>>,
+}
+
+impl ClientIdentity {
+	/// Whether this client may access `store_id`, as per the current client access file.
+	pub fn may_access(&self, store_id: &str) -> bool {
+		self.access.read().unwrap().may_access(&self.subject, store_id)
+	}
+}
+
+#[cfg(test)]
+impl ClientIdentity {
+	/// Returns the identity of a client with `subject` allowed to access `store_ids` only.
+	pub fn allowed(subject: &str, store_ids: &[&str]) -> Self {
+		let allowed_stores = AllowedStores::Only(store_ids.iter().map(|store_id| store_id.to_string()).collect());
+		let access = StoreAccess::BySubject(HashMap::from([(subject.to_string(), allowed_stores)]));
+		ClientIdentity { subject: subject.to_string(), access: Arc::new(RwLock::new(access)) }
+	}
+}
+
+/// `store_id`s clients may access, by certificate subject.
+///
+/// Read from a file with one `<subject> = <store_id>,<store_id>,...` entry per line, where `*`
+/// allows all stores. Empty lines and lines starting with `#` are ignored. Without a client access
+/// file, any authenticated client may access all stores.
+enum StoreAccess {
+	Unrestricted,
+	BySubject(HashMap<String, AllowedStores>),
+}
+
+enum AllowedStores {
+	All,
+	Only(HashSet<String>),
+}
+
+impl StoreAccess {
+	fn load(path: Option<&Path>) -> io::Result<Self> {
+		let path = match path {
+			Some(path) => path,
+			None => return Ok(StoreAccess::Unrestricted),
+		};
+		let mut by_subject = HashMap::new();
+		for (index, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			// Subjects contain `=` themselves, so entries are split on the last ` = `.
+			let (subject, store_ids) = line.rsplit_once(" = ").ok_or_else(|| io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("Invalid entry on line {} of {}", index + 1, path.display()),
+			))?;
+			let store_ids: HashSet<String> = store_ids.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect();
+			let allowed_stores = if store_ids.contains("*") { AllowedStores::All } else { AllowedStores::Only(store_ids) };
+			by_subject.insert(subject.trim().to_string(), allowed_stores);
+		}
+		Ok(StoreAccess::BySubject(by_subject))
+	}
+
+	fn may_access(&self, subject: &str, store_id: &str) -> bool {
+		match self {
+			StoreAccess::Unrestricted => true,
+			StoreAccess::BySubject(by_subject) => match by_subject.get(subject) {
+				Some(AllowedStores::All) => true,
+				Some(AllowedStores::Only(store_ids)) => store_ids.contains(store_id),
+				None => false,
+			},
+		}
+	}
+}
+
+/// Serves `app` over TLS on `bind_address` until `shutdown` completes, then stops accepting
+/// connections and waits for in-flight requests.
+///
+/// Certificates, keys and the client access file are checked for changes every
+/// `config.reload_interval` and reloaded without a restart. If reloading fails, the previous
+/// configuration stays in use.
+pub async fn serve(
+	app: Router, bind_address: SocketAddr, config: &TlsConfig, shutdown: impl Future<Output = ()> + Send + 'static,
+) -> io::Result<()> {
+	let rustls_config = RustlsConfig::from_config(Arc::new(load_server_config(config)?));
+	let access = Arc::new(RwLock::new(StoreAccess::load(config.client_access_file.as_deref())?));
+	tokio::spawn(reload_on_change(config.clone(), rustls_config.clone(), Arc::clone(&access)));
+
+	let handle = axum_server::Handle::new();
+	let shutdown_handle = handle.clone();
+	tokio::spawn(async move {
+		shutdown.await;
+		// The drain deadline is enforced by the caller.
+		shutdown_handle.graceful_shutdown(None);
+	});
+
+	let server = axum_server::bind(bind_address).handle(handle);
+	let acceptor = RustlsAcceptor::new(rustls_config);
+	if config.client_ca_file.is_some() {
+		server.acceptor(ClientIdentityAcceptor { inner: acceptor, access }).serve(app.into_make_service()).await
+	} else {
+		server.acceptor(acceptor).serve(app.into_make_service()).await
+	}
+}
+
+/// Completes TLS handshakes like `RustlsAcceptor`, and adds the `ClientIdentity` of the peer
+/// certificate to the extensions of requests made on the connection.
+#[derive(Clone)]
+struct ClientIdentityAcceptor {
+	inner: RustlsAcceptor,
+	access: Arc<RwLock<StoreAccess>>,
+}
+
+impl<S: Send + 'static> Accept<AddrStream, S> for ClientIdentityAcceptor {
+	type Stream = TlsStream<AddrStream>;
+	type Service = AddExtension<S, ClientIdentity>;
+	type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+	fn accept(&self, stream: AddrStream, service: S) -> Self::Future {
+		let handshake = self.inner.accept(stream, service);
+		let access = Arc::clone(&self.access);
+		Box::pin(async move {
+			let (stream, service) = handshake.await?;
+			let certificate = stream.get_ref().1.peer_certificates().and_then(|certificates| certificates.first())
+				// Not expected, client certificates are required once a client CA is configured.
+				.ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, "Client did not present a certificate"))?;
+			let identity = client_identity(certificate, access)?;
+			Ok((stream, Extension(identity).layer(service)))
+		})
+	}
+}
+
+/// Returns the identity of the client that presented `certificate`, named after its subject.
+fn client_identity(certificate: &Certificate, access: Arc<RwLock<StoreAccess>>) -> io::Result<ClientIdentity> {
+	let (_, parsed) = x509_parser::parse_x509_certificate(&certificate.0)
+		.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid client certificate: {}", err)))?;
+	Ok(ClientIdentity { subject: parsed.subject().to_string(), access })
+}
+
+fn load_server_config(config: &TlsConfig) -> io::Result<ServerConfig> {
+	let certificates = read_certificates(&config.cert_file)?;
+	let key = read_private_key(&config.key_file)?;
+
+	let builder = ServerConfig::builder().with_safe_defaults();
+	let builder = match &config.client_ca_file {
+		Some(client_ca_file) => {
+			let mut roots = RootCertStore::empty();
+			for certificate in read_certificates(client_ca_file)? {
+				roots.add(&certificate).map_err(|err| io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("Invalid client CA certificate in {}: {}", client_ca_file.display(), err),
+				))?;
+			}
+			builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+		}
+		None => builder.with_no_client_auth(),
+	};
+	let mut server_config = builder.with_single_cert(certificates, key)
+		.map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid TLS certificate or key: {}", err)))?;
+	server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+	Ok(server_config)
+}
+
+fn read_certificates(path: &Path) -> io::Result<Vec<Certificate>> {
+	let certificates = rustls_pemfile::certs(&mut io::BufReader::new(std::fs::File::open(path)?))?;
+	if certificates.is_empty() {
+		return Err(io::Error::new(io::ErrorKind::InvalidData, format!("No certificates found in {}", path.display())));
+	}
+	Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+fn read_private_key(path: &Path) -> io::Result<PrivateKey> {
+	for item in rustls_pemfile::read_all(&mut io::BufReader::new(std::fs::File::open(path)?))? {
+		match item {
+			rustls_pemfile::Item::PKCS8Key(key) | rustls_pemfile::Item::RSAKey(key) | rustls_pemfile::Item::ECKey(key) => {
+				return Ok(PrivateKey(key));
+			}
+			_ => {}
+		}
+	}
+	Err(io::Error::new(io::ErrorKind::InvalidData, format!("No private key found in {}", path.display())))
+}
+
+/// Reloads the server configuration and client access file whenever one of the files they are
+/// read from is modified.
+async fn reload_on_change(config: TlsConfig, rustls_config: RustlsConfig, access: Arc<RwLock<StoreAccess>>) {
+	let watched_files: Vec<PathBuf> = [Some(&config.cert_file), Some(&config.key_file), config.client_ca_file.as_ref(), config.client_access_file.as_ref()]
+		.into_iter()
+		.flatten()
+		.cloned()
+		.collect();
+	let modified_times = |files: &[PathBuf]| -> Vec<Option<SystemTime>> {
+		files.iter().map(|path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()).collect()
+	};
+
+	let mut last_modified = modified_times(&watched_files);
+	let mut interval = tokio::time::interval(config.reload_interval);
+	loop {
+		interval.tick().await;
+		let modified = modified_times(&watched_files);
+		if modified == last_modified {
+			continue;
+		}
+		last_modified = modified;
+
+		match load_server_config(&config) {
+			Ok(server_config) => {
+				rustls_config.reload_from_config(Arc::new(server_config));
+				tracing::info!("Reloaded TLS configuration");
+			}
+			Err(err) => tracing::error!(error = %err, "Failed to reload TLS certificates, keeping the previous ones"),
+		}
+		match StoreAccess::load(config.client_access_file.as_deref()) {
+			Ok(store_access) => *access.write().unwrap() = store_access,
+			Err(err) => tracing::error!(error = %err, "Failed to reload client access file, keeping the previous one"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Self-signed, with subject `O=Example, CN=wallet-1`.
+	const CLIENT_CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----
+MIIBoDCCAUegAwIBAgIUNScMlNbVSlSK0yi6dxUihMsf3KIwCgYIKoZIzj0EAwIw
+JTEQMA4GA1UECgwHRXhhbXBsZTERMA8GA1UEAwwId2FsbGV0LTEwIBcNMjYxMDE5
+MDQwNDMwWhgPMjEyNjA5MjUwNDA0MzBaMCUxEDAOBgNVBAoMB0V4YW1wbGUxETAP
+BgNVBAMMCHdhbGxldC0xMFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEG48dfUq5
+Q2r6Ts6mw6nXDmLs8LZkhocxBZgCFYqrws31q5r7u8ZF9jHRqwhZLEKuY1R7tBxx
+IdkT2GoX+E18GqNTMFEwHQYDVR0OBBYEFNN6Uq63ODHc9HOzqEEBi9paNRwVMB8G
+A1UdIwQYMBaAFNN6Uq63ODHc9HOzqEEBi9paNRwVMA8GA1UdEwEB/wQFMAMBAf8w
+CgYIKoZIzj0EAwIDRwAwRAIgb8gErrcvOb+uAfjE5hFE2sjhLorZe38larzzgQia
+jVYCIC3B+8TUovGqMbsocifrxSiv8Lt+urkAB5G8zXBkqbxv
+-----END CERTIFICATE-----
+";
+
+	fn access_file(contents: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("vss-client-access-{}", uuid::Uuid::new_v4()));
+		std::fs::write(&path, contents).unwrap();
+		path
+	}
+
+	fn client_certificate() -> Certificate {
+		let certificates = rustls_pemfile::certs(&mut CLIENT_CERTIFICATE.as_bytes()).unwrap();
+		Certificate(certificates.into_iter().next().unwrap())
+	}
+
+	#[test]
+	fn maps_certificate_subjects_to_allowed_stores() {
+		let path = access_file("\
+# Subjects contain `=` themselves.
+O=Example, CN=wallet-1 = store-a, store-b
+
+O=Example, CN=admin = *
+");
+		let access = Arc::new(RwLock::new(StoreAccess::load(Some(&path)).unwrap()));
+		let identity = client_identity(&client_certificate(), Arc::clone(&access)).unwrap();
+		assert_eq!(identity.subject, "O=Example, CN=wallet-1");
+		assert!(identity.may_access("store-a"));
+		assert!(identity.may_access("store-b"));
+		assert!(!identity.may_access("store-c"));
+
+		let admin = ClientIdentity { subject: "O=Example, CN=admin".to_string(), access: Arc::clone(&access) };
+		assert!(admin.may_access("store-c"));
+		let unknown = ClientIdentity { subject: "O=Example, CN=wallet-2".to_string(), access: Arc::clone(&access) };
+		assert!(!unknown.may_access("store-a"));
+
+		// Identities follow reloads of the client access file.
+		std::fs::write(&path, "O=Example, CN=wallet-1 = store-c\n").unwrap();
+		*access.write().unwrap() = StoreAccess::load(Some(&path)).unwrap();
+		assert!(!identity.may_access("store-a"));
+		assert!(identity.may_access("store-c"));
+		std::fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn restricts_identities_to_their_stores() {
+		let identity = ClientIdentity::allowed("O=Example, CN=wallet-1", &["store-a"]);
+		assert!(identity.may_access("store-a"));
+		assert!(!identity.may_access("store-b"));
+	}
+
+	#[test]
+	fn allows_all_stores_without_access_file() {
+		let access = Arc::new(RwLock::new(StoreAccess::load(None).unwrap()));
+		let identity = client_identity(&client_certificate(), access).unwrap();
+		assert!(identity.may_access("store-a"));
+	}
+
+	#[test]
+	fn rejects_invalid_access_files() {
+		let path = access_file("O=Example, CN=wallet-1 store-a\n");
+		let err = StoreAccess::load(Some(&path)).err().unwrap();
+		assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+		std::fs::remove_file(path).unwrap();
+	}
+}
@@ -1,4 +1,6 @@
 /// Request payload to be used for `GetObject` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct GetObjectRequest {
@@ -9,6 +11,7 @@ pub struct GetObjectRequest {
     /// This can be used for client-isolation/ rate-limiting / throttling on the server-side.
     /// Authorization and billing can also be performed at the `store_id` level.
     #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
     pub store_id: ::prost::alloc::string::String,
     /// The key of the value to be fetched.
     ///
@@ -24,16 +27,72 @@ pub struct GetObjectRequest {
     /// Ref: <https://en.wikipedia.org/wiki/Isolation_(database_systems>)#Read_committed
     #[prost(string, tag = "2")]
     pub key: ::prost::alloc::string::String,
+    /// Version of the value already held by the client, if any.
+    ///
+    /// If the stored version of `key` is `known_version`, the value is not returned and
+    /// `not_modified` is set in the response.
+    #[prost(int64, optional, tag = "3")]
+    #[serde(
+        with = "crate::json::optional_int64",
+        skip_serializing_if = "Option::is_none",
+        alias = "known_version"
+    )]
+    pub known_version: ::core::option::Option<i64>,
 }
 /// Server response for `GetObject` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct GetObjectResponse {
     /// Fetched `value` and `version` along with the corresponding `key` in the request.
+    ///
+    /// If `not_modified` is set, `value` is empty.
     #[prost(message, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub value: ::core::option::Option<KeyValue>,
+    /// Whether the stored version of `key` is the `known_version` given in the request.
+    #[prost(bool, tag = "3")]
+    #[serde(alias = "not_modified")]
+    pub not_modified: bool,
+}
+/// Request payload to be used for `GetObjects` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetObjectsRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// The keys of the values to be fetched, at most 100.
+    ///
+    /// Unlike `GetObject`, keys which do not exist are not an error, they are returned in
+    /// `missing_keys` instead. Each value is read with the consistency and isolation guarantees of
+    /// `GetObject`, but the values are not read as of a single point in time.
+    #[prost(string, repeated, tag = "2")]
+    pub keys: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// Server response for `GetObjects` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetObjectsResponse {
+    /// Fetched `value` and `version` of each requested key which exists, in the order of the request.
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<KeyValue>,
+    /// Requested keys which do not exist.
+    #[prost(string, repeated, tag = "2")]
+    #[serde(alias = "missing_keys")]
+    pub missing_keys: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 /// Request payload to be used for `PutObject` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct PutObjectRequest {
@@ -44,6 +103,7 @@ pub struct PutObjectRequest {
     /// This can be used for client-isolation/ rate-limiting / throttling on the server-side.
     /// Authorization and billing can also be performed at the `store_id` level.
     #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
     pub store_id: ::prost::alloc::string::String,
     /// `global_version` is a sequence-number/version of the whole store. This can be used for versioning
     /// and ensures that multiple updates in case of multiple devices can only be done linearly, even
@@ -63,6 +123,11 @@ pub struct PutObjectRequest {
     ///
     /// Requests with a conflicting version will fail with `CONFLICT_EXCEPTION` as ErrorCode.
     #[prost(int64, optional, tag = "2")]
+    #[serde(
+        with = "crate::json::optional_int64",
+        skip_serializing_if = "Option::is_none",
+        alias = "global_version"
+    )]
     pub global_version: ::core::option::Option<i64>,
     /// Items to be written as a result of this `PutObjectRequest`.
     ///
@@ -106,6 +171,7 @@ pub struct PutObjectRequest {
     /// All `PutObjectRequest`s are strongly consistent i.e. they provide read-after-write and
     /// read-after-update consistency guarantees.
     #[prost(message, repeated, tag = "3")]
+    #[serde(alias = "transaction_items")]
     pub transaction_items: ::prost::alloc::vec::Vec<KeyValue>,
     /// Items to be deleted as a result of this `PutObjectRequest`.
     ///
@@ -130,13 +196,51 @@ pub struct PutObjectRequest {
     ///
     /// All items within a single `PutObjectRequest` must have distinct keys.
     #[prost(message, repeated, tag = "4")]
+    #[serde(alias = "delete_items")]
     pub delete_items: ::prost::alloc::vec::Vec<KeyValue>,
+    /// Optional expiry of items written in `transaction_items`, after which they are treated as deleted.
+    ///
+    /// Expired items are no longer returned by `GetObject`, `GetObjects` or `ListKeyVersions`, and are
+    /// removed by the server some time later. Items written without an expiry never expire, including
+    /// those replacing an item which had one.
+    ///
+    /// Each key must be that of an item in `transaction_items`. Expiry has a precision of one second.
+    #[prost(message, repeated, tag = "5")]
+    #[serde(alias = "item_expiries")]
+    pub item_expiries: ::prost::alloc::vec::Vec<ItemExpiry>,
+}
+/// Time after which an item written by a `PutObjectRequest` expires.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ItemExpiry {
+    /// The key of the item in `transaction_items`.
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    /// When the item expires, in milliseconds since the Unix epoch.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64", alias = "expires_at")]
+    pub expires_at: i64,
 }
 /// Server response for `PutObject` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
-pub struct PutObjectResponse {}
+pub struct PutObjectResponse {
+    /// The `global_version` of the store once the write was applied, which the write incremented.
+    #[prost(int64, optional, tag = "1")]
+    #[serde(
+        with = "crate::json::optional_int64",
+        skip_serializing_if = "Option::is_none",
+        alias = "global_version"
+    )]
+    pub global_version: ::core::option::Option<i64>,
+}
 /// Request payload to be used for `DeleteObject` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DeleteObjectRequest {
@@ -147,6 +251,7 @@ pub struct DeleteObjectRequest {
     /// This can be used for client-isolation/ rate-limiting / throttling on the server-side.
     /// Authorization and billing can also be performed at the `store_id` level.
     #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
     pub store_id: ::prost::alloc::string::String,
     /// Item to be deleted as a result of this `DeleteObjectRequest`.
     ///
@@ -165,13 +270,18 @@ pub struct DeleteObjectRequest {
     /// If the requested item does not exist, this operation will not fail.
     /// If you wish to perform stricter checks while deleting an item, consider using `PutObject` API.
     #[prost(message, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "key_value")]
     pub key_value: ::core::option::Option<KeyValue>,
 }
 /// Server response for `DeleteObject` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DeleteObjectResponse {}
 /// Request payload to be used for `ListKeyVersions` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ListKeyVersionsRequest {
@@ -182,6 +292,7 @@ pub struct ListKeyVersionsRequest {
     /// This can be used for client-isolation/ rate-limiting / throttling on the server-side.
     /// Authorization and billing can also be performed at the `store_id` level.
     #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
     pub store_id: ::prost::alloc::string::String,
     /// A `key_prefix` is a string of characters at the beginning of the key. Prefixes can be used as
     /// a way to organize key-values in a similar way to directories.
@@ -192,12 +303,14 @@ pub struct ListKeyVersionsRequest {
     /// If no `key_prefix` is specified or it is empty (""), all the keys are eligible to be returned in
     /// the response.
     #[prost(string, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "key_prefix")]
     pub key_prefix: ::core::option::Option<::prost::alloc::string::String>,
     /// `page_size` is used by clients to specify the maximum number of results that can be returned by
     /// the server.
     /// The server may further constrain the maximum number of results returned in a single page.
     /// If the `page_size` is 0 or not set, the server will decide the number of results to be returned.
     #[prost(int32, optional, tag = "3")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "page_size")]
     pub page_size: ::core::option::Option<i32>,
     /// `page_token` is a pagination token.
     ///
@@ -206,15 +319,28 @@ pub struct ListKeyVersionsRequest {
     /// For subsequent pages, use the value that was returned as `next_page_token` in the previous
     /// page's `ListKeyVersionsResponse`.
     #[prost(string, optional, tag = "4")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "page_token")]
     pub page_token: ::core::option::Option<::prost::alloc::string::String>,
+    /// If set, the `value` of each key is returned along with its version, so that all values of a
+    /// store can be fetched without a `GetObject` call per key.
+    ///
+    /// Pages are then also limited by the total size of their values, and may hold fewer than
+    /// `page_size` results.
+    #[prost(bool, tag = "5")]
+    #[serde(alias = "include_values")]
+    pub include_values: bool,
 }
 /// Server response for `ListKeyVersions` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ListKeyVersionsResponse {
     /// Fetched keys and versions.
-    /// Even though this API reuses the `KeyValue` struct, the `value` sub-field will not be set by the server.
+    /// Even though this API reuses the `KeyValue` struct, the `value` sub-field will not be set by the
+    /// server, unless `include_values` is set in the request.
     #[prost(message, repeated, tag = "1")]
+    #[serde(alias = "key_versions")]
     pub key_versions: ::prost::alloc::vec::Vec<KeyValue>,
     /// `next_page_token` is a pagination token, used to retrieve the next page of results.
     /// Use this value to query for next-page of paginated `ListKeyVersions` operation, by specifying
@@ -230,6 +356,7 @@ pub struct ListKeyVersionsResponse {
     /// Caution: Clients must not assume a specific number of key_versions to be present in a page for
     /// paginated response.
     #[prost(string, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "next_page_token")]
     pub next_page_token: ::core::option::Option<::prost::alloc::string::String>,
     /// `global_version` is a sequence-number/version of the whole store.
     ///
@@ -245,10 +372,516 @@ pub struct ListKeyVersionsResponse {
     /// This guarantee is helpful for ensuring the versioning correctness if using the `global_version`
     /// in `PutObject` API and can help avoid the race conditions related to it.
     #[prost(int64, optional, tag = "3")]
+    #[serde(
+        with = "crate::json::optional_int64",
+        skip_serializing_if = "Option::is_none",
+        alias = "global_version"
+    )]
     pub global_version: ::core::option::Option<i64>,
+    /// Expiry of the items in `key_versions` written with one, in the order of `key_versions`.
+    ///
+    /// Expiries are kept with a precision of one second, so they may be up to a second later than
+    /// those given in `PutObjectRequest.item_expiries`.
+    #[prost(message, repeated, tag = "4")]
+    #[serde(alias = "item_expiries")]
+    pub item_expiries: ::prost::alloc::vec::Vec<ItemExpiry>,
+}
+/// Request payload to be used for `ListObjectVersions` API call to server.
+///
+/// Previous versions are only retained if the server has version history enabled, otherwise
+/// returns `ErrorCode.INVALID_REQUEST_EXCEPTION`.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListObjectVersionsRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// The key whose previous versions are listed.
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+}
+/// Server response for `ListObjectVersions` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListObjectVersionsResponse {
+    /// Previous versions of the key retained by the server, most recently superseded first.
+    /// The current version is not included, it is returned by `GetObject`.
+    #[prost(message, repeated, tag = "1")]
+    pub versions: ::prost::alloc::vec::Vec<ObjectVersion>,
+}
+/// A previous version of a key, replaced or deleted by a write.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ObjectVersion {
+    /// The version of the value.
+    #[prost(int64, tag = "1")]
+    #[serde(with = "crate::json::int64")]
+    pub version: i64,
+    /// When this version was replaced or deleted, in milliseconds since the Unix epoch.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64", alias = "superseded_at")]
+    pub superseded_at: i64,
+    /// Whether this version was deleted rather than replaced.
+    #[prost(bool, tag = "3")]
+    pub deleted: bool,
+}
+/// Request payload to be used for `GetObjectVersion` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetObjectVersionRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// The key of the value to be fetched.
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+    /// A previous version of `key`, as listed by `ListObjectVersions`.
+    ///
+    /// If this version is not retained, returns `ErrorCode.NO_SUCH_KEY_EXCEPTION` in the
+    /// `ErrorResponse`. If the same version was written more than once, the value most recently
+    /// superseded is returned.
+    #[prost(int64, tag = "3")]
+    #[serde(with = "crate::json::int64")]
+    pub version: i64,
+}
+/// Server response for `GetObjectVersion` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetObjectVersionResponse {
+    /// Fetched `value` of the requested `version` along with the corresponding `key` in the request.
+    #[prost(message, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: ::core::option::Option<KeyValue>,
+}
+/// Request payload to be used for `/admin/listDeletedObjects` API call to server.
+///
+/// Deleted items are only retained if the server has soft delete enabled, otherwise returns
+/// `ErrorCode.INVALID_REQUEST_EXCEPTION`.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListDeletedObjectsRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// If set, only deleted items whose key starts with `key_prefix` are listed.
+    #[prost(string, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "key_prefix")]
+    pub key_prefix: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// Server response for `/admin/listDeletedObjects` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListDeletedObjectsResponse {
+    /// Deleted items which can still be restored, most recently deleted first.
+    #[prost(message, repeated, tag = "1")]
+    pub objects: ::prost::alloc::vec::Vec<DeletedObject>,
+}
+/// An item deleted within the recovery window.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeletedObject {
+    /// The key of the deleted item.
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    /// The version of the deleted item.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64")]
+    pub version: i64,
+    /// When the item was deleted, in milliseconds since the Unix epoch.
+    #[prost(int64, tag = "3")]
+    #[serde(with = "crate::json::int64", alias = "deleted_at")]
+    pub deleted_at: i64,
+}
+/// Request payload to be used for `/admin/restoreObject` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RestoreObjectRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// The key of the deleted item to be restored, with the version and value it had when most
+    /// recently deleted.
+    ///
+    /// If no deleted item can be restored under this key, returns `ErrorCode.NO_SUCH_KEY_EXCEPTION`.
+    /// If the key was written to since, returns `ErrorCode.CONFLICT_EXCEPTION`.
+    #[prost(string, tag = "2")]
+    pub key: ::prost::alloc::string::String,
+}
+/// Server response for `/admin/restoreObject` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RestoreObjectResponse {
+    /// The restored item.
+    #[prost(message, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: ::core::option::Option<KeyValue>,
+}
+/// Request payload to be used for `/createSnapshot` API call to server.
+///
+/// Captures the items of a store as of its current `global_version`. The snapshot is consistent: it
+/// holds the items of the store at a single point in time, even if the store is written to while it
+/// is taken. Returns `ErrorCode.CONFLICT_EXCEPTION` if the store kept changing until then.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateSnapshotRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+}
+/// Server response for `/createSnapshot` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateSnapshotResponse {
+    /// The snapshot taken.
+    #[prost(message, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: ::core::option::Option<Snapshot>,
+}
+/// A consistent copy of the items of a store.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Snapshot {
+    /// The `global_version` of the store when the snapshot was taken, identifying the snapshot.
+    #[prost(int64, tag = "1")]
+    #[serde(with = "crate::json::int64", alias = "global_version")]
+    pub global_version: i64,
+    /// When the snapshot was taken, in milliseconds since the Unix epoch.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64", alias = "created_at")]
+    pub created_at: i64,
+    /// Number of items in the snapshot.
+    #[prost(int64, tag = "3")]
+    #[serde(with = "crate::json::int64", alias = "item_count")]
+    pub item_count: i64,
+}
+/// Request payload to be used for `/listSnapshots` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListSnapshotsRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+}
+/// Server response for `/listSnapshots` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListSnapshotsResponse {
+    /// Snapshots of the store, most recent first.
+    #[prost(message, repeated, tag = "1")]
+    pub snapshots: ::prost::alloc::vec::Vec<Snapshot>,
+}
+/// Request payload to be used for `/getSnapshot` API call to server, downloading the items of a
+/// snapshot a page at a time.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetSnapshotRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// The `global_version` of the snapshot, as listed by `/listSnapshots`.
+    ///
+    /// If there is no such snapshot, returns `ErrorCode.NO_SUCH_KEY_EXCEPTION`.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64", alias = "global_version")]
+    pub global_version: i64,
+    /// Optional field that specifies the maximum number of items to return in the page.
+    #[prost(int32, optional, tag = "3")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "page_size")]
+    pub page_size: ::core::option::Option<i32>,
+    /// `next_page_token` of the previous page, unset for the first page.
+    #[prost(string, optional, tag = "4")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "page_token")]
+    pub page_token: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// Server response for `/getSnapshot` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetSnapshotResponse {
+    /// Items of the snapshot in this page, with their values.
+    #[prost(message, repeated, tag = "1")]
+    #[serde(alias = "key_values")]
+    pub key_values: ::prost::alloc::vec::Vec<KeyValue>,
+    /// Token of the next page, unset or empty on the last page.
+    #[prost(string, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "next_page_token")]
+    pub next_page_token: ::core::option::Option<::prost::alloc::string::String>,
+    /// Expiry of the items in `key_values` written with one. Items expired since the snapshot was
+    /// taken are no longer part of it.
+    #[prost(message, repeated, tag = "3")]
+    #[serde(alias = "item_expiries")]
+    pub item_expiries: ::prost::alloc::vec::Vec<ItemExpiry>,
+}
+/// Request payload to be used for `/restoreSnapshot` API call to server.
+///
+/// Writes the items of the snapshot to the store with the versions they had, and deletes those not in
+/// the snapshot. The restore is not atomic: writes made to the store while it runs may be
+/// overwritten, and are reported to change subscribers like any other write.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RestoreSnapshotRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// The `global_version` of the snapshot to restore.
+    ///
+    /// If there is no such snapshot, returns `ErrorCode.NO_SUCH_KEY_EXCEPTION`.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64", alias = "global_version")]
+    pub global_version: i64,
+}
+/// Server response for `/restoreSnapshot` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RestoreSnapshotResponse {}
+/// Request payload to be used for `/deleteSnapshot` API call to server.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteSnapshotRequest {
+    /// `store_id` is a keyspace identifier.
+    /// Ref: <https://en.wikipedia.org/wiki/Keyspace_(distributed_data_store>)
+    /// All APIs operate within a single `store_id`.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// The `global_version` of the snapshot to delete.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64", alias = "global_version")]
+    pub global_version: i64,
+}
+/// Server response for `/deleteSnapshot` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteSnapshotResponse {}
+/// Request payload to be used for `/admin/listStores` API call to server, listing the stores holding
+/// items or written to before, a page at a time.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListStoresRequest {
+    /// Optional field that specifies the maximum number of stores to return in the page. Pages may
+    /// hold fewer stores even if there are more.
+    #[prost(int32, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "page_size")]
+    pub page_size: ::core::option::Option<i32>,
+    /// `next_page_token` of the previous page, unset for the first page.
+    #[prost(string, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "page_token")]
+    pub page_token: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// Server response for `/admin/listStores` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListStoresResponse {
+    /// Stores in this page.
+    #[prost(message, repeated, tag = "1")]
+    pub stores: ::prost::alloc::vec::Vec<StoreStats>,
+    /// Token of the next page, unset or empty on the last page.
+    #[prost(string, optional, tag = "2")]
+    #[serde(skip_serializing_if = "Option::is_none", alias = "next_page_token")]
+    pub next_page_token: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// Number of keys and total size of a store.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StoreStats {
+    /// `store_id` is a keyspace identifier.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+    /// Number of unexpired keys of the store.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64", alias = "key_count")]
+    pub key_count: i64,
+    /// Total size of the values of the keys, in bytes. Items written before sizes were recorded count
+    /// as empty until they are next written to.
+    #[prost(int64, tag = "3")]
+    #[serde(with = "crate::json::int64", alias = "total_size")]
+    pub total_size: i64,
+}
+/// Request payload to be used for `/admin/getStore` API call to server.
+///
+/// If the store was never written to, returns `ErrorCode.NO_SUCH_KEY_EXCEPTION`.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetStoreRequest {
+    /// `store_id` is a keyspace identifier.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+}
+/// Server response for `/admin/getStore` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetStoreResponse {
+    /// Metadata of the store.
+    #[prost(message, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: ::core::option::Option<StoreMetadata>,
+}
+/// Metadata of a store, along with its `StoreStats`.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StoreMetadata {
+    /// Number of keys and total size of the store.
+    #[prost(message, optional, tag = "1")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: ::core::option::Option<StoreStats>,
+    /// `global_version` of the store, 0 if it was never set.
+    #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64", alias = "global_version")]
+    pub global_version: i64,
+    /// When the store was first written to, in milliseconds since the Unix epoch, if known.
+    #[prost(int64, optional, tag = "3")]
+    #[serde(
+        with = "crate::json::optional_int64",
+        skip_serializing_if = "Option::is_none",
+        alias = "created_at"
+    )]
+    pub created_at: ::core::option::Option<i64>,
+    /// When the store was last written to, in milliseconds since the Unix epoch, if known.
+    #[prost(int64, optional, tag = "4")]
+    #[serde(
+        with = "crate::json::optional_int64",
+        skip_serializing_if = "Option::is_none",
+        alias = "last_modified_at"
+    )]
+    pub last_modified_at: ::core::option::Option<i64>,
+}
+/// Request payload to be used for `/admin/deleteStore` API call to server.
+///
+/// Permanently deletes a store, along with its previous versions, deleted items and snapshots, e.g. to
+/// honour erasure requests. Writes made to the store while it is being deleted may survive it.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteStoreRequest {
+    /// `store_id` is a keyspace identifier.
+    #[prost(string, tag = "1")]
+    #[serde(alias = "store_id")]
+    pub store_id: ::prost::alloc::string::String,
+}
+/// Server response for `/admin/deleteStore` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteStoreResponse {
+    /// Number of keys deleted.
+    #[prost(int64, tag = "1")]
+    #[serde(with = "crate::json::int64", alias = "deleted_keys")]
+    pub deleted_keys: i64,
+}
+/// Request payload to be used for `/admin/verifyAuditLog` API call to server.
+///
+/// The audit log is only verified if the server records one, otherwise returns
+/// `ErrorCode.INVALID_REQUEST_EXCEPTION`.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VerifyAuditLogRequest {}
+/// Server response for `/admin/verifyAuditLog` API.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct VerifyAuditLogResponse {
+    /// Number of entries of the chain of the server found intact, starting from the first one.
+    #[prost(int64, tag = "1")]
+    #[serde(with = "crate::json::int64", alias = "entry_count")]
+    pub entry_count: i64,
+    /// `seq` of the first entry found altered, removed, reordered or unreadable. Unset if the whole
+    /// chain is intact.
+    #[prost(int64, optional, tag = "2")]
+    #[serde(
+        with = "crate::json::optional_int64",
+        skip_serializing_if = "Option::is_none",
+        alias = "broken_at"
+    )]
+    pub broken_at: ::core::option::Option<i64>,
 }
 /// When HttpStatusCode is not ok (200), the response `content` contains a serialized `ErrorResponse`
 /// with the relevant `ErrorCode` and `message`
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ErrorResponse {
@@ -256,6 +889,7 @@ pub struct ErrorResponse {
     /// It is meant to be read and understood programmatically by code that detects/handles errors by
     /// type.
     #[prost(enumeration = "ErrorCode", tag = "1")]
+    #[serde(with = "crate::json::error_code", alias = "error_code")]
     pub error_code: i32,
     /// The error message containing a generic description of the error condition in English.
     /// It is intended for a human audience only and should not be parsed to extract any information
@@ -264,6 +898,8 @@ pub struct ErrorResponse {
     pub message: ::prost::alloc::string::String,
 }
 /// Represents a key-value pair to be stored or retrieved.
+#[derive(::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct KeyValue {
@@ -277,12 +913,14 @@ pub struct KeyValue {
     /// client-side increment is required to ensure matching versions. These updated key versions should
     /// be used in subsequent `PutObjectRequest`s for the keys.
     #[prost(int64, tag = "2")]
+    #[serde(with = "crate::json::int64")]
     pub version: i64,
     /// Object value in bytes which is stored (in put) and fetched (in get).
     /// Clients must encrypt the secret contents of this blob client-side before sending it over the
     /// wire to the server in order to preserve privacy and security.
     /// Clients may use a `Storable` object, serialize it and set it here.
     #[prost(bytes = "vec", tag = "3")]
+    #[serde(with = "crate::json::bytes")]
     pub value: ::prost::alloc::vec::Vec<u8>,
 }
 /// Represents a storable object that can be serialized and stored as `value` in `PutObjectRequest`.
@@ -348,6 +986,14 @@ pub enum ErrorCode {
     InternalServerException = 3,
     /// Used when the specified `key` in a `GetObjectRequest` does not exist.
     NoSuchKeyException = 4,
+    /// Used when authentication fails or in case of an unauthenticated request.
+    AuthException = 5,
+    /// Used when the request body is larger than the server allows for the API.
+    PayloadTooLargeException = 6,
+    /// Used when the request could not be completed within the time the server allows for it.
+    RequestTimeoutException = 7,
+    /// Used when the client is authenticated but not allowed to access the requested `store_id`.
+    PermissionDeniedException = 8,
 }
 impl ErrorCode {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -361,6 +1007,10 @@ impl ErrorCode {
             ErrorCode::InvalidRequestException => "INVALID_REQUEST_EXCEPTION",
             ErrorCode::InternalServerException => "INTERNAL_SERVER_EXCEPTION",
             ErrorCode::NoSuchKeyException => "NO_SUCH_KEY_EXCEPTION",
+            ErrorCode::AuthException => "AUTH_EXCEPTION",
+            ErrorCode::PayloadTooLargeException => "PAYLOAD_TOO_LARGE_EXCEPTION",
+            ErrorCode::RequestTimeoutException => "REQUEST_TIMEOUT_EXCEPTION",
+            ErrorCode::PermissionDeniedException => "PERMISSION_DENIED_EXCEPTION",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -371,7 +1021,512 @@ impl ErrorCode {
             "INVALID_REQUEST_EXCEPTION" => Some(Self::InvalidRequestException),
             "INTERNAL_SERVER_EXCEPTION" => Some(Self::InternalServerException),
             "NO_SUCH_KEY_EXCEPTION" => Some(Self::NoSuchKeyException),
+            "AUTH_EXCEPTION" => Some(Self::AuthException),
+            "PAYLOAD_TOO_LARGE_EXCEPTION" => Some(Self::PayloadTooLargeException),
+            "REQUEST_TIMEOUT_EXCEPTION" => Some(Self::RequestTimeoutException),
+            "PERMISSION_DENIED_EXCEPTION" => Some(Self::PermissionDeniedException),
             _ => None,
         }
     }
 }
+/// Generated server implementations.
+pub mod vss_service_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with VssServiceServer.
+    #[async_trait]
+    pub trait VssService: Send + Sync + 'static {
+        async fn get_object(
+            &self,
+            request: tonic::Request<super::GetObjectRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetObjectResponse>,
+            tonic::Status,
+        >;
+        async fn get_objects(
+            &self,
+            request: tonic::Request<super::GetObjectsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetObjectsResponse>,
+            tonic::Status,
+        >;
+        async fn put_object(
+            &self,
+            request: tonic::Request<super::PutObjectRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PutObjectResponse>,
+            tonic::Status,
+        >;
+        async fn delete_object(
+            &self,
+            request: tonic::Request<super::DeleteObjectRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::DeleteObjectResponse>,
+            tonic::Status,
+        >;
+        async fn list_key_versions(
+            &self,
+            request: tonic::Request<super::ListKeyVersionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ListKeyVersionsResponse>,
+            tonic::Status,
+        >;
+        async fn list_object_versions(
+            &self,
+            request: tonic::Request<super::ListObjectVersionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ListObjectVersionsResponse>,
+            tonic::Status,
+        >;
+        async fn get_object_version(
+            &self,
+            request: tonic::Request<super::GetObjectVersionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetObjectVersionResponse>,
+            tonic::Status,
+        >;
+    }
+    /// gRPC service exposing the same operations as the HTTP endpoints, with the same messages.
+    ///
+    /// Failures are reported with a gRPC status instead of an `ErrorResponse`, with codes mapped from
+    /// `ErrorCode`: `CONFLICT_EXCEPTION` as `ABORTED`, `INVALID_REQUEST_EXCEPTION` as `INVALID_ARGUMENT`,
+    /// `NO_SUCH_KEY_EXCEPTION` as `NOT_FOUND`, `AUTH_EXCEPTION` as `UNAUTHENTICATED`,
+    /// `PERMISSION_DENIED_EXCEPTION` as `PERMISSION_DENIED`, `PAYLOAD_TOO_LARGE_EXCEPTION` as
+    /// `RESOURCE_EXHAUSTED`, `REQUEST_TIMEOUT_EXCEPTION` as `DEADLINE_EXCEEDED` and
+    /// `INTERNAL_SERVER_EXCEPTION` as `INTERNAL`.
+    #[derive(Debug)]
+    pub struct VssServiceServer<T: VssService> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: VssService> VssServiceServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for VssServiceServer<T>
+    where
+        T: VssService,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/vss.VssService/GetObject" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetObjectSvc<T: VssService>(pub Arc<T>);
+                    impl<
+                        T: VssService,
+                    > tonic::server::UnaryService<super::GetObjectRequest>
+                    for GetObjectSvc<T> {
+                        type Response = super::GetObjectResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetObjectRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { (*inner).get_object(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetObjectSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/vss.VssService/GetObjects" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetObjectsSvc<T: VssService>(pub Arc<T>);
+                    impl<
+                        T: VssService,
+                    > tonic::server::UnaryService<super::GetObjectsRequest>
+                    for GetObjectsSvc<T> {
+                        type Response = super::GetObjectsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetObjectsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { (*inner).get_objects(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetObjectsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/vss.VssService/PutObject" => {
+                    #[allow(non_camel_case_types)]
+                    struct PutObjectSvc<T: VssService>(pub Arc<T>);
+                    impl<
+                        T: VssService,
+                    > tonic::server::UnaryService<super::PutObjectRequest>
+                    for PutObjectSvc<T> {
+                        type Response = super::PutObjectResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PutObjectRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move { (*inner).put_object(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PutObjectSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/vss.VssService/DeleteObject" => {
+                    #[allow(non_camel_case_types)]
+                    struct DeleteObjectSvc<T: VssService>(pub Arc<T>);
+                    impl<
+                        T: VssService,
+                    > tonic::server::UnaryService<super::DeleteObjectRequest>
+                    for DeleteObjectSvc<T> {
+                        type Response = super::DeleteObjectResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DeleteObjectRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                (*inner).delete_object(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DeleteObjectSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/vss.VssService/ListKeyVersions" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListKeyVersionsSvc<T: VssService>(pub Arc<T>);
+                    impl<
+                        T: VssService,
+                    > tonic::server::UnaryService<super::ListKeyVersionsRequest>
+                    for ListKeyVersionsSvc<T> {
+                        type Response = super::ListKeyVersionsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListKeyVersionsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                (*inner).list_key_versions(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ListKeyVersionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/vss.VssService/ListObjectVersions" => {
+                    #[allow(non_camel_case_types)]
+                    struct ListObjectVersionsSvc<T: VssService>(pub Arc<T>);
+                    impl<
+                        T: VssService,
+                    > tonic::server::UnaryService<super::ListObjectVersionsRequest>
+                    for ListObjectVersionsSvc<T> {
+                        type Response = super::ListObjectVersionsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ListObjectVersionsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                (*inner).list_object_versions(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ListObjectVersionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/vss.VssService/GetObjectVersion" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetObjectVersionSvc<T: VssService>(pub Arc<T>);
+                    impl<
+                        T: VssService,
+                    > tonic::server::UnaryService<super::GetObjectVersionRequest>
+                    for GetObjectVersionSvc<T> {
+                        type Response = super::GetObjectVersionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetObjectVersionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                (*inner).get_object_version(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetObjectVersionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        Ok(
+                            http::Response::builder()
+                                .status(200)
+                                .header("grpc-status", "12")
+                                .header("content-type", "application/grpc")
+                                .body(empty_body())
+                                .unwrap(),
+                        )
+                    })
+                }
+            }
+        }
+    }
+    impl<T: VssService> Clone for VssServiceServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    impl<T: VssService> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(Arc::clone(&self.0))
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: VssService> tonic::server::NamedService for VssServiceServer<T> {
+        const NAME: &'static str = "vss.VssService";
+    }
+}